@@ -133,6 +133,12 @@ pub trait Config: Default + Clone + PartialEq + Eq + core::fmt::Debug + Send + S
 	fn max_seed_lookahead() -> Uint;
 	/// Slots per eth1 voting period.
 	fn slots_per_eth1_voting_period() -> Uint { Self::SlotsPerEth1VotingPeriod::to_u64() }
+	/// Seconds per slot.
+	fn seconds_per_slot() -> Uint;
+	/// Expected seconds between eth1 blocks.
+	fn seconds_per_eth1_block() -> Uint;
+	/// Number of eth1 blocks to wait before considering a block final.
+	fn eth1_follow_distance() -> Uint;
 	/// Slots per historical root.
 	fn slots_per_historical_root() -> Uint { Self::SlotsPerHistoricalRoot::to_u64() }
 	/// Minimal validator withdrawability delay.
@@ -189,6 +195,14 @@ pub trait Config: Default + Clone + PartialEq + Eq + core::fmt::Debug + Send + S
 	fn domain_deposit() -> u32 { 3 }
 	/// Voluntary exit domain.
 	fn domain_voluntary_exit() -> u32 { 4 }
+	/// Aggregate and proof domain.
+	fn domain_aggregate_and_proof() -> u32 { 6 }
+
+	// == Validator ==
+	/// Target number of aggregators per committee.
+	fn target_aggregators_per_committee() -> Uint { 16 }
+	/// Number of slots during which an attestation is propagated on gossip.
+	fn attestation_propagation_slot_range() -> Uint { 32 }
 
 	// == Helpers ==
 	/// Hash function.
@@ -250,6 +264,9 @@ impl Config for MinimalConfig {
 
 	// == Time parameters ==
 	fn min_attestation_inclusion_delay() -> Uint { 1 }
+	fn seconds_per_slot() -> Uint { 6 }
+	fn seconds_per_eth1_block() -> Uint { 14 }
+	fn eth1_follow_distance() -> Uint { 16 }
 	fn min_seed_lookahead() -> Uint { 1 }
 	fn max_seed_lookahead() -> Uint { 4 }
 	fn min_validator_withdrawability_delay() -> Uint { 256 }
@@ -312,6 +329,9 @@ impl Config for MainnetConfig {
 
 	// == Time parameters ==
 	fn min_attestation_inclusion_delay() -> Uint { 1 }
+	fn seconds_per_slot() -> Uint { 12 }
+	fn seconds_per_eth1_block() -> Uint { 14 }
+	fn eth1_follow_distance() -> Uint { 1024 }
 	fn min_seed_lookahead() -> Uint { 1 }
 	fn max_seed_lookahead() -> Uint { 4 }
 	fn min_validator_withdrawability_delay() -> Uint { 256 }
@@ -374,6 +394,9 @@ impl Config for SapphireConfig {
 
 	// == Time parameters ==
 	fn min_attestation_inclusion_delay() -> Uint { 1 }
+	fn seconds_per_slot() -> Uint { 6 }
+	fn seconds_per_eth1_block() -> Uint { 14 }
+	fn eth1_follow_distance() -> Uint { 16 }
 	fn min_seed_lookahead() -> Uint { 1 }
 	fn max_seed_lookahead() -> Uint { 4 }
 	fn min_validator_withdrawability_delay() -> Uint { 256 }
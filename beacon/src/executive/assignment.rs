@@ -14,7 +14,7 @@
 // You should have received a copy of the GNU General Public License along with
 // Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
 
-use crate::primitives::ValidatorId;
+use crate::primitives::{ValidatorId, Signature};
 use crate::{Config, BeaconExecutive, Error, utils};
 
 /// Committee assignment.
@@ -56,6 +56,26 @@ impl<'a, C: Config> BeaconExecutive<'a, C> {
 		Ok(None)
 	}
 
+	/// Find proposer indices of all slots in the current epoch.
+	pub fn proposer_assignments(&self) -> Result<Vec<(u64, u64)>, Error> {
+		let epoch_start_slot = utils::start_slot_of_epoch::<C>(self.current_epoch());
+		(epoch_start_slot..(epoch_start_slot + C::slots_per_epoch()))
+			.map(|slot| Ok((slot, self.beacon_proposer_index_at_slot(slot)?)))
+			.collect()
+	}
+
+	/// Whether the validator that produced `slot_signature` is an aggregator
+	/// of the committee at `index` in `slot`.
+	pub fn is_aggregator(
+		&self,
+		slot: u64,
+		index: u64,
+		slot_signature: &Signature,
+	) -> Result<bool, Error> {
+		let committee = self.beacon_committee(slot, index)?;
+		Ok(utils::is_aggregator::<C>(committee.len() as u64, slot_signature))
+	}
+
 	/// Get validator public key.
 	pub fn validator_pubkey(&self, index: u64) -> Option<ValidatorId> {
 		if index as usize >= self.validators.len() {
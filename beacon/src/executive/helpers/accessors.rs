@@ -126,10 +126,19 @@ impl<'a, C: Config> BeaconExecutive<'a, C> {
 
 	/// Get the current beacon proposer index.
 	pub fn beacon_proposer_index(&self) -> Result<ValidatorIndex, Error> {
+		self.beacon_proposer_index_at_slot(self.slot)
+	}
+
+	/// Get the beacon proposer index at a slot of the current epoch.
+	pub fn beacon_proposer_index_at_slot(&self, slot: Slot) -> Result<ValidatorIndex, Error> {
 		let epoch = self.current_epoch();
+		if utils::epoch_of_slot::<C>(slot) != epoch {
+			return Err(Error::SlotOutOfRange)
+		}
+
 		let seed = C::hash(&[
 			&self.seed(epoch, C::domain_beacon_proposer())[..],
-			&self.slot.to_le_bytes()[..8]
+			&slot.to_le_bytes()[..8]
 		]);
 		let indices = self.active_validator_indices(epoch);
 
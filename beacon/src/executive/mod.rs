@@ -215,9 +215,11 @@ impl<'a, C: Config> Registry for BeaconExecutive<'a, C> {
 pub struct BeaconState<C: Config> {
 	// == Versioning ==
 	/// Genesis time as Unix timestamp.
+	#[cfg_attr(feature = "serde", serde(serialize_with = "crate::utils::serialize_uint"))]
 	#[cfg_attr(feature = "serde", serde(deserialize_with = "crate::utils::deserialize_uint"))]
 	pub genesis_time: Uint,
 	/// Current slot.
+	#[cfg_attr(feature = "serde", serde(serialize_with = "crate::utils::serialize_uint"))]
 	#[cfg_attr(feature = "serde", serde(deserialize_with = "crate::utils::deserialize_uint"))]
 	pub slot: Uint,
 	/// Fork version.
@@ -239,6 +241,7 @@ pub struct BeaconState<C: Config> {
 	/// Votes on eth1 data.
 	pub eth1_data_votes: MaxVec<Eth1Data, C::SlotsPerEth1VotingPeriod>,
 	/// Eth1 data deposit index.
+	#[cfg_attr(feature = "serde", serde(serialize_with = "crate::utils::serialize_uint"))]
 	#[cfg_attr(feature = "serde", serde(deserialize_with = "crate::utils::deserialize_uint"))]
 	pub eth1_deposit_index: Uint,
 
@@ -246,6 +249,8 @@ pub struct BeaconState<C: Config> {
 	/// Validator registry.
 	pub validators: MaxVec<Validator, C::ValidatorRegistryLimit>,
 	#[bm(compact)]
+	#[cfg_attr(feature = "serde", serde(serialize_with = "crate::utils::serialize_uint_list"))]
+	#[cfg_attr(feature = "serde", serde(deserialize_with = "crate::utils::deserialize_uint_list"))]
 	/// Balance of the validators.
 	pub balances: MaxVec<Uint, C::ValidatorRegistryLimit>,
 
@@ -255,6 +260,8 @@ pub struct BeaconState<C: Config> {
 
 	// == Slashings ==
 	#[bm(compact)]
+	#[cfg_attr(feature = "serde", serde(serialize_with = "crate::utils::serialize_uint_vector"))]
+	#[cfg_attr(feature = "serde", serde(deserialize_with = "crate::utils::deserialize_uint_vector"))]
 	/// Past slashings.
 	pub slashings: VecArray<Uint, C::EpochsPerSlashingsVector>,
 
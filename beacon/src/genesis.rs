@@ -14,9 +14,11 @@
 // You should have received a copy of the GNU General Public License along with
 // Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
 
-use crate::primitives::Uint;
+use crate::primitives::{Uint, H256};
 use crate::types::{Deposit, BeaconBlockHeader, BeaconBlock, Eth1Data, BeaconBlockBody};
-use crate::{Config, BeaconState, BeaconExecutive, BLSConfig, Error};
+use crate::utils::DepositTree;
+use crate::{Config, BeaconState, BeaconExecutive, BLSConfig, Error, consts};
+use core::cmp::min;
 use bm_le::tree_root;
 
 /// Generate genesis state and genesis block from given deposits, timestamp and eth1 data.
@@ -36,6 +38,9 @@ pub fn genesis<C: Config, BLS: BLSConfig>(
 }
 
 /// Generate genesis state from given deposits, timestamp, and eth1 data.
+///
+/// All deposits are checked against the deposit root of `genesis_eth1_data`.
+/// Use `initialize_beacon_state_from_eth1` for spec-compliant genesis.
 pub fn genesis_beacon_state<C: Config, BLS: BLSConfig>(
 	deposits: &[Deposit],
 	genesis_time: Uint,
@@ -73,3 +78,75 @@ pub fn genesis_beacon_state<C: Config, BLS: BLSConfig>(
 
 	Ok(state)
 }
+
+/// Initialize a candidate genesis state from an Eth1 block and all deposits
+/// made up to it, as in the spec's `initialize_beacon_state_from_eth1`.
+pub fn initialize_beacon_state_from_eth1<C: Config, BLS: BLSConfig>(
+	eth1_block_hash: H256,
+	eth1_timestamp: Uint,
+	deposits: &[Deposit],
+) -> Result<BeaconState<C>, Error> {
+	let genesis_time = eth1_timestamp - eth1_timestamp % consts::SECONDS_PER_DAY +
+		2 * consts::SECONDS_PER_DAY;
+
+	let mut state = BeaconState {
+		genesis_time,
+		eth1_data: Eth1Data {
+			block_hash: eth1_block_hash,
+			deposit_count: deposits.len() as Uint,
+			..Default::default()
+		},
+		latest_block_header: BeaconBlockHeader {
+			body_root: tree_root::<C::Digest, _>(
+				&BeaconBlockBody::<C>::default()
+			),
+			..Default::default()
+		},
+		..BeaconState::<C>::default()
+	};
+
+	for i in 0..state.randao_mixes.len() {
+		state.randao_mixes[i] = eth1_block_hash;
+	}
+
+	let mut deposit_tree = DepositTree::<C>::new();
+	for deposit in deposits {
+		deposit_tree.push(deposit.data.clone());
+		state.eth1_data.deposit_root = deposit_tree.root();
+
+		let mut executive = BeaconExecutive::new(&mut state);
+		executive.process_deposit::<BLS>(deposit.clone())?;
+	}
+
+	for (index, validator) in state.validators.iter_mut().enumerate() {
+		let balance = state.balances[index];
+		validator.effective_balance = min(
+			balance - balance % C::effective_balance_increment(),
+			C::max_effective_balance(),
+		);
+
+		if validator.effective_balance == C::max_effective_balance() {
+			validator.activation_eligibility_epoch = C::genesis_epoch();
+			validator.activation_epoch = C::genesis_epoch();
+		}
+	}
+
+	Ok(state)
+}
+
+/// Whether the given candidate genesis state can be used to start the beacon
+/// chain, as in the spec's `is_valid_genesis_state`.
+pub fn is_valid_genesis_state<C: Config>(state: &BeaconState<C>) -> bool {
+	if state.genesis_time < C::min_genesis_time() {
+		return false
+	}
+
+	let active_validator_count = state.validators.iter()
+		.filter(|validator| validator.is_active(C::genesis_epoch()))
+		.count() as Uint;
+	if active_validator_count < C::min_genesis_active_validator_count() {
+		return false
+	}
+
+	true
+}
@@ -42,7 +42,9 @@ pub use self::config::{
 	Config, MinimalConfig, MainnetConfig, SapphireConfig,
 };
 pub use self::executive::{BeaconState, BeaconExecutive};
-pub use self::genesis::{genesis, genesis_beacon_state};
+pub use self::genesis::{
+	genesis, genesis_beacon_state, initialize_beacon_state_from_eth1, is_valid_genesis_state,
+};
 
 use self::primitives::{H256, H768};
 use self::types::{
@@ -72,6 +74,8 @@ pub struct Inherent {
 	pub randao_reveal: H768,
 	/// New eth1 data.
 	pub eth1_data: Eth1Data,
+	/// Graffiti of the proposer.
+	pub graffiti: H256,
 }
 
 /// Beacon block transaction.
@@ -106,6 +110,7 @@ pub fn apply_inherent<C: Config, BLS: BLSConfig>(
 	let body = BeaconBlockBody {
 		randao_reveal: inherent.randao_reveal,
 		eth1_data: inherent.eth1_data,
+		graffiti: inherent.graffiti,
 		..Default::default()
 	};
 
@@ -35,6 +35,7 @@ pub struct Fork {
 	/// Current fork version
 	pub current_version: Version,
 	/// Fork epoch number
+	#[cfg_attr(feature = "serde", serde(serialize_with = "crate::utils::serialize_uint"))]
 	#[cfg_attr(feature = "serde", serde(deserialize_with = "crate::utils::deserialize_uint"))]
 	pub epoch: Uint,
 }
@@ -45,6 +46,7 @@ pub struct Fork {
 /// Checkpoint
 pub struct Checkpoint {
 	/// Epoch
+	#[cfg_attr(feature = "serde", serde(serialize_with = "crate::utils::serialize_uint"))]
 	#[cfg_attr(feature = "serde", serde(deserialize_with = "crate::utils::deserialize_uint"))]
 	pub epoch: Uint,
 	/// Root of the checkpoint
@@ -67,6 +69,7 @@ pub struct Validator {
 	/// Withdrawal credentials
 	pub withdrawal_credentials: H256,
 	/// Effective balance
+	#[cfg_attr(feature = "serde", serde(serialize_with = "crate::utils::serialize_uint"))]
 	#[cfg_attr(feature = "serde", serde(deserialize_with = "crate::utils::deserialize_uint"))]
 	pub effective_balance: Uint,
 	/// Was the validator slashed
@@ -74,15 +77,19 @@ pub struct Validator {
 
 	// == Status epochs ==
 	/// Epoch when became eligible for activation
+	#[cfg_attr(feature = "serde", serde(serialize_with = "crate::utils::serialize_uint"))]
 	#[cfg_attr(feature = "serde", serde(deserialize_with = "crate::utils::deserialize_uint"))]
 	pub activation_eligibility_epoch: Uint,
 	/// Epoch when validator activated
+	#[cfg_attr(feature = "serde", serde(serialize_with = "crate::utils::serialize_uint"))]
 	#[cfg_attr(feature = "serde", serde(deserialize_with = "crate::utils::deserialize_uint"))]
 	pub activation_epoch: Uint,
 	/// Epoch when validator exited
+	#[cfg_attr(feature = "serde", serde(serialize_with = "crate::utils::serialize_uint"))]
 	#[cfg_attr(feature = "serde", serde(deserialize_with = "crate::utils::deserialize_uint"))]
 	pub exit_epoch: Uint,
 	/// Epoch when validator is eligible to withdraw
+	#[cfg_attr(feature = "serde", serde(serialize_with = "crate::utils::serialize_uint"))]
 	#[cfg_attr(feature = "serde", serde(deserialize_with = "crate::utils::deserialize_uint"))]
 	pub withdrawable_epoch: Uint,
 }
@@ -115,8 +122,12 @@ impl ValidatorT for Validator {
 /// Attestation data.
 pub struct AttestationData {
 	/// Voting slot.
+	#[cfg_attr(feature = "serde", serde(serialize_with = "crate::utils::serialize_uint"))]
+	#[cfg_attr(feature = "serde", serde(deserialize_with = "crate::utils::deserialize_uint"))]
 	pub slot: Uint,
 	/// Voting committee index.
+	#[cfg_attr(feature = "serde", serde(serialize_with = "crate::utils::serialize_uint"))]
+	#[cfg_attr(feature = "serde", serde(deserialize_with = "crate::utils::deserialize_uint"))]
 	pub index: Uint,
 
 	// == LMD-GHOST vote ==
@@ -158,9 +169,13 @@ pub struct AttestationDataAndCustodyBit {
 pub struct IndexedAttestation<C: Config> {
 	/// Validator indices of custody bit 0.
 	#[bm(compact)]
+	#[cfg_attr(feature = "serde", serde(serialize_with = "crate::utils::serialize_uint_list"))]
+	#[cfg_attr(feature = "serde", serde(deserialize_with = "crate::utils::deserialize_uint_list"))]
 	pub custody_bit_0_indices: MaxVec<Uint, C::MaxValidatorsPerCommittee>,
 	/// Validator indices of custody bit 1
 	#[bm(compact)]
+	#[cfg_attr(feature = "serde", serde(serialize_with = "crate::utils::serialize_uint_list"))]
+	#[cfg_attr(feature = "serde", serde(deserialize_with = "crate::utils::deserialize_uint_list"))]
 	pub custody_bit_1_indices: MaxVec<Uint, C::MaxValidatorsPerCommittee>,
 	/// Attestation data
 	pub data: AttestationData,
@@ -186,9 +201,13 @@ impl<C: Config> From<IndexedAttestation<C>> for SigningIndexedAttestation<C> {
 pub struct SigningIndexedAttestation<C: Config> {
 	/// Validator indices of custody bit 0.
 	#[bm(compact)]
+	#[cfg_attr(feature = "serde", serde(serialize_with = "crate::utils::serialize_uint_list"))]
+	#[cfg_attr(feature = "serde", serde(deserialize_with = "crate::utils::deserialize_uint_list"))]
 	pub custody_bit_0_indices: MaxVec<Uint, C::MaxValidatorsPerCommittee>,
 	/// Validator indices of custody bit 1
 	#[bm(compact)]
+	#[cfg_attr(feature = "serde", serde(serialize_with = "crate::utils::serialize_uint_list"))]
+	#[cfg_attr(feature = "serde", serde(deserialize_with = "crate::utils::deserialize_uint_list"))]
 	pub custody_bit_1_indices: MaxVec<Uint, C::MaxValidatorsPerCommittee>,
 	/// Attestation data
 	pub data: AttestationData,
@@ -208,9 +227,11 @@ pub struct PendingAttestation<C: Config> {
 	/// Attestation data
 	pub data: AttestationData,
 	/// Inclusion delay
+	#[cfg_attr(feature = "serde", serde(serialize_with = "crate::utils::serialize_uint"))]
 	#[cfg_attr(feature = "serde", serde(deserialize_with = "crate::utils::deserialize_uint"))]
 	pub inclusion_delay: Uint,
 	/// Proposer index
+	#[cfg_attr(feature = "serde", serde(serialize_with = "crate::utils::serialize_uint"))]
 	#[cfg_attr(feature = "serde", serde(deserialize_with = "crate::utils::deserialize_uint"))]
 	pub proposer_index: Uint,
 }
@@ -233,6 +254,7 @@ pub struct Eth1Data {
 	/// Root of the deposit tree
 	pub deposit_root: H256,
 	/// Total number of deposits
+	#[cfg_attr(feature = "serde", serde(serialize_with = "crate::utils::serialize_uint"))]
 	#[cfg_attr(feature = "serde", serde(deserialize_with = "crate::utils::deserialize_uint"))]
 	pub deposit_count: Uint,
 	/// Block hash
@@ -261,6 +283,7 @@ pub struct DepositData {
 	/// Withdrawal credentials
 	pub withdrawal_credentials: H256,
 	/// Amount in Gwei
+	#[cfg_attr(feature = "serde", serde(serialize_with = "crate::utils::serialize_uint"))]
 	#[cfg_attr(feature = "serde", serde(deserialize_with = "crate::utils::deserialize_uint"))]
 	pub amount: Uint,
 	/// Container self-signature
@@ -287,6 +310,7 @@ pub struct SigningDepositData {
 	/// Withdrawal credentials
 	pub withdrawal_credentials: H256,
 	/// Amount in Gwei
+	#[cfg_attr(feature = "serde", serde(serialize_with = "crate::utils::serialize_uint"))]
 	#[cfg_attr(feature = "serde", serde(deserialize_with = "crate::utils::deserialize_uint"))]
 	pub amount: Uint,
 }
@@ -297,6 +321,7 @@ pub struct SigningDepositData {
 /// Beacon block header.
 pub struct BeaconBlockHeader {
 	/// Slot of the block.
+	#[cfg_attr(feature = "serde", serde(serialize_with = "crate::utils::serialize_uint"))]
 	#[cfg_attr(feature = "serde", serde(deserialize_with = "crate::utils::deserialize_uint"))]
     pub slot: Uint,
 	/// Previous block root.
@@ -326,6 +351,7 @@ impl From<BeaconBlockHeader> for SigningBeaconBlockHeader {
 /// Beacon block header.
 pub struct SigningBeaconBlockHeader {
 	/// Slot of the block.
+	#[cfg_attr(feature = "serde", serde(serialize_with = "crate::utils::serialize_uint"))]
 	#[cfg_attr(feature = "serde", serde(deserialize_with = "crate::utils::deserialize_uint"))]
     pub slot: Uint,
 	/// Previous block root.
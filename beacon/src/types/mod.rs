@@ -26,7 +26,8 @@ pub use self::misc::{
 };
 pub use self::operation::{
 	ProposerSlashing, AttesterSlashing, Attestation, SigningAttestation,
-	Deposit, VoluntaryExit, SigningVoluntaryExit
+	Deposit, VoluntaryExit, SigningVoluntaryExit, AggregateAndProof,
+	SignedAggregateAndProof,
 };
 pub use self::block::{
 	BeaconBlockBody, BeaconBlock, UnsealedBeaconBlock, Block,
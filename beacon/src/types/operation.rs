@@ -31,6 +31,7 @@ use super::misc::{IndexedAttestation, BeaconBlockHeader, DepositData, Attestatio
 /// Block proposer slashing.
 pub struct ProposerSlashing {
 	/// Proposer index
+	#[cfg_attr(feature = "serde", serde(serialize_with = "crate::utils::serialize_uint"))]
 	#[cfg_attr(feature = "serde", serde(deserialize_with = "crate::utils::deserialize_uint"))]
 	pub proposer_index: Uint,
 	/// First proposal
@@ -103,6 +104,34 @@ pub struct SigningAttestation<C: Config> {
 	pub custody_bits: MaxVec<bool, C::MaxValidatorsPerCommittee>,
 }
 
+#[derive(Codec, Encode, Decode, IntoTree, FromTree, Clone, PartialEq, Eq, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(deny_unknown_fields))]
+#[cfg_attr(feature = "serde", serde(bound = "C: Config"))]
+#[cfg_attr(feature = "parity-codec", derive(parity_codec::Encode, parity_codec::Decode))]
+/// Aggregated attestation with the proof that its sender is an aggregator.
+pub struct AggregateAndProof<C: Config> {
+	/// Index of the aggregating validator
+	#[cfg_attr(feature = "serde", serde(serialize_with = "crate::utils::serialize_uint"))]
+	#[cfg_attr(feature = "serde", serde(deserialize_with = "crate::utils::deserialize_uint"))]
+	pub index: Uint,
+	/// Slot signature of the aggregating validator
+	pub selection_proof: Signature,
+	/// Aggregated attestation
+	pub aggregate: Attestation<C>,
+}
+
+#[derive(Codec, Encode, Decode, IntoTree, FromTree, Clone, PartialEq, Eq, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(deny_unknown_fields))]
+#[cfg_attr(feature = "serde", serde(bound = "C: Config"))]
+#[cfg_attr(feature = "parity-codec", derive(parity_codec::Encode, parity_codec::Decode))]
+/// Aggregate and proof signed by the aggregating validator.
+pub struct SignedAggregateAndProof<C: Config> {
+	/// Aggregate and proof
+	pub message: AggregateAndProof<C>,
+	/// Aggregator signature
+	pub signature: Signature,
+}
+
 #[derive(Codec, Encode, Decode, IntoTree, FromTree, Clone, PartialEq, Eq, Default, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(deny_unknown_fields))]
 #[cfg_attr(feature = "parity-codec", derive(parity_codec::Encode, parity_codec::Decode))]
@@ -120,9 +149,11 @@ pub struct Deposit {
 /// Block voluntary exit.
 pub struct VoluntaryExit {
 	/// Minimum epoch for processing exit
+	#[cfg_attr(feature = "serde", serde(serialize_with = "crate::utils::serialize_uint"))]
 	#[cfg_attr(feature = "serde", serde(deserialize_with = "crate::utils::deserialize_uint"))]
 	pub epoch: Uint,
 	/// Index of the exiting validator
+	#[cfg_attr(feature = "serde", serde(serialize_with = "crate::utils::serialize_uint"))]
 	#[cfg_attr(feature = "serde", serde(deserialize_with = "crate::utils::deserialize_uint"))]
 	pub validator_index: Uint,
 	/// Validator signature
@@ -144,9 +175,11 @@ impl From<VoluntaryExit> for SigningVoluntaryExit {
 /// Unsealed voluntary exit transaction.
 pub struct SigningVoluntaryExit {
 	/// Minimum epoch for processing exit
+	#[cfg_attr(feature = "serde", serde(serialize_with = "crate::utils::serialize_uint"))]
 	#[cfg_attr(feature = "serde", serde(deserialize_with = "crate::utils::deserialize_uint"))]
 	pub epoch: Uint,
 	/// Index of the exiting validator
+	#[cfg_attr(feature = "serde", serde(serialize_with = "crate::utils::serialize_uint"))]
 	#[cfg_attr(feature = "serde", serde(deserialize_with = "crate::utils::deserialize_uint"))]
 	pub validator_index: Uint,
 }
@@ -0,0 +1,290 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Parity Shasper.
+
+// Parity Shasper is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+
+// Parity Shasper is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more
+// details.
+
+// You should have received a copy of the GNU General Public License along with
+// Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+use ssz::{Codec, Encode, Decode};
+use bm_le::tree_root;
+use core::marker::PhantomData;
+use crate::Config;
+use crate::consts::DEPOSIT_CONTRACT_TREE_DEPTH;
+use crate::primitives::{H256, Uint};
+use crate::types::DepositData;
+use super::to_bytes;
+
+const DEPTH: usize = DEPOSIT_CONTRACT_TREE_DEPTH as usize;
+
+#[derive(Codec, Encode, Decode, Clone, PartialEq, Eq, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(deny_unknown_fields))]
+/// Snapshot of the finalized part of a deposit tree.
+pub struct DepositTreeSnapshot {
+	/// Finalized branch, one node per tree height. Only nodes at heights
+	/// whose bit is set in `deposit_count` are meaningful.
+	pub finalized_branch: Vec<H256>,
+	/// Number of finalized deposits.
+	#[cfg_attr(feature = "serde", serde(serialize_with = "crate::utils::serialize_uint"))]
+	#[cfg_attr(feature = "serde", serde(deserialize_with = "crate::utils::deserialize_uint"))]
+	pub deposit_count: Uint,
+}
+
+/// Incremental deposit contract Merkle tree.
+///
+/// Leaves before the finalized deposit count are dropped, and only the
+/// branch needed to compute roots and proofs of later deposits is kept.
+/// Roots and proofs can be requested for any deposit count not smaller than
+/// the finalized one.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct DepositTree<C: Config> {
+	finalized_count: Uint,
+	finalized_branch: Vec<H256>,
+	/// Complete nodes by height, starting from the first node at each
+	/// height that is not fully finalized. Height zero holds the leaves.
+	nodes: Vec<Vec<H256>>,
+	zero_hashes: Vec<H256>,
+	_marker: PhantomData<C>,
+}
+
+impl<C: Config> Default for DepositTree<C> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<C: Config> DepositTree<C> {
+	/// Create a new empty deposit tree.
+	pub fn new() -> Self {
+		let mut zero_hashes = vec![H256::default()];
+		for h in 0..DEPTH {
+			zero_hashes.push(C::hash(&[zero_hashes[h].as_ref(), zero_hashes[h].as_ref()]));
+		}
+
+		Self {
+			finalized_count: 0,
+			finalized_branch: vec![H256::default(); DEPTH],
+			nodes: vec![Vec::new(); DEPTH + 1],
+			zero_hashes,
+			_marker: PhantomData,
+		}
+	}
+
+	/// Restore a deposit tree from a snapshot.
+	pub fn from_snapshot(snapshot: DepositTreeSnapshot) -> Option<Self> {
+		if snapshot.finalized_branch.len() != DEPTH {
+			return None
+		}
+
+		let mut tree = Self::new();
+		tree.finalized_count = snapshot.deposit_count;
+		tree.finalized_branch = snapshot.finalized_branch;
+		Some(tree)
+	}
+
+	/// Snapshot of the finalized part of the tree.
+	pub fn snapshot(&self) -> DepositTreeSnapshot {
+		DepositTreeSnapshot {
+			finalized_branch: self.finalized_branch.clone(),
+			deposit_count: self.finalized_count,
+		}
+	}
+
+	/// Total number of deposits in the tree.
+	pub fn deposit_count(&self) -> Uint {
+		self.finalized_count + self.nodes[0].len() as Uint
+	}
+
+	/// Number of finalized deposits.
+	pub fn finalized_count(&self) -> Uint {
+		self.finalized_count
+	}
+
+	/// Push a new deposit.
+	pub fn push(&mut self, data: DepositData) {
+		self.push_leaf(tree_root::<C::Digest, _>(&data))
+	}
+
+	/// Push a new deposit leaf.
+	pub fn push_leaf(&mut self, leaf: H256) {
+		self.nodes[0].push(leaf);
+
+		let count = self.deposit_count();
+		for h in 1..=DEPTH {
+			if count % (1 << h) != 0 {
+				break
+			}
+
+			let j = (count >> h) - 1;
+			let left = self.complete(h - 1, 2 * j)
+				.expect("Children of a complete node are complete; qed");
+			let right = self.complete(h - 1, 2 * j + 1)
+				.expect("Children of a complete node are complete; qed");
+			self.nodes[h].push(C::hash(&[left.as_ref(), right.as_ref()]));
+		}
+	}
+
+	/// Node `j` at height `h`, which must cover only existing deposits.
+	fn complete(&self, h: usize, j: Uint) -> Option<H256> {
+		let base = self.finalized_count >> h;
+		if j < base {
+			if j + 1 == base && base & 1 == 1 {
+				Some(self.finalized_branch[h])
+			} else {
+				None
+			}
+		} else {
+			self.nodes[h].get((j - base) as usize).cloned()
+		}
+	}
+
+	/// Node `j` at height `h` when the deposit count was `count`.
+	fn node(&self, h: usize, j: Uint, count: Uint) -> Option<H256> {
+		let start = j << h;
+		if start >= count {
+			return Some(self.zero_hashes[h])
+		}
+
+		let end = (j + 1) << h;
+		if end <= count {
+			return self.complete(h, j)
+		}
+
+		let left = self.node(h - 1, 2 * j, count)?;
+		let right = self.node(h - 1, 2 * j + 1, count)?;
+		Some(C::hash(&[left.as_ref(), right.as_ref()]))
+	}
+
+	fn valid_count(&self, count: Uint) -> bool {
+		count >= self.finalized_count && count <= self.deposit_count()
+	}
+
+	/// Deposit root, with length mixed in, of all deposits in the tree.
+	pub fn root(&self) -> H256 {
+		self.root_at(self.deposit_count())
+			.expect("Current deposit count is always valid; qed")
+	}
+
+	/// Deposit root, with length mixed in, when the deposit count was
+	/// `count`.
+	pub fn root_at(&self, count: Uint) -> Option<H256> {
+		if !self.valid_count(count) {
+			return None
+		}
+
+		let root = self.node(DEPTH, 0, count)?;
+		Some(C::hash(&[root.as_ref(), to_bytes(count).as_ref()]))
+	}
+
+	/// Merkle proof of deposit `index` against the deposit root when the
+	/// deposit count was `count`. The proof includes the length leaf.
+	pub fn proof(&self, index: Uint, count: Uint) -> Option<Vec<H256>> {
+		if !self.valid_count(count) || index < self.finalized_count || index >= count {
+			return None
+		}
+
+		let mut proof = Vec::with_capacity(DEPTH + 1);
+		for h in 0..DEPTH {
+			proof.push(self.node(h, (index >> h) ^ 1, count)?);
+		}
+		proof.push(to_bytes(count));
+		Some(proof)
+	}
+
+	/// Finalize the first `count` deposits, dropping data no longer needed.
+	pub fn finalize(&mut self, count: Uint) -> Option<()> {
+		if !self.valid_count(count) {
+			return None
+		}
+
+		let mut finalized_branch = vec![H256::default(); DEPTH];
+		for h in 0..DEPTH {
+			if (count >> h) & 1 == 1 {
+				finalized_branch[h] = self.complete(h, (count >> h) - 1)?;
+			}
+		}
+
+		for h in 0..=DEPTH {
+			let dropped = ((count >> h) - (self.finalized_count >> h)) as usize;
+			self.nodes[h].drain(..dropped);
+		}
+		self.finalized_branch = finalized_branch;
+		self.finalized_count = count;
+
+		Some(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::MinimalConfig;
+	use crate::utils::is_valid_merkle_branch;
+
+	fn leaf(i: u64) -> H256 {
+		H256::from_low_u64_le(i + 1)
+	}
+
+	fn assert_proofs(tree: &DepositTree<MinimalConfig>, from: u64, count: u64) {
+		let root = tree.root_at(count).unwrap();
+		for index in from..count {
+			let proof = tree.proof(index, count).unwrap();
+			assert!(is_valid_merkle_branch::<MinimalConfig>(
+				leaf(index), &proof, DEPOSIT_CONTRACT_TREE_DEPTH + 1, index, root,
+			));
+		}
+	}
+
+	#[test]
+	fn historical_proofs_are_valid() {
+		let mut tree = DepositTree::<MinimalConfig>::new();
+		for i in 0..21 {
+			tree.push_leaf(leaf(i));
+		}
+
+		for count in 0..=21 {
+			assert_proofs(&tree, 0, count);
+		}
+		assert_eq!(tree.proof(21, 21), None);
+		assert_eq!(tree.root_at(22), None);
+	}
+
+	#[test]
+	fn finalized_snapshot_matches_full_tree() {
+		let mut full = DepositTree::<MinimalConfig>::new();
+		for i in 0..13 {
+			full.push_leaf(leaf(i));
+		}
+
+		let mut finalized = full.clone();
+		finalized.finalize(11).unwrap();
+		let mut restored = DepositTree::<MinimalConfig>::from_snapshot(finalized.snapshot()).unwrap();
+		for i in 11..13 {
+			restored.push_leaf(leaf(i));
+		}
+
+		for i in 13..30 {
+			full.push_leaf(leaf(i));
+			finalized.push_leaf(leaf(i));
+			restored.push_leaf(leaf(i));
+		}
+
+		for count in 13..=30 {
+			assert_eq!(finalized.root_at(count), full.root_at(count));
+			assert_eq!(restored.root_at(count), full.root_at(count));
+		}
+		assert_proofs(&finalized, 11, 30);
+		assert_proofs(&restored, 11, 30);
+		assert_eq!(finalized.proof(10, 30), None);
+	}
+}
@@ -20,8 +20,12 @@ mod serde;
 #[cfg(feature = "serde")]
 pub use self::serde::*;
 
+mod deposit_tree;
+
+pub use self::deposit_tree::{DepositTree, DepositTreeSnapshot};
+
 use crate::{Config, Error};
-use crate::primitives::{H256, Uint, ValidatorIndex, Version};
+use crate::primitives::{H256, Uint, ValidatorIndex, Version, Signature};
 use core::cmp::max;
 use core::convert::TryInto;
 
@@ -40,6 +44,13 @@ pub fn to_uint(v: &[u8]) -> Uint {
 	u64::from_le_bytes(ret)
 }
 
+/// Whether the validator that produced `slot_signature` is an aggregator of
+/// a committee of `committee_length`.
+pub fn is_aggregator<C: Config>(committee_length: Uint, slot_signature: &Signature) -> bool {
+	let modulo = max(1, committee_length / C::target_aggregators_per_committee());
+	to_uint(&C::hash(&[&slot_signature[..]])[0..8]) % modulo == 0
+}
+
 /// Get integer squareroot.
 pub fn integer_squareroot(n: Uint) -> Uint {
 	let mut x = n;
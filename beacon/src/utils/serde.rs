@@ -14,7 +14,7 @@
 // You should have received a copy of the GNU General Public License along with
 // Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
 
-use serde::{Serializer, Deserializer};
+use serde::{Serializer, Deserializer, Deserialize};
 use serde::de::{Error, Visitor};
 use impl_serde::serialize;
 use core::fmt;
@@ -55,9 +55,16 @@ fn deserialize_bitseq<'a, 'de, D: Deserializer<'de>>(
 	is_list: bool,
 ) -> Result<Vec<bool>, D::Error> {
 	let bytes = serialize::deserialize_check_len(deserializer, serialize::ExpectedLen::Any)?;
+	let last = match bytes.last() {
+		Some(last) => *last,
+		None => return Err(D::Error::custom("Empty bitfield")),
+	};
+	if is_list && last == 0 {
+		return Err(D::Error::custom("Bitlist without length bit"))
+	}
 
 	let len = (bytes.len() - 1) * 8 +
-		(if is_list { 7 } else { 8 } - bytes[bytes.len() - 1].leading_zeros() as usize);
+		(if is_list { 7 } else { 8 } - last.leading_zeros() as usize);
 	let mut ret = Vec::new();
 	for i in 0..len {
 		ret.push(bytes[i / 8] & (1 << (i % 8)) != 0);
@@ -66,10 +73,15 @@ fn deserialize_bitseq<'a, 'de, D: Deserializer<'de>>(
 }
 
 /// Deserialize a serde bitlist.
-pub fn deserialize_bitlist<'a, 'de, ML, D: Deserializer<'de>>(
+pub fn deserialize_bitlist<'a, 'de, ML: typenum::Unsigned, D: Deserializer<'de>>(
 	deserializer: D
 ) -> Result<bm_le::MaxVec<bool, ML>, D::Error> {
-	Ok(bm_le::MaxVec::from(deserialize_bitseq(deserializer, true)?))
+	let seq = deserialize_bitseq(deserializer, true)?;
+	if seq.len() > ML::to_usize() {
+		return Err(D::Error::custom("Bitlist too long"))
+	}
+
+	Ok(bm_le::MaxVec::from(seq))
 }
 
 /// Deserialize a serde bitvector.
@@ -84,6 +96,58 @@ pub fn deserialize_bitvector<'a, 'de, L: typenum::Unsigned, D: Deserializer<'de>
 	vecarray::VecArray::try_from(seq).map_err(|_| D::Error::custom("Invalid bitlist"))
 }
 
+/// Serialize u64 as a quoted decimal string, as the Beacon API does.
+pub fn serialize_uint<S: Serializer>(
+	value: &u64,
+	serializer: S
+) -> Result<S::Ok, S::Error> {
+	serializer.collect_str(value)
+}
+
+/// Serialize a sequence of u64 as quoted decimal strings.
+pub fn serialize_uint_seq<T: AsRef<[u64]>, S: Serializer>(
+	value: &T,
+	serializer: S,
+) -> Result<S::Ok, S::Error> {
+	serializer.collect_seq(value.as_ref().iter().map(|value| value.to_string()))
+}
+
+/// Serialize a list of u64 as quoted decimal strings.
+pub fn serialize_uint_list<ML, S: Serializer>(
+	value: &bm_le::MaxVec<u64, ML>,
+	serializer: S
+) -> Result<S::Ok, S::Error> { serialize_uint_seq(value, serializer) }
+
+/// Serialize a vector of u64 as quoted decimal strings.
+pub fn serialize_uint_vector<L: typenum::Unsigned, S: Serializer>(
+	value: &vecarray::VecArray<u64, L>,
+	serializer: S
+) -> Result<S::Ok, S::Error> { serialize_uint_seq(value, serializer) }
+
+#[derive(Deserialize)]
+struct AnyUint(#[serde(deserialize_with = "deserialize_uint")] u64);
+
+fn deserialize_uint_seq<'a, 'de, D: Deserializer<'de>>(
+	deserializer: D,
+) -> Result<Vec<u64>, D::Error> {
+	Ok(Vec::<AnyUint>::deserialize(deserializer)?.into_iter().map(|value| value.0).collect())
+}
+
+/// Deserialize a list of u64, each either a number or a string.
+pub fn deserialize_uint_list<'a, 'de, ML, D: Deserializer<'de>>(
+	deserializer: D
+) -> Result<bm_le::MaxVec<u64, ML>, D::Error> {
+	Ok(bm_le::MaxVec::from(deserialize_uint_seq(deserializer)?))
+}
+
+/// Deserialize a vector of u64, each either a number or a string.
+pub fn deserialize_uint_vector<'a, 'de, L: typenum::Unsigned, D: Deserializer<'de>>(
+	deserializer: D
+) -> Result<vecarray::VecArray<u64, L>, D::Error> {
+	vecarray::VecArray::try_from(deserialize_uint_seq(deserializer)?)
+		.map_err(|_| D::Error::custom("Invalid vector length"))
+}
+
 /// Deserialize u64 or string.
 pub fn deserialize_uint<'a, 'de, D: Deserializer<'de>>(
 	deserializer: D
@@ -123,3 +187,88 @@ pub fn deserialize_uint<'a, 'de, D: Deserializer<'de>>(
 
 	deserializer.deserialize_any(UintVisitor)
 }
+
+#[cfg(test)]
+mod tests {
+	use serde::{Serialize, de::DeserializeOwned};
+	use crate::{BeaconState, MinimalConfig};
+	use crate::types::{
+		AggregateAndProof, Attestation, AttesterSlashing, BeaconBlock, Deposit, ProposerSlashing,
+		Validator, VoluntaryExit,
+	};
+
+	fn assert_round_trip<T: Serialize + DeserializeOwned>(fixture: &str) {
+		let expected: serde_json::Value = serde_json::from_str(fixture).unwrap();
+		let value: T = serde_json::from_str(fixture).unwrap();
+		assert_eq!(serde_json::to_value(&value).unwrap(), expected);
+	}
+
+	#[test]
+	fn json_round_trips_reference_fixtures() {
+		assert_round_trip::<Attestation<MinimalConfig>>(
+			include_str!("../../res/json/attestation.json")
+		);
+		assert_round_trip::<AttesterSlashing<MinimalConfig>>(
+			include_str!("../../res/json/attester_slashing.json")
+		);
+		assert_round_trip::<Validator>(include_str!("../../res/json/validator.json"));
+		assert_round_trip::<VoluntaryExit>(include_str!("../../res/json/voluntary_exit.json"));
+		assert_round_trip::<ProposerSlashing>(
+			include_str!("../../res/json/proposer_slashing.json")
+		);
+		assert_round_trip::<Deposit>(include_str!("../../res/json/deposit.json"));
+		assert_round_trip::<AggregateAndProof<MinimalConfig>>(
+			include_str!("../../res/json/aggregate_and_proof.json")
+		);
+		assert_round_trip::<BeaconBlock<MinimalConfig>>(
+			include_str!("../../res/json/beacon_block.json")
+		);
+		assert_round_trip::<BeaconState<MinimalConfig>>(
+			include_str!("../../res/json/beacon_state.json")
+		);
+	}
+
+	#[test]
+	fn json_fixtures_decode_nested_encodings() {
+		let state: BeaconState<MinimalConfig> = serde_json::from_str(
+			include_str!("../../res/json/beacon_state.json")
+		).unwrap();
+		assert_eq!(&state.justification_bits[..], &[true, true, false, false][..]);
+		assert_eq!(&state.previous_epoch_attestations[0].aggregation_bits[..], &[true][..]);
+		assert_eq!(state.slashings[1], 1_000_000_000);
+		assert_eq!(state.latest_block_header.slot, 16);
+
+		let block: BeaconBlock<MinimalConfig> = serde_json::from_str(
+			include_str!("../../res/json/beacon_block.json")
+		).unwrap();
+		let slashing = &block.body.proposer_slashings[0];
+		assert_eq!(slashing.header_1.slot, slashing.header_2.slot);
+		assert_ne!(slashing.header_1.state_root, slashing.header_2.state_root);
+		assert_eq!(&block.body.attestations[0].aggregation_bits[..], &[true, true, false][..]);
+	}
+
+	#[test]
+	fn malformed_bitlists_are_rejected() {
+		let fixture = include_str!("../../res/json/attestation.json");
+		let decode = |bits: &str| serde_json::from_str::<Attestation<MinimalConfig>>(
+			&fixture.replace("\"0x0b\"", &format!("\"{}\"", bits))
+		);
+
+		assert!(decode("0x").is_err());
+		assert!(decode("0x00").is_err());
+		assert!(decode("0x0b00").is_err());
+		assert!(decode(&format!("0x{}02", "ff".repeat(256))).is_err());
+		assert!(decode("0x0b").is_ok());
+	}
+
+	#[test]
+	fn yaml_with_bare_integers_still_decodes() {
+		let yaml = "epoch: 3\nvalidator_index: 42\nsignature: '0x".to_string() +
+			&"d4".repeat(96) + "'\n";
+		let exit: VoluntaryExit = serde_yaml::from_str(&yaml).unwrap();
+		let fixture: VoluntaryExit = serde_json::from_str(
+			include_str!("../../res/json/voluntary_exit.json")
+		).unwrap();
+		assert_eq!(exit, fixture);
+	}
+}
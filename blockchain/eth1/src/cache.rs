@@ -0,0 +1,86 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Parity Shasper.
+
+// Parity Shasper is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+
+// Parity Shasper is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more
+// details.
+
+// You should have received a copy of the GNU General Public License along with
+// Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
+use beacon::Config;
+use beacon::primitives::H256;
+use beacon::types::Deposit;
+use beacon::utils::DepositTree;
+use core::convert::TryInto;
+use crate::{Error, DepositLog};
+
+/// Cache of all deposits seen in the deposit contract.
+pub struct DepositCache<C: Config> {
+	logs: Vec<DepositLog>,
+	tree: DepositTree<C>,
+}
+
+impl<C: Config> DepositCache<C> {
+	pub fn new() -> Self {
+		Self {
+			logs: Vec::new(),
+			tree: DepositTree::new(),
+		}
+	}
+
+	/// Number of deposits in the cache.
+	pub fn len(&self) -> u64 {
+		self.logs.len() as u64
+	}
+
+	/// Insert a new deposit log. Logs must be inserted in deposit index
+	/// order. Re-inserting an already known log is a no-op.
+	pub fn insert(&mut self, log: DepositLog) -> Result<(), Error> {
+		if log.index < self.len() && self.logs[log.index as usize] == log {
+			return Ok(())
+		}
+
+		if log.index != self.len() {
+			return Err(Error::NonSequentialDeposit { expected: self.len(), got: log.index })
+		}
+
+		self.tree.push(log.data.clone());
+		self.logs.push(log);
+		Ok(())
+	}
+
+	/// Number of deposits made up to and including the given Eth1 block.
+	pub fn count_at_block(&self, block_number: u64) -> u64 {
+		self.logs.iter().take_while(|log| log.block_number <= block_number).count() as u64
+	}
+
+	/// Deposit root, with length mixed in, when the deposit count was
+	/// `count`.
+	pub fn root(&self, count: u64) -> Option<H256> {
+		self.tree.root_at(count)
+	}
+
+	/// Deposits in `start..end`, with proofs against the deposit root when
+	/// the deposit count was `count`.
+	pub fn deposits(&self, start: u64, end: u64, count: u64) -> Result<Vec<Deposit>, Error> {
+		if start > end || end > count || count > self.len() {
+			return Err(Error::MissingDeposits)
+		}
+
+		(start..end).map(|index| {
+			Ok(Deposit {
+				proof: self.tree.proof(index, count)
+					.ok_or(Error::MissingDeposits)?
+					.try_into()
+					.map_err(|_| Error::MissingDeposits)?,
+				data: self.logs[index as usize].data.clone(),
+			})
+		}).collect()
+	}
+}
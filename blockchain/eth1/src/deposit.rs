@@ -0,0 +1,115 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Parity Shasper.
+
+// Parity Shasper is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+
+// Parity Shasper is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more
+// details.
+
+// You should have received a copy of the GNU General Public License along with
+// Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
+use beacon::primitives::{H256, ValidatorId, Signature};
+use beacon::types::DepositData;
+use core::convert::TryInto;
+use crate::Error;
+
+/// Topic of the deposit contract `DepositEvent(bytes,bytes,bytes,bytes,bytes)`.
+pub const DEPOSIT_EVENT_TOPIC: &str =
+	"0x649bbc62d0e31342afea4e5cd82d4049e7e1ee912fc0889aa790803be39038c5";
+
+/// Number of dynamic `bytes` fields in a deposit event.
+const DEPOSIT_EVENT_FIELDS: usize = 5;
+
+/// A decoded deposit contract log.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct DepositLog {
+	/// Eth1 block number containing the log.
+	pub block_number: u64,
+	/// Index of the deposit in the deposit contract.
+	pub index: u64,
+	/// Deposit data.
+	pub data: DepositData,
+}
+
+fn read_word(data: &[u8], offset: usize) -> Result<u64, Error> {
+	let word = data.get(offset..offset + 32).ok_or(Error::InvalidDepositLog)?;
+	if word[..24].iter().any(|b| *b != 0) {
+		return Err(Error::InvalidDepositLog)
+	}
+	let mut bytes = [0u8; 8];
+	bytes.copy_from_slice(&word[24..]);
+	Ok(u64::from_be_bytes(bytes))
+}
+
+fn read_bytes(data: &[u8], field: usize) -> Result<&[u8], Error> {
+	let offset = read_word(data, field * 32)? as usize;
+	let len = read_word(data, offset)? as usize;
+	data.get(offset + 32..offset + 32 + len).ok_or(Error::InvalidDepositLog)
+}
+
+fn read_u64_le(value: &[u8]) -> Result<u64, Error> {
+	let bytes: [u8; 8] = value.try_into().map_err(|_| Error::InvalidDepositLog)?;
+	Ok(u64::from_le_bytes(bytes))
+}
+
+impl DepositLog {
+	/// Decode the ABI-encoded data of a deposit event.
+	pub fn from_event_data(block_number: u64, data: &[u8]) -> Result<Self, Error> {
+		let pubkey = read_bytes(data, 0)?;
+		let withdrawal_credentials = read_bytes(data, 1)?;
+		let amount = read_bytes(data, 2)?;
+		let signature = read_bytes(data, 3)?;
+		let index = read_bytes(data, 4)?;
+
+		if pubkey.len() != 48 || withdrawal_credentials.len() != 32 || signature.len() != 96 {
+			return Err(Error::InvalidDepositLog)
+		}
+
+		Ok(Self {
+			block_number,
+			index: read_u64_le(index)?,
+			data: DepositData {
+				pubkey: ValidatorId::from_slice(pubkey),
+				withdrawal_credentials: H256::from_slice(withdrawal_credentials),
+				amount: read_u64_le(amount)?,
+				signature: Signature::from_slice(signature),
+			},
+		})
+	}
+
+	/// ABI-encode the deposit event data.
+	pub fn to_event_data(&self) -> Vec<u8> {
+		let fields: [&[u8]; DEPOSIT_EVENT_FIELDS] = [
+			self.data.pubkey.as_ref(),
+			self.data.withdrawal_credentials.as_ref(),
+			&self.data.amount.to_le_bytes(),
+			self.data.signature.as_ref(),
+			&self.index.to_le_bytes(),
+		];
+
+		let word = |value: usize| {
+			let mut word = [0u8; 32];
+			word[24..].copy_from_slice(&(value as u64).to_be_bytes());
+			word
+		};
+
+		let mut head = Vec::new();
+		let mut tail = Vec::new();
+		for field in fields.iter() {
+			head.extend_from_slice(&word(DEPOSIT_EVENT_FIELDS * 32 + tail.len()));
+			tail.extend_from_slice(&word(field.len()));
+			tail.extend_from_slice(field);
+			while tail.len() % 32 != 0 {
+				tail.push(0);
+			}
+		}
+
+		head.extend(tail);
+		head
+	}
+}
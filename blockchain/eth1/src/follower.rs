@@ -0,0 +1,285 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Parity Shasper.
+
+// Parity Shasper is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+
+// Parity Shasper is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more
+// details.
+
+// You should have received a copy of the GNU General Public License along with
+// Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
+use beacon::{Config, BeaconState};
+use beacon::types::{Eth1Data, Deposit};
+use beacon::utils::integer_squareroot;
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use std::thread;
+use core::cmp::min;
+use core::ops::Range;
+use log::{info, warn};
+use crate::{Error, Eth1Client, DepositCache};
+
+/// Maximum number of blocks requested in a single `eth_getLogs` call.
+const LOGS_PER_REQUEST: u64 = 1000;
+
+/// Number of follow distances worth of Eth1 blocks kept in the cache.
+const BLOCK_CACHE_DISTANCES: u64 = 4;
+
+/// Eth1 follower configuration.
+#[derive(Clone, Debug)]
+pub struct Eth1FollowerConfig {
+	/// Eth1 JSON-RPC HTTP endpoint.
+	pub endpoint: String,
+	/// Address of the deposit contract.
+	pub deposit_contract: String,
+	/// Eth1 block number the deposit contract was deployed at.
+	pub deploy_block: u64,
+	/// Interval between polls of the endpoint.
+	pub poll_interval: Duration,
+}
+
+/// Eth1 deposit contract follower.
+///
+/// Only blocks at least `ETH1_FOLLOW_DISTANCE` deep are processed, so the
+/// follower does not need to handle Eth1 reorganizations.
+pub struct Eth1Follower<C: Config> {
+	client: Eth1Client,
+	config: Eth1FollowerConfig,
+	cache: DepositCache<C>,
+	blocks: BTreeMap<u64, Eth1Data>,
+	latest_block: Option<u64>,
+	processed_block: Option<u64>,
+}
+
+impl<C: Config> Eth1Follower<C> {
+	pub fn new(config: Eth1FollowerConfig) -> Self {
+		Self {
+			client: Eth1Client::new(&config.endpoint),
+			config,
+			cache: DepositCache::new(),
+			blocks: BTreeMap::new(),
+			latest_block: None,
+			processed_block: None,
+		}
+	}
+
+	/// Deposit cache of the follower.
+	pub fn deposit_cache(&self) -> &DepositCache<C> {
+		&self.cache
+	}
+
+	/// Poll the endpoint once, importing new deposit logs and blocks.
+	pub fn update(&mut self) -> Result<(), Error> {
+		let follow_distance = C::eth1_follow_distance();
+		let latest = self.client.block_number()?;
+		let target = match latest.checked_sub(follow_distance) {
+			Some(target) => target,
+			None => {
+				self.latest_block = Some(latest);
+				return Ok(())
+			},
+		};
+
+		let mut from = self.processed_block.map(|n| n + 1).unwrap_or(self.config.deploy_block);
+		while from <= target {
+			let to = min(from + LOGS_PER_REQUEST - 1, target);
+			for log in self.client.deposit_logs(&self.config.deposit_contract, from, to)? {
+				self.cache.insert(log)?;
+			}
+			self.processed_block = Some(to);
+			from = to + 1;
+		}
+
+		for number in (target + 1).saturating_sub(follow_distance)..=target {
+			if self.blocks.contains_key(&number) {
+				continue
+			}
+
+			let block = self.client.block_by_number(number)?.ok_or(Error::InvalidResponse)?;
+			let deposit_count = self.cache.count_at_block(number);
+			let deposit_root = self.cache.root(deposit_count)
+				.expect("Count at block is never larger than the cache; qed");
+			self.blocks.insert(number, Eth1Data {
+				deposit_root, deposit_count, block_hash: block.hash,
+			});
+		}
+
+		let retain_from = (target + 1).saturating_sub(BLOCK_CACHE_DISTANCES * follow_distance);
+		self.blocks = self.blocks.split_off(&retain_from);
+		self.latest_block = Some(latest);
+
+		Ok(())
+	}
+
+	/// Eth1 data of the block `distance` blocks behind the latest one.
+	pub fn eth1_data(&self, distance: u64) -> Option<Eth1Data> {
+		let number = self.latest_block?.checked_sub(distance)?;
+		self.blocks.get(&number).cloned()
+	}
+
+	/// Eth1 data to vote for in a block built on top of the given state,
+	/// following `get_eth1_vote` of the validator guide.
+	pub fn eth1_vote(&self, state: &BeaconState<C>) -> Option<Eth1Data> {
+		let follow_distance = C::eth1_follow_distance();
+		let latest = self.latest_block?;
+
+		let previous_distance = self.blocks.iter()
+			.find(|(_, data)| data.block_hash == state.eth1_data.block_hash)
+			.map(|(number, _)| latest - number)
+			.unwrap_or(2 * follow_distance);
+
+		let eth1_data_in = |distances: Range<u64>| {
+			distances
+				.filter_map(|distance| self.eth1_data(distance))
+				.filter(|data| data.deposit_count >= state.eth1_data.deposit_count)
+				.collect::<Vec<_>>()
+		};
+		let new_eth1_data = eth1_data_in(follow_distance..(2 * follow_distance));
+		let all_eth1_data = eth1_data_in(follow_distance..previous_distance);
+
+		let period_tail = state.slot % C::slots_per_eth1_voting_period() >=
+			integer_squareroot(C::slots_per_eth1_voting_period());
+		let votes_to_consider = if period_tail { &all_eth1_data } else { &new_eth1_data };
+
+		let valid_votes = state.eth1_data_votes.iter()
+			.filter(|vote| votes_to_consider.contains(*vote))
+			.collect::<Vec<_>>();
+
+		valid_votes.iter()
+			.max_by_key(|vote| {
+				let count = valid_votes.iter().filter(|v| v == vote).count();
+				// Tiebreak by smallest distance.
+				let rank = all_eth1_data.iter()
+					.position(|data| data == **vote)
+					.map(|index| all_eth1_data.len() - index)
+					.unwrap_or(0);
+				(count, rank)
+			})
+			.map(|vote| (*vote).clone())
+			.or_else(|| {
+				self.eth1_data(follow_distance)
+					.filter(|data| data.deposit_count >= state.eth1_data.deposit_count)
+			})
+	}
+
+	/// Deposits to include in a block built on top of the given state. The
+	/// state must already have the block's eth1 data vote applied.
+	pub fn deposits(&self, state: &BeaconState<C>) -> Result<Vec<Deposit>, Error> {
+		let count = state.eth1_data.deposit_count;
+		let start = state.eth1_deposit_index;
+		let end = min(count, start + C::max_deposits());
+
+		if start >= end {
+			return Ok(Vec::new())
+		}
+		if count > self.cache.len() {
+			return Err(Error::MissingDeposits)
+		}
+		if self.cache.root(count) != Some(state.eth1_data.deposit_root) {
+			return Err(Error::DepositRootMismatch)
+		}
+
+		self.cache.deposits(start, end, count)
+	}
+}
+
+/// Spawn a thread polling the Eth1 endpoint, and return the shared
+/// follower.
+pub fn spawn_follower<C: Config>(config: Eth1FollowerConfig) -> Arc<RwLock<Eth1Follower<C>>> {
+	let poll_interval = config.poll_interval;
+	let follower = Arc::new(RwLock::new(Eth1Follower::new(config)));
+
+	let follower_thread = follower.clone();
+	thread::spawn(move || {
+		loop {
+			let result = follower_thread.write()
+				.expect("Eth1 follower lock poisoned")
+				.update();
+			match result {
+				Ok(()) => {
+					let follower = follower_thread.read().expect("Eth1 follower lock poisoned");
+					info!("Eth1 follower at block {:?} with {} deposits",
+						  follower.latest_block, follower.cache.len());
+				},
+				Err(err) => warn!("Error when updating Eth1 follower: {}", err),
+			}
+
+			thread::sleep(poll_interval);
+		}
+	});
+
+	follower
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use beacon::MinimalConfig;
+	use beacon::primitives::{H256, ValidatorId};
+	use beacon::types::DepositData;
+	use beacon::utils::is_valid_merkle_branch;
+	use bm_le::tree_root;
+	use crate::mock::{MockEth1, MOCK_DEPOSIT_CONTRACT};
+
+	fn deposit_data(i: u64) -> DepositData {
+		DepositData {
+			pubkey: ValidatorId::from_low_u64_le(i),
+			withdrawal_credentials: H256::from_low_u64_le(i),
+			amount: 32000000000,
+			signature: Default::default(),
+		}
+	}
+
+	#[test]
+	fn follows_mock_deposits() {
+		let mock = MockEth1::start(&"127.0.0.1:0".parse().unwrap()).unwrap();
+		let mut follower = Eth1Follower::<MinimalConfig>::new(Eth1FollowerConfig {
+			endpoint: mock.endpoint(),
+			deposit_contract: MOCK_DEPOSIT_CONTRACT.to_string(),
+			deploy_block: 0,
+			poll_interval: Duration::from_secs(1),
+		});
+
+		for i in 0..3 {
+			mock.deposit(deposit_data(i));
+		}
+		mock.mine(1);
+		for i in 3..5 {
+			mock.deposit(deposit_data(i));
+		}
+		mock.mine(1 + 2 * MinimalConfig::eth1_follow_distance());
+		follower.update().unwrap();
+
+		let eth1_data = follower.eth1_data(MinimalConfig::eth1_follow_distance()).unwrap();
+		assert_eq!(eth1_data.deposit_count, 5);
+		assert_eq!(eth1_data.block_hash, mock.block_hash(
+			mock.block_number() - MinimalConfig::eth1_follow_distance()
+		).unwrap());
+
+		let mut state = BeaconState::<MinimalConfig>::default();
+		assert_eq!(follower.eth1_vote(&state), Some(eth1_data.clone()));
+
+		state.eth1_data = eth1_data.clone();
+		state.eth1_deposit_index = 1;
+		let deposits = follower.deposits(&state).unwrap();
+		assert_eq!(deposits.len(), 4);
+		for (i, deposit) in deposits.iter().enumerate() {
+			assert_eq!(deposit.data, deposit_data(i as u64 + 1));
+			assert!(is_valid_merkle_branch::<MinimalConfig>(
+				tree_root::<<MinimalConfig as Config>::Digest, _>(&deposit.data),
+				&deposit.proof,
+				beacon::consts::DEPOSIT_CONTRACT_TREE_DEPTH + 1,
+				i as u64 + 1,
+				eth1_data.deposit_root,
+			));
+		}
+
+		mock.close();
+	}
+}
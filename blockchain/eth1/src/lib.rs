@@ -0,0 +1,92 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Parity Shasper.
+
+// Parity Shasper is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+
+// Parity Shasper is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more
+// details.
+
+// You should have received a copy of the GNU General Public License along with
+// Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
+//! Eth1 deposit contract follower.
+//!
+//! The follower polls an Eth1 JSON-RPC endpoint for blocks and deposit
+//! contract logs, keeps a deposit cache, and provides eth1 votes and
+//! deposits for block production.
+
+mod rpc;
+mod deposit;
+mod cache;
+mod follower;
+pub mod mock;
+
+pub use crate::rpc::{Eth1Client, Eth1Block};
+pub use crate::deposit::{DepositLog, DEPOSIT_EVENT_TOPIC};
+pub use crate::cache::DepositCache;
+pub use crate::follower::{Eth1Follower, Eth1FollowerConfig, spawn_follower};
+
+#[derive(Debug)]
+pub enum Error {
+	/// HTTP transport error.
+	Http(reqwest::Error),
+	/// JSON error.
+	Json(serde_json::Error),
+	/// The endpoint returned a JSON-RPC error.
+	Rpc(String),
+	/// The endpoint returned a malformed response.
+	InvalidResponse,
+	/// A deposit log cannot be decoded.
+	InvalidDepositLog,
+	/// A deposit log does not follow the last known deposit.
+	NonSequentialDeposit { expected: u64, got: u64 },
+	/// Requested deposits are not yet known to the cache.
+	MissingDeposits,
+	/// Deposit root in the state does not match the cache.
+	DepositRootMismatch,
+}
+
+impl std::fmt::Display for Error {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "{:?}", self)
+	}
+}
+
+impl std::error::Error for Error { }
+
+impl From<reqwest::Error> for Error {
+	fn from(error: reqwest::Error) -> Error {
+		Error::Http(error)
+	}
+}
+
+impl From<serde_json::Error> for Error {
+	fn from(error: serde_json::Error) -> Error {
+		Error::Json(error)
+	}
+}
+
+/// Parse a JSON-RPC hex quantity.
+fn parse_quantity(value: &str) -> Result<u64, Error> {
+	let value = value.trim_start_matches("0x");
+	u64::from_str_radix(value, 16).map_err(|_| Error::InvalidResponse)
+}
+
+/// Format a JSON-RPC hex quantity.
+fn format_quantity(value: u64) -> String {
+	format!("0x{:x}", value)
+}
+
+/// Parse JSON-RPC hex data.
+fn parse_data(value: &str) -> Result<Vec<u8>, Error> {
+	hex::decode(value.trim_start_matches("0x")).map_err(|_| Error::InvalidResponse)
+}
+
+/// Format JSON-RPC hex data.
+fn format_data(value: &[u8]) -> String {
+	format!("0x{}", hex::encode(value))
+}
@@ -0,0 +1,164 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Parity Shasper.
+
+// Parity Shasper is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+
+// Parity Shasper is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more
+// details.
+
+// You should have received a copy of the GNU General Public License along with
+// Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
+//! In-process mock Eth1 JSON-RPC node, for testing the follower offline.
+
+use beacon::primitives::H256;
+use beacon::types::DepositData;
+use jsonrpc_core::{IoHandler, Params, Value, Error as RpcError, Result as RpcResult};
+use jsonrpc_http_server::{Server, ServerBuilder};
+use serde_json::json;
+use sha2::{Sha256, Digest};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use crate::{DepositLog, DEPOSIT_EVENT_TOPIC, parse_quantity, format_quantity, format_data};
+
+/// Address the mock node reports deposit logs for.
+pub const MOCK_DEPOSIT_CONTRACT: &str = "0x1111111111111111111111111111111111111111";
+
+#[derive(Default)]
+struct MockChain {
+	blocks: Vec<H256>,
+	logs: Vec<DepositLog>,
+	pending: Vec<DepositData>,
+}
+
+impl MockChain {
+	fn mine(&mut self) {
+		let number = self.blocks.len() as u64;
+		self.blocks.push(H256::from_slice(&Sha256::digest(&number.to_le_bytes())[..]));
+
+		for data in self.pending.drain(..) {
+			let index = self.logs.len() as u64;
+			self.logs.push(DepositLog { block_number: number, index, data });
+		}
+	}
+
+	fn latest(&self) -> u64 {
+		self.blocks.len() as u64 - 1
+	}
+
+	fn block_number(&self, tag: &str) -> RpcResult<u64> {
+		match tag {
+			"latest" | "pending" => Ok(self.latest()),
+			"earliest" => Ok(0),
+			tag => parse_quantity(tag).map_err(|_| RpcError::invalid_params("Invalid block number")),
+		}
+	}
+}
+
+/// Mock Eth1 node serving `eth_blockNumber`, `eth_getBlockByNumber` and
+/// `eth_getLogs` for a single deposit contract.
+pub struct MockEth1 {
+	chain: Arc<Mutex<MockChain>>,
+	server: Server,
+}
+
+impl MockEth1 {
+	/// Start the mock node with only a genesis block.
+	pub fn start(address: &SocketAddr) -> std::io::Result<Self> {
+		let chain = Arc::new(Mutex::new(MockChain::default()));
+		chain.lock().expect("Mock chain lock poisoned").mine();
+
+		let mut io = IoHandler::new();
+
+		let c = chain.clone();
+		io.add_method("eth_blockNumber", move |_: Params| -> RpcResult<Value> {
+			let chain = c.lock().expect("Mock chain lock poisoned");
+			Ok(Value::String(format_quantity(chain.latest())))
+		});
+
+		let c = chain.clone();
+		io.add_method("eth_getBlockByNumber", move |params: Params| -> RpcResult<Value> {
+			let (tag, _full) = params.parse::<(String, bool)>()?;
+			let chain = c.lock().expect("Mock chain lock poisoned");
+			let number = chain.block_number(&tag)?;
+
+			Ok(match chain.blocks.get(number as usize) {
+				Some(hash) => json!({
+					"number": format_quantity(number),
+					"hash": format_data(hash.as_ref()),
+					"parentHash": format_data(
+						chain.blocks.get((number as usize).wrapping_sub(1))
+							.cloned().unwrap_or_default().as_ref()
+					),
+				}),
+				None => Value::Null,
+			})
+		});
+
+		let c = chain.clone();
+		io.add_method("eth_getLogs", move |params: Params| -> RpcResult<Value> {
+			let (filter,) = params.parse::<(Value,)>()?;
+			let chain = c.lock().expect("Mock chain lock poisoned");
+			let from = chain.block_number(filter["fromBlock"].as_str().unwrap_or("earliest"))?;
+			let to = chain.block_number(filter["toBlock"].as_str().unwrap_or("latest"))?;
+
+			let address = filter["address"].as_str().unwrap_or(MOCK_DEPOSIT_CONTRACT);
+			if address.to_lowercase() != MOCK_DEPOSIT_CONTRACT {
+				return Ok(Value::Array(Vec::new()))
+			}
+
+			Ok(Value::Array(chain.logs.iter()
+				.filter(|log| log.block_number >= from && log.block_number <= to)
+				.map(|log| json!({
+					"address": MOCK_DEPOSIT_CONTRACT,
+					"blockNumber": format_quantity(log.block_number),
+					"blockHash": format_data(chain.blocks[log.block_number as usize].as_ref()),
+					"logIndex": format_quantity(log.index),
+					"topics": [DEPOSIT_EVENT_TOPIC],
+					"data": format_data(&log.to_event_data()),
+				}))
+				.collect()))
+		});
+
+		let server = ServerBuilder::new(io).start_http(address)?;
+
+		Ok(Self { chain, server })
+	}
+
+	/// HTTP endpoint of the mock node.
+	pub fn endpoint(&self) -> String {
+		format!("http://{}", self.server.address())
+	}
+
+	/// Submit a deposit, to be included in the next mined block.
+	pub fn deposit(&self, data: DepositData) {
+		self.chain.lock().expect("Mock chain lock poisoned").pending.push(data);
+	}
+
+	/// Mine `count` new blocks.
+	pub fn mine(&self, count: u64) {
+		let mut chain = self.chain.lock().expect("Mock chain lock poisoned");
+		for _ in 0..count {
+			chain.mine();
+		}
+	}
+
+	/// Number of the latest block.
+	pub fn block_number(&self) -> u64 {
+		self.chain.lock().expect("Mock chain lock poisoned").latest()
+	}
+
+	/// Hash of the block at the given number.
+	pub fn block_hash(&self, number: u64) -> Option<H256> {
+		self.chain.lock().expect("Mock chain lock poisoned").blocks.get(number as usize).cloned()
+	}
+
+	/// Stop the mock node.
+	pub fn close(self) {
+		self.server.close();
+	}
+}
@@ -0,0 +1,111 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Parity Shasper.
+
+// Parity Shasper is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+
+// Parity Shasper is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more
+// details.
+
+// You should have received a copy of the GNU General Public License along with
+// Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
+use beacon::primitives::H256;
+use serde_json::{Value, json};
+use crate::{Error, DepositLog, DEPOSIT_EVENT_TOPIC, parse_quantity, format_quantity, parse_data};
+
+/// An Eth1 block header, as far as the follower is concerned.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Eth1Block {
+	/// Block hash.
+	pub hash: H256,
+	/// Block number.
+	pub number: u64,
+}
+
+/// Minimal blocking Eth1 JSON-RPC client.
+pub struct Eth1Client {
+	client: reqwest::Client,
+	endpoint: String,
+}
+
+impl Eth1Client {
+	pub fn new(endpoint: &str) -> Self {
+		Self {
+			client: reqwest::Client::new(),
+			endpoint: endpoint.to_string(),
+		}
+	}
+
+	fn call(&self, method: &str, params: Value) -> Result<Value, Error> {
+		let request = json!({
+			"jsonrpc": "2.0",
+			"id": 1,
+			"method": method,
+			"params": params,
+		});
+		let response: Value = self.client.post(&self.endpoint)
+			.json(&request)
+			.send()?
+			.json()?;
+
+		if let Some(error) = response.get("error") {
+			return Err(Error::Rpc(error.to_string()))
+		}
+		response.get("result").cloned().ok_or(Error::InvalidResponse)
+	}
+
+	/// Number of the latest Eth1 block.
+	pub fn block_number(&self) -> Result<u64, Error> {
+		let result = self.call("eth_blockNumber", json!([]))?;
+		parse_quantity(result.as_str().ok_or(Error::InvalidResponse)?)
+	}
+
+	/// Eth1 block at the given number, if it exists.
+	pub fn block_by_number(&self, number: u64) -> Result<Option<Eth1Block>, Error> {
+		let result = self.call(
+			"eth_getBlockByNumber", json!([format_quantity(number), false])
+		)?;
+		if result.is_null() {
+			return Ok(None)
+		}
+
+		let hash = parse_data(result["hash"].as_str().ok_or(Error::InvalidResponse)?)?;
+		let number = parse_quantity(result["number"].as_str().ok_or(Error::InvalidResponse)?)?;
+		if hash.len() != 32 {
+			return Err(Error::InvalidResponse)
+		}
+
+		Ok(Some(Eth1Block { hash: H256::from_slice(&hash), number }))
+	}
+
+	/// Deposit logs of the deposit contract between `from` and `to`,
+	/// inclusive.
+	pub fn deposit_logs(
+		&self,
+		deposit_contract: &str,
+		from: u64,
+		to: u64,
+	) -> Result<Vec<DepositLog>, Error> {
+		let result = self.call("eth_getLogs", json!([{
+			"address": deposit_contract,
+			"topics": [DEPOSIT_EVENT_TOPIC],
+			"fromBlock": format_quantity(from),
+			"toBlock": format_quantity(to),
+		}]))?;
+
+		result.as_array().ok_or(Error::InvalidResponse)?
+			.iter()
+			.map(|log| {
+				let block_number = parse_quantity(
+					log["blockNumber"].as_str().ok_or(Error::InvalidResponse)?
+				)?;
+				let data = parse_data(log["data"].as_str().ok_or(Error::InvalidResponse)?)?;
+				DepositLog::from_event_data(block_number, &data)
+			})
+			.collect()
+	}
+}
@@ -14,7 +14,9 @@
 // You should have received a copy of the GNU General Public License along with
 // Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
 use std::collections::HashMap;
+use std::sync::mpsc;
 use core::hash::Hash;
+use futures::channel::mpsc as async_mpsc;
 use core::mem;
 use blockchain::{Block, Auxiliary, BlockExecutor, AsExternalities};
 use blockchain::import::{BlockImporter, RawImporter, ImportAction};
@@ -172,6 +174,23 @@ impl std::fmt::Display for Error {
 
 impl std::error::Error for Error { }
 
+/// Change committed, or block refused, by the importer. Checkpoints that
+/// are not yet set are reported as the genesis block.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ImportNotification<I> {
+	/// Block was imported, with the checkpoints of its post state.
+	BlockImported { id: I, depth: usize, justified: I, finalized: I },
+	/// Head was changed from `old` to `new`, reverting the last
+	/// `reorg_depth` blocks of the old chain.
+	NewHead { old: I, new: I, reorg_depth: usize },
+	/// Justified checkpoint was changed by the new `head`.
+	JustifiedChanged { old: I, new: I, head: I },
+	/// Finalized checkpoint was changed by the new `head`.
+	FinalizedChanged { old: I, new: I, head: I },
+	/// Block was rejected.
+	BlockRejected { id: I, reason: String },
+}
+
 pub struct ArchiveGhostImporter<E: BlockExecutor, Ba: Store<Block=E::Block>> where
 	E: JustifiableExecutor,
 	Ba::Auxiliary: Auxiliary<E::Block>
@@ -179,6 +198,11 @@ pub struct ArchiveGhostImporter<E: BlockExecutor, Ba: Store<Block=E::Block>> whe
 	ghost: ArchiveGhost<Ba, E::ValidatorIndex>,
 	import_lock: ImportLock,
 	executor: E,
+	subscribers: Vec<mpsc::Sender<ImportNotification<<E::Block as Block>::Identifier>>>,
+	async_subscribers: Vec<async_mpsc::UnboundedSender<ImportNotification<<E::Block as Block>::Identifier>>>,
+	head_checkpoints: Option<(<E::Block as Block>::Identifier, <E::Block as Block>::Identifier)>,
+	vote_sender: mpsc::Sender<(E::ValidatorIndex, <E::Block as Block>::Identifier)>,
+	pending_votes: mpsc::Receiver<(E::ValidatorIndex, <E::Block as Block>::Identifier)>,
 }
 
 impl<E: BlockExecutor, Ba: SharedCommittable + Store<Block=E::Block>> ArchiveGhostImporter<E, Ba> where
@@ -187,10 +211,130 @@ impl<E: BlockExecutor, Ba: SharedCommittable + Store<Block=E::Block>> ArchiveGho
 	Ba::Auxiliary: Auxiliary<E::Block>
 {
 	pub fn new(executor: E, backend: Ba, import_lock: ImportLock) -> Self {
+		let (vote_sender, pending_votes) = mpsc::channel();
 		Self {
 			executor, import_lock,
 			ghost: ArchiveGhost::new(backend),
+			subscribers: Vec::new(),
+			async_subscribers: Vec::new(),
+			head_checkpoints: None,
+			vote_sender, pending_votes,
+		}
+	}
+
+	/// Sender of votes seen outside of blocks, such as attestations received
+	/// on gossip. They are taken into fork choice at the next import.
+	pub fn vote_sender(
+		&self
+	) -> mpsc::Sender<(E::ValidatorIndex, <E::Block as Block>::Identifier)> {
+		self.vote_sender.clone()
+	}
+
+	/// Subscribe to notifications of imports, head and checkpoint changes,
+	/// and rejected blocks.
+	pub fn subscribe(
+		&mut self
+	) -> mpsc::Receiver<ImportNotification<<E::Block as Block>::Identifier>> {
+		let (sender, receiver) = mpsc::channel();
+		self.subscribers.push(sender);
+		receiver
+	}
+
+	/// Subscribe to the same notifications as `subscribe`, as a stream that
+	/// can be polled from within a future.
+	pub fn subscribe_async(
+		&mut self
+	) -> async_mpsc::UnboundedReceiver<ImportNotification<<E::Block as Block>::Identifier>> {
+		let (sender, receiver) = async_mpsc::unbounded();
+		self.async_subscribers.push(sender);
+		receiver
+	}
+}
+
+impl<E: BlockExecutor, Ba: Store<Block=E::Block>> ArchiveGhostImporter<E, Ba> where
+	E: JustifiableExecutor,
+	Ba: ChainQuery + AncestorQuery,
+	Ba::Auxiliary: Auxiliary<E::Block>,
+	Ba::State: AsExternalities<E::Externalities>,
+{
+	fn notify(&mut self, notification: ImportNotification<<E::Block as Block>::Identifier>) {
+		self.subscribers.retain(|subscriber| subscriber.send(notification.clone()).is_ok());
+		self.async_subscribers.retain(|subscriber| {
+			subscriber.unbounded_send(notification.clone()).is_ok()
+		});
+	}
+
+	/// Justified and finalized block of a state.
+	fn checkpoints_of(
+		&self,
+		externalities: &mut E::Externalities,
+	) -> Result<(<E::Block as Block>::Identifier, <E::Block as Block>::Identifier), Error> {
+		let justified = self.executor.justified_block_id(externalities)
+			.map_err(|e| Error::Executor(Box::new(e)))?
+			.unwrap_or_else(|| self.ghost.backend.genesis());
+		let finalized = self.executor.finalized_block_id(externalities)
+			.map_err(|e| Error::Executor(Box::new(e)))?
+			.unwrap_or_else(|| self.ghost.backend.genesis());
+		Ok((justified, finalized))
+	}
+
+	/// Justified and finalized block of the post state of `id`.
+	fn checkpoints_at(
+		&self,
+		id: &<E::Block as Block>::Identifier,
+	) -> Result<(<E::Block as Block>::Identifier, <E::Block as Block>::Identifier), Error> {
+		let mut state = self.ghost.backend.state_at(id)
+			.map_err(|e| Error::Backend(Box::new(e)))?;
+		self.checkpoints_of(state.as_externalities())
+	}
+
+	/// Number of blocks of `old` that are not ancestors of `new`.
+	fn reorg_depth(
+		&self,
+		old: &<E::Block as Block>::Identifier,
+		new: &<E::Block as Block>::Identifier,
+	) -> Result<usize, Ba::Error> {
+		let old_depth = self.ghost.backend.depth_at(old)?;
+		let mut depth = core::cmp::min(old_depth, self.ghost.backend.depth_at(new)?);
+		while self.ghost.backend.ancestor_at(old, depth)? !=
+			self.ghost.backend.ancestor_at(new, depth)?
+		{
+			depth -= 1;
 		}
+		Ok(old_depth - depth)
+	}
+
+	fn notify_new_head(
+		&mut self,
+		old: <E::Block as Block>::Identifier,
+		new: <E::Block as Block>::Identifier,
+		new_checkpoints: Option<(<E::Block as Block>::Identifier, <E::Block as Block>::Identifier)>,
+	) -> Result<(), Error> {
+		let reorg_depth = self.reorg_depth(&old, &new)
+			.map_err(|e| Error::Backend(Box::new(e)))?;
+		let (old_justified, old_finalized) = match self.head_checkpoints.take() {
+			Some(checkpoints) => checkpoints,
+			None => self.checkpoints_at(&old)?,
+		};
+		let (new_justified, new_finalized) = match new_checkpoints {
+			Some(checkpoints) => checkpoints,
+			None => self.checkpoints_at(&new)?,
+		};
+		self.head_checkpoints = Some((new_justified.clone(), new_finalized.clone()));
+
+		self.notify(ImportNotification::NewHead { old, new: new.clone(), reorg_depth });
+		if old_justified != new_justified {
+			self.notify(ImportNotification::JustifiedChanged {
+				old: old_justified, new: new_justified, head: new.clone(),
+			});
+		}
+		if old_finalized != new_finalized {
+			self.notify(ImportNotification::FinalizedChanged {
+				old: old_finalized, new: new_finalized, head: new,
+			});
+		}
+
+		Ok(())
 	}
 }
 
@@ -205,15 +349,23 @@ impl<E: BlockExecutor, Ba: Store<Block=E::Block>> BlockImporter for ArchiveGhost
 	type Error = Error;
 
 	fn import_block(&mut self, block: Ba::Block) -> Result<(), Self::Error> {
-		let mut state = self.ghost.backend
-			.state_at(
-				&block.parent_id().ok_or(Error::IsGenesis)?
-			)
-			.map_err(|e| Error::Backend(Box::new(e)))?;
-		self.executor.execute_block(&block, state.as_externalities())
-			.map_err(|e| Error::Executor(Box::new(e)))?;
+		let id = block.id();
+		let result = (|| -> Result<(), Error> {
+			let mut state = self.ghost.backend
+				.state_at(
+					&block.parent_id().ok_or(Error::IsGenesis)?
+				)
+				.map_err(|e| Error::Backend(Box::new(e)))?;
+			self.executor.execute_block(&block, state.as_externalities())
+				.map_err(|e| Error::Executor(Box::new(e)))?;
+
+			self.import_raw(ImportOperation { block, state })
+		})();
 
-		self.import_raw(ImportOperation { block, state })
+		if let Err(err) = &result {
+			self.notify(ImportNotification::BlockRejected { id, reason: err.to_string() });
+		}
+		result
 	}
 }
 
@@ -231,18 +383,13 @@ impl<E: BlockExecutor, Ba: Store<Block=E::Block>> RawImporter for ArchiveGhostIm
 		&mut self,
 		mut raw: ImportOperation<Ba::Block, Ba::State>
 	) -> Result<(), Self::Error> {
-		let (justified_active_validators, justified_block_id, votes) = {
+		let block_id = raw.block.id();
+		let (justified_active_validators, checkpoints, votes) = {
 			let externalities = raw.state.as_externalities();
 			let justified_active_validators = self.executor
 				.justified_active_validators(externalities)
 				.map_err(|e| Error::Executor(Box::new(e)))?;
-			let justified_block_id = match self.executor
-				.justified_block_id(externalities)
-				.map_err(|e| Error::Executor(Box::new(e)))?
-			{
-				Some(value) => value,
-				None => self.ghost.backend.genesis(),
-			};
+			let checkpoints = self.checkpoints_of(externalities)?;
 			let votes = self.executor
 				.votes(&raw. block, externalities)
 				.map_err(|e| Error::Executor(Box::new(e)))?;
@@ -253,14 +400,26 @@ impl<E: BlockExecutor, Ba: Store<Block=E::Block>> RawImporter for ArchiveGhostIm
 			importer.import_raw(raw);
 			importer.commit().map_err(|e| Error::Backend(Box::new(e)))?;
 
-			(justified_active_validators, justified_block_id, votes)
+			(justified_active_validators, checkpoints, votes)
 		};
 
+		let depth = self.ghost.backend.depth_at(&block_id)
+			.map_err(|e| Error::Backend(Box::new(e)))?;
+		self.notify(ImportNotification::BlockImported {
+			id: block_id.clone(),
+			depth,
+			justified: checkpoints.0.clone(),
+			finalized: checkpoints.1.clone(),
+		});
+
 		for (k, v) in votes {
 			self.ghost.update_overlay(k, v);
 		}
+		while let Ok((k, v)) = self.pending_votes.try_recv() {
+			self.ghost.update_overlay(k, v);
+		}
 		self.ghost.update_active(&justified_active_validators);
-		let new_head = match self.ghost.head(&justified_block_id) {
+		let new_head = match self.ghost.head(&checkpoints.0) {
 			Ok(value) => value,
 			Err(e) => {
 				self.ghost.reset_overlay();
@@ -268,13 +427,22 @@ impl<E: BlockExecutor, Ba: Store<Block=E::Block>> RawImporter for ArchiveGhostIm
 			},
 		};
 
+		let old_head = self.ghost.backend.head();
 		let mut importer = ImportAction::new(
 			&self.ghost.backend, self.import_lock.lock()
 		);
-		importer.set_head(new_head);
+		importer.set_head(new_head.clone());
 
 		match importer.commit() {
-			Ok(()) => { self.ghost.commit_overlay(); },
+			Ok(()) => {
+				self.ghost.commit_overlay();
+				if old_head != new_head {
+					let new_checkpoints = if new_head == block_id { Some(checkpoints) } else { None };
+					if let Err(err) = self.notify_new_head(old_head, new_head, new_checkpoints) {
+						warn!("Unable to notify head change: {}", err);
+					}
+				}
+			},
 			Err(_) => { self.ghost.reset_overlay(); },
 		}
 
@@ -29,6 +29,10 @@ pub trait JustifiableExecutor: BlockExecutor {
 		&self,
 		state: &mut Self::Externalities, // FIXME: replace `&mut` with `&`.
 	) -> Result<Option<<Self::Block as Block>::Identifier>, Self::Error>;
+	fn finalized_block_id(
+		&self,
+		state: &mut Self::Externalities, // FIXME: replace `&mut` with `&`.
+	) -> Result<Option<<Self::Block as Block>::Identifier>, Self::Error>;
 	fn votes(
 		&self,
 		block: &Self::Block,
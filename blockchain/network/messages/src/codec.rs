@@ -2,10 +2,11 @@ use core::marker::PhantomData;
 use tokio::codec::{Encoder, Decoder};
 use bytes::{BufMut, Bytes, BytesMut};
 use beacon::Config;
+use beacon::types::BeaconBlock;
 use ssz::{Encode, Decode};
 use log::*;
 use unsigned_varint::codec::UviBytes;
-use crate::{RPCType, RPCRequest, RPCResponse};
+use crate::{RPCType, RPCRequest, RPCResponse, MAX_REQUEST_BLOCKS};
 
 pub struct InboundCodec<C: Config> {
 	typ: RPCType,
@@ -27,19 +28,26 @@ impl<C: Config> Encoder for InboundCodec<C> {
 		trace!("inbound encode item: {:?}", item);
 
 		match item {
-			RPCResponse::Unknown(id, _) => dst.put(id),
-			_ => dst.put(0u8),
+			RPCResponse::Hello(item) => {
+				dst.reserve(1);
+				dst.put(0u8);
+				self.uvi.encode(Bytes::from(item.encode()), dst)?;
+			},
+			RPCResponse::BeaconBlocks(blocks) | RPCResponse::RecentBeaconBlocks(blocks) => {
+				// Blocks are sent as a stream of chunks, one block per chunk.
+				for block in blocks {
+					dst.reserve(1);
+					dst.put(0u8);
+					self.uvi.encode(Bytes::from(block.encode()), dst)?;
+				}
+			},
+			RPCResponse::Unknown(id, value) => {
+				dst.reserve(1);
+				dst.put(id);
+				self.uvi.encode(Bytes::from(value), dst)?;
+			},
 		}
 
-		let bytes = match item {
-			RPCResponse::Hello(item) => item.encode(),
-			RPCResponse::BeaconBlocks(item) => item.encode(),
-			RPCResponse::RecentBeaconBlocks(item) => item.encode(),
-			RPCResponse::Unknown(_, value) => value,
-		};
-
-		self.uvi.encode(Bytes::from(bytes), dst)?;
-
 		Ok(())
 	}
 }
@@ -67,12 +75,39 @@ impl<C: Config> Decoder for InboundCodec<C> {
 pub struct OutboundCodec<C: Config> {
 	typ: RPCType,
 	uvi: UviBytes,
-	_marker: PhantomData<C>,
+	code: Option<u8>,
+	blocks: Vec<BeaconBlock<C>>,
+	finished: bool,
 }
 
 impl<C: Config> OutboundCodec<C> {
 	pub fn new(typ: RPCType) -> Self {
-		Self { typ, uvi: UviBytes::default(), _marker: PhantomData }
+		Self { typ, uvi: UviBytes::default(), code: None, blocks: Vec::new(), finished: false }
+	}
+
+	/// Decode the result code and payload of a response chunk, keeping the
+	/// code until the payload is complete.
+	fn decode_chunk(&mut self, src: &mut BytesMut) -> Result<Option<(u8, BytesMut)>, ssz::Error> {
+		if self.code.is_none() {
+			if src.is_empty() {
+				return Ok(None)
+			}
+			self.code = Some(src.split_to(1)[0]);
+		}
+
+		match self.uvi.decode(src)? {
+			Some(bytes) => Ok(self.code.take().map(|code| (code, bytes))),
+			None => Ok(None),
+		}
+	}
+
+	fn blocks_response(&mut self) -> RPCResponse<C> {
+		self.finished = true;
+		let blocks = core::mem::replace(&mut self.blocks, Vec::new());
+		match self.typ {
+			RPCType::RecentBeaconBlocks => RPCResponse::RecentBeaconBlocks(blocks),
+			_ => RPCResponse::BeaconBlocks(blocks),
+		}
 	}
 }
 
@@ -104,79 +139,130 @@ impl<C: Config> Decoder for OutboundCodec<C> {
 	fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
 		trace!("outbound decode src len: {}", src.len());
 
-		if src.is_empty() {
+		if self.finished {
+			src.clear();
 			return Ok(None)
 		}
 
-		Ok(Some(match self.typ {
-			RPCType::Hello => {
-				let code = src.split_to(1)[0];
-				let bytes = match self.uvi.decode(src)? {
-					Some(bytes) => bytes,
-					None => return Ok(None),
-				};
-
-				if code == 0 {
-					RPCResponse::Hello(Decode::decode(&bytes[..])?)
-				} else {
-					RPCResponse::Unknown(code, bytes.to_vec())
+		match self.typ {
+			RPCType::Hello => Ok(match self.decode_chunk(src)? {
+				Some((0, bytes)) => Some(RPCResponse::Hello(Decode::decode(&bytes[..])?)),
+				Some((code, bytes)) => Some(RPCResponse::Unknown(code, bytes.to_vec())),
+				None => None,
+			}),
+			RPCType::Goodbye => Ok(self.decode_chunk(src)?
+				.map(|(code, bytes)| RPCResponse::Unknown(code, bytes.to_vec()))),
+			// Blocks are streamed as chunks until the end of the stream. They
+			// are decoded as they arrive, and returned together at the end of
+			// the stream, or once the maximum number of blocks is reached.
+			RPCType::BeaconBlocks | RPCType::RecentBeaconBlocks => {
+				while let Some((code, bytes)) = self.decode_chunk(src)? {
+					if code != 0 {
+						return Err(ssz::Error::Other("error response chunk"))
+					}
+
+					self.blocks.push(Decode::decode(&bytes[..])?);
+					if self.blocks.len() as u64 >= MAX_REQUEST_BLOCKS {
+						src.clear();
+						return Ok(Some(self.blocks_response()))
+					}
 				}
+				Ok(None)
 			},
-			RPCType::Goodbye => {
-				let code = src.split_to(1)[0];
-				let bytes = match self.uvi.decode(src)? {
-					Some(bytes) => bytes,
-					None => return Ok(None),
-				};
-
-				RPCResponse::Unknown(code, bytes.to_vec())
-			},
-			RPCType::BeaconBlocks => {
-				let mut result = Vec::new();
+		}
+	}
 
-				loop {
-					if src.is_empty() {
-						break
-					}
+	fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+		match self.typ {
+			RPCType::BeaconBlocks | RPCType::RecentBeaconBlocks => (),
+			_ => return self.decode(src),
+		}
 
-					let code = src.split_to(1)[0];
-					if code != 0 {
-						break
-					}
+		if let Some(response) = self.decode(src)? {
+			return Ok(Some(response))
+		}
+		if self.finished {
+			return Ok(None)
+		}
+		if self.code.is_some() || !src.is_empty() {
+			return Err(ssz::Error::Other("incomplete chunk"))
+		}
 
-					let bytes = match self.uvi.decode(src)? {
-						Some(bytes) => bytes,
-						None => break,
-					};
+		Ok(Some(self.blocks_response()))
+	}
+}
 
-					result.push(Decode::decode(&bytes[..])?);
-				}
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use beacon::MinimalConfig;
 
-				RPCResponse::BeaconBlocks(result)
-			},
-			RPCType::RecentBeaconBlocks => {
-				let mut result = Vec::new();
+	fn blocks(count: u64) -> Vec<BeaconBlock<MinimalConfig>> {
+		(0..count).map(|slot| BeaconBlock { slot, ..Default::default() }).collect()
+	}
 
-				loop {
-					if src.is_empty() {
-						break
-					}
+	fn encode(blocks: Vec<BeaconBlock<MinimalConfig>>) -> BytesMut {
+		let mut dst = BytesMut::new();
+		InboundCodec::<MinimalConfig>::new(RPCType::BeaconBlocks)
+			.encode(RPCResponse::BeaconBlocks(blocks), &mut dst)
+			.unwrap();
+		dst
+	}
 
-					let code = src.split_to(1)[0];
-					if code != 0 {
-						break
-					}
+	fn decoded_slots(response: Option<RPCResponse<MinimalConfig>>) -> Vec<u64> {
+		match response {
+			Some(RPCResponse::BeaconBlocks(blocks)) => blocks.iter().map(|b| b.slot).collect(),
+			response => panic!("Unexpected response {:?}", response),
+		}
+	}
 
-					let bytes = match self.uvi.decode(src)? {
-						Some(bytes) => bytes,
-						None => break,
-					};
+	#[test]
+	fn blocks_round_trip_in_chunks() {
+		let mut src = encode(blocks(3));
+		let rest = src.split_off(src.len() / 2);
+		let mut codec = OutboundCodec::<MinimalConfig>::new(RPCType::BeaconBlocks);
+
+		assert!(codec.decode(&mut src).unwrap().is_none());
+		src.extend_from_slice(&rest);
+		assert!(codec.decode(&mut src).unwrap().is_none());
+		assert_eq!(decoded_slots(codec.decode_eof(&mut src).unwrap()), vec![0, 1, 2]);
+		assert!(codec.decode_eof(&mut src).unwrap().is_none());
+	}
 
-					result.push(Decode::decode(&bytes[..])?);
-				}
+	#[test]
+	fn empty_blocks_response_is_decoded() {
+		let mut src = encode(Vec::new());
+		let mut codec = OutboundCodec::<MinimalConfig>::new(RPCType::BeaconBlocks);
+		assert_eq!(decoded_slots(codec.decode_eof(&mut src).unwrap()), Vec::<u64>::new());
+	}
 
-				RPCResponse::RecentBeaconBlocks(result)
-			},
-		}))
+	#[test]
+	fn blocks_are_decoded_up_to_maximum() {
+		let mut src = encode(blocks(MAX_REQUEST_BLOCKS + 1));
+		let mut codec = OutboundCodec::<MinimalConfig>::new(RPCType::BeaconBlocks);
+		let slots = decoded_slots(codec.decode(&mut src).unwrap());
+		assert_eq!(slots.len() as u64, MAX_REQUEST_BLOCKS);
+		assert!(codec.decode_eof(&mut src).unwrap().is_none());
+	}
+
+	#[test]
+	fn error_chunk_is_rejected() {
+		let mut src = encode(blocks(1));
+		src.reserve(1);
+		src.put(1u8);
+		UviBytes::<Bytes>::default().encode(Bytes::from(&b"error"[..]), &mut src).unwrap();
+
+		let mut codec = OutboundCodec::<MinimalConfig>::new(RPCType::BeaconBlocks);
+		assert!(codec.decode_eof(&mut src).is_err());
+	}
+
+	#[test]
+	fn incomplete_chunk_is_rejected() {
+		let mut src = encode(blocks(1));
+		let len = src.len();
+		src.truncate(len - 1);
+
+		let mut codec = OutboundCodec::<MinimalConfig>::new(RPCType::BeaconBlocks);
+		assert!(codec.decode_eof(&mut src).is_err());
 	}
 }
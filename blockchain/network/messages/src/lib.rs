@@ -5,10 +5,16 @@ pub use items::{HelloMessage, GoodbyeReason, BeaconBlocksRequest, RecentBeaconBl
 pub use codec::{InboundCodec, OutboundCodec};
 
 use beacon::{
-	Config, types::{BeaconBlock, Attestation, VoluntaryExit, ProposerSlashing, AttesterSlashing},
+	Config, types::{
+		BeaconBlock, Attestation, VoluntaryExit, ProposerSlashing, AttesterSlashing,
+		SignedAggregateAndProof,
+	},
 };
 use libp2p::gossipsub;
 
+/// Maximum number of blocks in a response to a single request.
+pub const MAX_REQUEST_BLOCKS: u64 = 1024;
+
 /// RPC type.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum RPCType {
@@ -86,6 +92,7 @@ pub enum RPCResponse<C: Config> {
 pub enum PubsubType {
 	Block,
 	Attestation,
+	AggregateAndProof,
 	VoluntaryExit,
 	ProposerSlashing,
 	AttesterSlashing,
@@ -96,6 +103,7 @@ impl PubsubType {
 		match topic.as_str() {
 			"/eth2/beacon_block/ssz" => Some(Self::Block),
 			"/eth2/beacon_attestation/ssz" => Some(Self::Attestation),
+			"/eth2/beacon_aggregate_and_proof/ssz" => Some(Self::AggregateAndProof),
 			"/eth2/voluntary_exit/ssz" => Some(Self::VoluntaryExit),
 			"/eth2/proposer_slashing/ssz" => Some(Self::ProposerSlashing),
 			"/eth2/attester_slashing/ssz" => Some(Self::AttesterSlashing),
@@ -107,6 +115,7 @@ impl PubsubType {
 		gossipsub::TopicHash::from_raw(match self {
 			Self::Block => "/eth2/beacon_block/ssz".to_string(),
 			Self::Attestation => "/eth2/beacon_attestation/ssz".to_string(),
+			Self::AggregateAndProof => "/eth2/beacon_aggregate_and_proof/ssz".to_string(),
 			Self::VoluntaryExit => "/eth2/voluntary_exit/ssz".to_string(),
 			Self::ProposerSlashing => "/eth2/proposer_slashing/ssz".to_string(),
 			Self::AttesterSlashing => "/eth2/attester_slashing/ssz".to_string(),
@@ -117,6 +126,7 @@ impl PubsubType {
 		gossipsub::Topic::new(match self {
 			Self::Block => "/eth2/beacon_block/ssz".to_string(),
 			Self::Attestation => "/eth2/beacon_attestation/ssz".to_string(),
+			Self::AggregateAndProof => "/eth2/beacon_aggregate_and_proof/ssz".to_string(),
 			Self::VoluntaryExit => "/eth2/voluntary_exit/ssz".to_string(),
 			Self::ProposerSlashing => "/eth2/proposer_slashing/ssz".to_string(),
 			Self::AttesterSlashing => "/eth2/attester_slashing/ssz".to_string(),
@@ -129,6 +139,7 @@ impl<'a, C: Config> From<&'a PubsubMessage<C>> for PubsubType {
 		match message {
 			PubsubMessage::Block(_) => PubsubType::Block,
 			PubsubMessage::Attestation(_) => PubsubType::Attestation,
+			PubsubMessage::AggregateAndProof(_) => PubsubType::AggregateAndProof,
 			PubsubMessage::VoluntaryExit(_) => PubsubType::VoluntaryExit,
 			PubsubMessage::ProposerSlashing(_) => PubsubType::ProposerSlashing,
 			PubsubMessage::AttesterSlashing(_) => PubsubType::AttesterSlashing,
@@ -144,6 +155,8 @@ pub enum PubsubMessage<C: Config> {
     Block(BeaconBlock<C>),
     /// Gossipsub message providing notification of a new attestation.
     Attestation(Attestation<C>),
+    /// Gossipsub message providing notification of a new aggregated attestation.
+    AggregateAndProof(SignedAggregateAndProof<C>),
     /// Gossipsub message providing notification of a voluntary exit.
     VoluntaryExit(VoluntaryExit),
     /// Gossipsub message providing notification of a new proposer slashing.
@@ -157,6 +170,7 @@ impl<C: Config> PubsubMessage<C> {
 		match self {
 			Self::Block(item) => ssz::Encode::encode(item),
 			Self::Attestation(item) => ssz::Encode::encode(item),
+			Self::AggregateAndProof(item) => ssz::Encode::encode(item),
 			Self::VoluntaryExit(item) => ssz::Encode::encode(item),
 			Self::ProposerSlashing(item) => ssz::Encode::encode(item),
 			Self::AttesterSlashing(item) => ssz::Encode::encode(item),
@@ -167,6 +181,7 @@ impl<C: Config> PubsubMessage<C> {
 		Ok(match typ {
 			PubsubType::Block => Self::Block(ssz::Decode::decode(&mut data)?),
 			PubsubType::Attestation => Self::Attestation(ssz::Decode::decode(&mut data)?),
+			PubsubType::AggregateAndProof => Self::AggregateAndProof(ssz::Decode::decode(&mut data)?),
 			PubsubType::VoluntaryExit => Self::VoluntaryExit(ssz::Decode::decode(&mut data)?),
 			PubsubType::ProposerSlashing => Self::ProposerSlashing(ssz::Decode::decode(&mut data)?),
 			PubsubType::AttesterSlashing => Self::AttesterSlashing(ssz::Decode::decode(&mut data)?),
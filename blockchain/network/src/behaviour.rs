@@ -22,7 +22,7 @@ use futures01::prelude::*;
 use libp2p::{
     core::identity::Keypair,
     discv5::Discv5Event,
-    gossipsub::{Gossipsub, GossipsubEvent, Topic},
+    gossipsub::{Gossipsub, GossipsubEvent, MessageId, Topic},
     identify::{Identify, IdentifyEvent},
     ping::{Ping, PingConfig, PingEvent},
     swarm::{NetworkBehaviourAction, NetworkBehaviourEventProcess},
@@ -100,7 +100,7 @@ impl<C: Config, TSubstream: AsyncRead + AsyncWrite> NetworkBehaviourEventProcess
 {
     fn inject_event(&mut self, event: GossipsubEvent) {
         match event {
-            GossipsubEvent::Message(_, gs_msg) => {
+            GossipsubEvent::Message(propagation_source, id, gs_msg) => {
                 trace!("Received GossipEvent");
 
 				let typ = match gs_msg.topics.iter()
@@ -122,7 +122,7 @@ impl<C: Config, TSubstream: AsyncRead + AsyncWrite> NetworkBehaviourEventProcess
 					},
 				};
 
-                self.events.push(Libp2pEvent::Pubsub(gs_msg.source, msg));
+                self.events.push(Libp2pEvent::Pubsub(propagation_source, id, msg));
             }
             GossipsubEvent::Subscribed { .. } => {}
             GossipsubEvent::Unsubscribed { .. } => {}
@@ -221,6 +221,13 @@ impl<C: Config, TSubstream: AsyncRead + AsyncWrite> Behaviour<C, TSubstream> {
 		self.gossipsub.publish(&typ.gossipsub_topic(), data);
     }
 
+    /// Forwards a received message that passed validation to the other peers.
+    pub fn propagate(&mut self, id: &MessageId, propagation_source: &PeerId) {
+        if !self.gossipsub.propagate_message(id, propagation_source) {
+            trace!("Message {:?} is no longer in the cache, not propagated", id);
+        }
+    }
+
     /// Sends an RPC Request/Response via the RPC protocol.
     pub fn send_rpc(&mut self, peer_id: PeerId, rpc_event: RPCEvent<C>) {
         self.rpc.send_rpc(peer_id, rpc_event);
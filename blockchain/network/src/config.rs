@@ -16,11 +16,19 @@
 // Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
 
 use enr::Enr;
-use libp2p::gossipsub::{GossipsubConfig, GossipsubConfigBuilder};
+use libp2p::gossipsub::{GossipsubConfig, GossipsubConfigBuilder, GossipsubMessage, MessageId};
 use libp2p::Multiaddr;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::time::Duration;
 
+/// Content-based gossipsub message id, the base64 encoded SHA256 hash of the
+/// message data, so that the same message relayed by different peers is only
+/// delivered once.
+pub fn message_id(message: &GossipsubMessage) -> MessageId {
+    MessageId(base64::encode_config(&Sha256::digest(&message.data), base64::STANDARD))
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(default)]
 /// Network configuration for lighthouse.
@@ -67,9 +75,11 @@ impl Default for Config {
             discovery_port: 9000,
             max_peers: 10,
             // Note: The topics by default are sent as plain strings. Hashes are an optional
-            // parameter.
+            // parameter. Received messages are only forwarded once they are validated.
             gs_config: GossipsubConfigBuilder::new()
                 .max_transmit_size(1_048_576)
+                .manual_propagation()
+                .message_id_fn(message_id)
                 .heartbeat_interval(Duration::from_secs(20))
                 .build(),
             boot_nodes: vec![],
@@ -28,7 +28,7 @@ use libp2p::enr::{Enr, EnrBuilder, NodeId};
 use libp2p::multiaddr::Protocol;
 use libp2p::swarm::{NetworkBehaviour, NetworkBehaviourAction, PollParameters, ProtocolsHandler};
 use log::*;
-use std::collections::HashSet;
+use std::collections::HashMap;
 use std::time::{Duration, Instant};
 use tokio::io::{AsyncRead, AsyncWrite};
 use tokio_timer::Delay;
@@ -41,8 +41,8 @@ const INITIAL_SEARCH_DELAY: u64 = 5;
 /// Lighthouse discovery behaviour. This provides peer management and discovery using the Discv5
 /// libp2p protocol.
 pub struct Discovery<TSubstream> {
-    /// The peers currently connected to libp2p streams.
-    connected_peers: HashSet<PeerId>,
+    /// The peers currently connected to libp2p streams, with their endpoints.
+    connected_peers: HashMap<PeerId, ConnectedPoint>,
 
     /// The target number of connected peers on the libp2p interface.
     max_peers: usize,
@@ -87,7 +87,7 @@ impl<TSubstream> Discovery<TSubstream> {
         }
 
         Ok(Self {
-            connected_peers: HashSet::new(),
+            connected_peers: HashMap::new(),
             max_peers: config.max_peers,
             peer_discovery_delay: Delay::new(Instant::now()),
             past_discovery_delay: INITIAL_SEARCH_DELAY,
@@ -117,8 +117,8 @@ impl<TSubstream> Discovery<TSubstream> {
         self.connected_peers.len()
     }
 
-    /// The current number of connected libp2p peers.
-    pub fn connected_peer_set(&self) -> &HashSet<PeerId> {
+    /// The currently connected libp2p peers, with their endpoints.
+    pub fn connected_peer_endpoints(&self) -> &HashMap<PeerId, ConnectedPoint> {
         &self.connected_peers
     }
 
@@ -160,8 +160,8 @@ where
         self.discovery.addresses_of_peer(peer_id)
     }
 
-    fn inject_connected(&mut self, peer_id: PeerId, _endpoint: ConnectedPoint) {
-        self.connected_peers.insert(peer_id);
+    fn inject_connected(&mut self, peer_id: PeerId, endpoint: ConnectedPoint) {
+        self.connected_peers.insert(peer_id, endpoint);
     }
 
     fn inject_disconnected(&mut self, peer_id: &PeerId, _endpoint: ConnectedPoint) {
@@ -170,11 +170,12 @@ where
 
     fn inject_replaced(
         &mut self,
-        _peer_id: PeerId,
+        peer_id: PeerId,
         _closed: ConnectedPoint,
-        _opened: ConnectedPoint,
+        opened: ConnectedPoint,
     ) {
-        // discv5 doesn't implement
+        // Keep the tracked endpoint in sync with the new connection.
+        self.connected_peers.insert(peer_id, opened);
     }
 
     fn inject_node_event(
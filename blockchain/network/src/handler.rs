@@ -2,10 +2,13 @@ use core::marker::PhantomData;
 use blockchain::{Auxiliary, Block as BlockT, AsExternalities};
 use blockchain::backend::{Store, SharedCommittable, ChainQuery, ImportLock};
 use beacon::{Config, types::BeaconBlock, primitives::H256};
-use network_messages::{HelloMessage, BeaconBlocksRequest};
+use network_messages::{HelloMessage, BeaconBlocksRequest, MAX_REQUEST_BLOCKS};
 use shasper_runtime::{StateExternalities, Block};
 use log::*;
 
+/// Number of blocks requested at a time when syncing.
+pub const SYNC_BATCH_SIZE: u64 = 64;
+
 pub struct Handler<C, Ba> {
 	import_lock: ImportLock,
 	backend: Ba,
@@ -42,73 +45,287 @@ impl<C, Ba> Handler<C, Ba> where
 		}
 	}
 
-	pub fn head_request(&self, count: usize) -> BeaconBlocksRequest {
+	/// Request of the blocks following the local head, on the chain of the
+	/// peer with the given status, in batches of at most
+	/// `SYNC_BATCH_SIZE` blocks.
+	pub fn head_request(&self, peer: Option<&HelloMessage>) -> BeaconBlocksRequest {
 		let head_hash = self.backend.head();
 		let head_state = self.backend.state_at(&head_hash).unwrap();
 		let head_slot = head_state.state().slot;
 
 		info!("Chain head: {:?} (slot: {})", head_hash, head_slot);
 
+		let (head_block_root, count) = match peer {
+			Some(peer) => (
+				peer.head_root,
+				peer.head_slot.saturating_sub(head_slot).max(1).min(SYNC_BATCH_SIZE),
+			),
+			None => (head_hash, SYNC_BATCH_SIZE),
+		};
+
 		BeaconBlocksRequest {
-			head_block_root: head_hash,
+			head_block_root,
 			start_slot: head_slot + 1,
-			count: count as u64,
+			count,
 			step: 1
 		}
 	}
 
-	fn blocks_by_depth_no_lock(&self, start_depth: usize, count: usize) -> Vec<BeaconBlock<C>> {
-		let mut ret = Vec::new();
-		for d in start_depth..(start_depth + count) {
-			match self.backend.lookup_canon_depth(d as usize) {
-				Ok(Some(hash)) => {
-					let block = self.backend.block_at(&hash)
-						.expect("Found hash cannot fail");
-					ret.push(block);
-				},
-				_ => break,
-			}
+	/// Canonical block at the given depth.
+	fn canon_block_at_depth(&self, depth: usize) -> Option<Block<C>> {
+		match self.backend.lookup_canon_depth(depth) {
+			Ok(Some(hash)) => self.backend.block_at(&hash).ok(),
+			_ => None,
 		}
-		ret.into_iter().map(Into::into).collect()
 	}
 
-	pub fn blocks_by_depth(&self, start_depth: usize, count: usize) -> Vec<BeaconBlock<C>> {
-		let _ = self.import_lock.lock();
-		self.blocks_by_depth_no_lock(start_depth, count)
+	/// Lowest canonical depth, up to `max_depth`, whose block is at or after
+	/// `slot`.
+	fn canon_depth_from_slot(&self, slot: u64, max_depth: usize) -> usize {
+		let (mut low, mut high) = (0, max_depth + 1);
+		while low < high {
+			let mid = low + (high - low) / 2;
+			match self.canon_block_at_depth(mid) {
+				Some(block) if block.0.slot < slot => low = mid + 1,
+				_ => high = mid,
+			}
+		}
+		low
 	}
 
+	/// Blocks of the chain of `head_block_root`, starting at `start_slot` and
+	/// taking one slot every `step`, up to `count` of them and at most
+	/// `MAX_REQUEST_BLOCKS`. Slots without a block are skipped. If
+	/// `head_block_root` is not known, no blocks are returned.
 	pub fn blocks_by_slot(
-		&self, mut start_hash: H256, start_slot: u64, count: usize
+		&self, head_block_root: H256, start_slot: u64, count: u64, step: u64,
 	) -> Vec<BeaconBlock<C>> {
-		let _ = self.import_lock.lock();
+		let _guard = self.import_lock.lock();
 
-		if !self.backend.contains(&start_hash).unwrap() || start_hash == H256::default() {
-			self.blocks_by_depth_no_lock(1, count)
-		} else {
-			if !self.backend.is_canon(&start_hash).unwrap() {
-				return Vec::new();
-			}
+		let count = core::cmp::min(count, MAX_REQUEST_BLOCKS);
+		if count == 0 || step == 0 {
+			return Vec::new()
+		}
+		let end_slot = start_slot.saturating_add(count.saturating_mul(step));
+		let wanted = |slot: u64| {
+			slot >= start_slot && slot < end_slot && (slot - start_slot) % step == 0
+		};
 
-			let mut start_state = match self.backend.state_at(&start_hash) {
-				Ok(state) => state,
+		if !self.backend.contains(&head_block_root).unwrap_or(false) {
+			return Vec::new()
+		}
+		let mut current = head_block_root;
+
+		// Blocks of the fork that are not canonical, walked back by parent
+		// until the chain joins the canonical one. At most
+		// `MAX_REQUEST_BLOCKS` blocks that are not wanted are walked through.
+		let mut fork_blocks = Vec::new();
+		let mut skipped = 0;
+		let mut joined = true;
+		while !self.backend.is_canon(&current).unwrap_or(false) {
+			let block = match self.backend.block_at(&current) {
+				Ok(block) => block,
 				Err(_) => return Vec::new(),
 			};
+			if block.0.slot < start_slot {
+				joined = false;
+				break
+			}
+			let parent = block.parent_id();
+			if wanted(block.0.slot) {
+				fork_blocks.push(block);
+			} else {
+				skipped += 1;
+				if skipped > MAX_REQUEST_BLOCKS {
+					return Vec::new()
+				}
+			}
+			current = match parent {
+				Some(parent) => parent,
+				None => {
+					joined = false;
+					break
+				},
+			};
+		}
 
-			while start_state.state().slot > start_slot {
-				start_hash = match self.backend.block_at(&start_hash).unwrap().parent_id() {
-					Some(id) => id,
+		let mut blocks = Vec::new();
+		if joined {
+			let fork_depth = match self.backend.depth_at(&current) {
+				Ok(depth) => depth,
+				Err(_) => return Vec::new(),
+			};
+			for depth in self.canon_depth_from_slot(start_slot, fork_depth)..=fork_depth {
+				match self.canon_block_at_depth(depth) {
+					Some(block) => {
+						if block.0.slot >= end_slot {
+							break
+						}
+						if wanted(block.0.slot) {
+							blocks.push(block);
+						}
+					},
 					None => break,
-				};
-
-				start_state = match self.backend.state_at(&start_hash) {
-					Ok(state) => state,
-					Err(_) => return Vec::new(),
-				};
+				}
 			}
+		}
+		blocks.extend(fork_blocks.into_iter().rev());
+		blocks.truncate(count as usize);
+
+		blocks.into_iter().map(|block| block.0).collect()
+	}
+
+	/// Whether the block with the given root is in the backend.
+	pub fn contains(&self, root: &H256) -> bool {
+		self.backend.contains(root).unwrap_or(false)
+	}
+
+	/// Known blocks among the given roots, at most `MAX_REQUEST_BLOCKS` of
+	/// them.
+	pub fn blocks_by_root(&self, roots: &[H256]) -> Vec<BeaconBlock<C>> {
+		roots.iter()
+			.take(MAX_REQUEST_BLOCKS as usize)
+			.filter_map(|root| self.backend.block_at(root).ok())
+			.map(|block| block.0)
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use beacon::{BeaconState, MinimalConfig};
+	use blockchain::backend::{SharedMemoryBackend, ImportOperation};
+	use blockchain::import::ImportAction;
+	use parity_codec::{Encode, Decode};
+
+	#[derive(Clone)]
+	struct TestState(BeaconState<MinimalConfig>);
+
+	impl StateExternalities for TestState {
+		type Config = MinimalConfig;
 
-			let start_depth = self.backend.depth_at(&start_hash).unwrap();
+		fn state(&self) -> &BeaconState<MinimalConfig> {
+			&self.0
+		}
+
+		fn state_mut(&mut self) -> &mut BeaconState<MinimalConfig> {
+			&mut self.0
+		}
+	}
 
-			self.blocks_by_depth_no_lock(start_depth, count)
+	impl AsExternalities<dyn StateExternalities<Config=MinimalConfig>> for TestState {
+		fn as_externalities(&mut self) -> &mut (dyn StateExternalities<Config=MinimalConfig> + 'static) {
+			self
 		}
 	}
+
+	#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug, Encode, Decode)]
+	enum TestAuxiliaryKey {
+		Empty,
+	}
+
+	#[derive(Clone, Eq, PartialEq, Debug, Encode, Decode)]
+	enum TestAuxiliary {
+		Empty,
+	}
+
+	impl Auxiliary<Block<MinimalConfig>> for TestAuxiliary {
+		type Key = TestAuxiliaryKey;
+
+		fn key(&self) -> TestAuxiliaryKey {
+			TestAuxiliaryKey::Empty
+		}
+	}
+
+	type TestBackend = SharedMemoryBackend<Block<MinimalConfig>, TestAuxiliary, TestState>;
+
+	struct TestChain {
+		handler: Handler<MinimalConfig, TestBackend>,
+		canon_head: H256,
+		fork_head: H256,
+	}
+
+	fn insert(backend: &TestBackend, import_lock: &ImportLock, parent: H256, slot: u64, fork: bool) -> H256 {
+		let block = Block(BeaconBlock {
+			slot,
+			parent_root: parent,
+			state_root: if fork { H256::repeat_byte(1) } else { H256::default() },
+			..Default::default()
+		});
+		let id = block.id();
+
+		let mut action = ImportAction::new(backend, import_lock.lock());
+		action.import_raw(ImportOperation { block, state: TestState(BeaconState::default()) });
+		action.commit().unwrap();
+		id
+	}
+
+	/// Canonical chain with blocks at slots 1, 2, 3, 5 and 6, and a fork
+	/// from the block at slot 2 with blocks at slots 4, 7 and 8.
+	fn chain() -> TestChain {
+		let backend = TestBackend::new_with_genesis(
+			Block(BeaconBlock::default()),
+			TestState(BeaconState::default()),
+		);
+		let import_lock = ImportLock::new();
+
+		let mut canon_head = backend.head();
+		let mut fork_point = canon_head;
+		for slot in &[1, 2, 3, 5, 6] {
+			canon_head = insert(&backend, &import_lock, canon_head, *slot, false);
+			if *slot == 2 {
+				fork_point = canon_head;
+			}
+		}
+		let mut fork_head = fork_point;
+		for slot in &[4, 7, 8] {
+			fork_head = insert(&backend, &import_lock, fork_head, *slot, true);
+		}
+
+		let mut action = ImportAction::new(&backend, import_lock.lock());
+		action.set_head(canon_head);
+		action.commit().unwrap();
+
+		TestChain { handler: Handler::new(backend, import_lock), canon_head, fork_head }
+	}
+
+	fn slots(blocks: Vec<BeaconBlock<MinimalConfig>>) -> Vec<u64> {
+		blocks.iter().map(|block| block.slot).collect()
+	}
+
+	#[test]
+	fn canonical_blocks_follow_count_and_step() {
+		let chain = chain();
+		let handler = &chain.handler;
+
+		assert_eq!(slots(handler.blocks_by_slot(chain.canon_head, 1, 4, 1)), vec![1, 2, 3]);
+		assert_eq!(slots(handler.blocks_by_slot(chain.canon_head, 1, 2, 1)), vec![1, 2]);
+		assert_eq!(slots(handler.blocks_by_slot(chain.canon_head, 1, 3, 2)), vec![1, 3, 5]);
+		assert_eq!(slots(handler.blocks_by_slot(chain.canon_head, 2, 10, 3)), vec![2, 5]);
+		assert!(handler.blocks_by_slot(chain.canon_head, 1, 0, 1).is_empty());
+		assert!(handler.blocks_by_slot(chain.canon_head, 1, 4, 0).is_empty());
+	}
+
+	#[test]
+	fn unknown_head_returns_nothing() {
+		let chain = chain();
+		assert!(chain.handler.blocks_by_slot(H256::repeat_byte(2), 5, 10, 1).is_empty());
+		assert!(chain.handler.blocks_by_slot(H256::default(), 5, 10, 1).is_empty());
+	}
+
+	#[test]
+	fn fork_blocks_join_canonical_ones() {
+		let chain = chain();
+		let handler = &chain.handler;
+
+		assert_eq!(
+			slots(handler.blocks_by_slot(chain.fork_head, 1, 10, 1)),
+			vec![1, 2, 4, 7, 8],
+		);
+		assert_eq!(slots(handler.blocks_by_slot(chain.fork_head, 1, 4, 1)), vec![1, 2, 4]);
+		assert_eq!(slots(handler.blocks_by_slot(chain.fork_head, 1, 4, 2)), vec![1, 7]);
+		assert_eq!(slots(handler.blocks_by_slot(chain.fork_head, 4, 10, 1)), vec![4, 7, 8]);
+		assert_eq!(slots(handler.blocks_by_slot(chain.fork_head, 7, 1, 1)), vec![7]);
+	}
 }
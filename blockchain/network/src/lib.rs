@@ -21,6 +21,8 @@ mod error;
 mod rpc;
 mod service;
 mod handler;
+mod orphans;
+mod status;
 
 pub use behaviour::Behaviour;
 pub use config::Config as NetworkConfig;
@@ -28,29 +30,42 @@ pub use libp2p::enr::Enr;
 pub use libp2p::multiaddr;
 pub use libp2p::Multiaddr;
 pub use libp2p::{
-	gossipsub::{GossipsubConfig, GossipsubConfigBuilder},
+	gossipsub::{GossipsubConfig, GossipsubConfigBuilder, MessageId},
 	PeerId, Swarm,
 };
 pub use error::Error;
 pub use service::Service;
 pub use handler::Handler;
+pub use status::{NetworkStatus, SharedNetworkStatus, PeerInfo, PeerDirection};
+pub use network_messages::PubsubMessage;
 
 use log::*;
 use core::time::Duration;
 use libp2p::identity;
+use libp2p::core::ConnectedPoint;
+use std::collections::HashMap;
 use futures01::{Async, stream::Stream};
 use futures::{Poll, StreamExt as _};
-use blockchain::{Auxiliary, AsExternalities};
+use futures::channel::mpsc;
+use blockchain::{Auxiliary, AsExternalities, Block as BlockT};
 use blockchain::backend::{Store, SharedCommittable, ChainQuery, ImportLock};
 use blockchain::import::BlockImporter;
 use blockchain_network::sync::{NetworkSync, SyncConfig, SyncEvent};
 use beacon::Config;
+use beacon::primitives::H256;
+use beacon::types::BeaconBlock;
+use lmd_ghost::archive::ImportNotification;
 use shasper_runtime::{Block, StateExternalities};
-use network_messages::{HelloMessage, PubsubMessage};
+use network_messages::{HelloMessage, RecentBeaconBlocksRequest};
+use crate::orphans::{OrphanQueue, MAX_ORPHAN_BLOCKS, MAX_ORPHANS_PER_PEER, ORPHAN_EXPIRY};
 use crate::rpc::{RPCEvent, RPCRequest, RPCResponse};
 
 pub const VERSION: &str = "v0.1";
 
+/// Request id of ancestor requests for orphan blocks, so that errors of
+/// those requests can be told apart from others. Other requests use 0.
+const ANCESTOR_REQUEST_ID: usize = 1;
+
 /// Events that can be obtained from polling the Libp2p Service.
 #[derive(Debug)]
 pub enum Libp2pEvent<C: Config> {
@@ -60,14 +75,174 @@ pub enum Libp2pEvent<C: Config> {
     PeerDialed(PeerId),
     /// A peer has disconnected.
     PeerDisconnected(PeerId),
-    /// Received pubsub message.
-    Pubsub(PeerId, PubsubMessage<C>),
+    /// Received pubsub message, with the peer it was propagated from.
+    Pubsub(PeerId, MessageId, PubsubMessage<C>),
+}
+
+/// Outcome of validating a message received on gossip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Validation {
+	/// Message is valid, and is forwarded to other peers.
+	Accept,
+	/// Message is not invalid, but is not useful to forward, for example
+	/// because it is already known.
+	Ignore,
+	/// Message is invalid, and is not forwarded.
+	Reject,
+}
+
+/// Sending half of the channel of messages to be published on gossip.
+pub type PubsubSender<C> = mpsc::UnboundedSender<PubsubMessage<C>>;
+/// Receiving half of the channel of messages to be published on gossip.
+pub type PubsubReceiver<C> = mpsc::UnboundedReceiver<PubsubMessage<C>>;
+
+/// Create a channel of messages to be published on gossip.
+pub fn pubsub_channel<C: Config>() -> (PubsubSender<C>, PubsubReceiver<C>) {
+	mpsc::unbounded()
+}
+
+/// Sending half of the channel of messages received on gossip.
+pub type InboundSender<C> = std::sync::mpsc::Sender<(PeerId, MessageId, PubsubMessage<C>)>;
+/// Receiving half of the channel of messages received on gossip.
+pub type InboundReceiver<C> = std::sync::mpsc::Receiver<(PeerId, MessageId, PubsubMessage<C>)>;
+
+/// Create a channel of messages received on gossip.
+pub fn inbound_channel<C: Config>() -> (InboundSender<C>, InboundReceiver<C>) {
+	std::sync::mpsc::channel()
+}
+
+/// Sending half of the channel of validation outcomes of messages received
+/// on gossip.
+pub type ValidationSender = mpsc::UnboundedSender<(PeerId, MessageId, Validation)>;
+/// Receiving half of the channel of validation outcomes of messages
+/// received on gossip.
+pub type ValidationReceiver = mpsc::UnboundedReceiver<(PeerId, MessageId, Validation)>;
+
+/// Create a channel of validation outcomes of messages received on gossip.
+pub fn validation_channel() -> (ValidationSender, ValidationReceiver) {
+	mpsc::unbounded()
+}
+
+/// Sending half of the channel of gossiped blocks that passed validation but
+/// whose parent is unknown, with the peer they were received from.
+pub type OrphanSender<C> = mpsc::UnboundedSender<(PeerId, BeaconBlock<C>)>;
+/// Receiving half of the channel of gossiped blocks whose parent is unknown.
+pub type OrphanReceiver<C> = mpsc::UnboundedReceiver<(PeerId, BeaconBlock<C>)>;
+
+/// Create a channel of gossiped blocks whose parent is unknown.
+pub fn orphan_channel<C: Config>() -> (OrphanSender<C>, OrphanReceiver<C>) {
+	mpsc::unbounded()
+}
+
+fn update_status<C: Config>(
+	status: &SharedNetworkStatus,
+	service: &Service<C>,
+	peer_statuses: &HashMap<PeerId, HelloMessage>,
+) {
+	let mut status = status.write().expect("Network status lock poisoned");
+	status.listen_addresses = libp2p::Swarm::listeners(&service.swarm).cloned().collect();
+	status.peers = service.swarm.discovery().connected_peer_endpoints().iter()
+		.map(|(peer, endpoint)| {
+			let (address, direction) = match endpoint {
+				ConnectedPoint::Dialer { address } =>
+					(address.clone(), PeerDirection::Outbound),
+				ConnectedPoint::Listener { send_back_addr, .. } =>
+					(send_back_addr.clone(), PeerDirection::Inbound),
+			};
+			(peer.clone(), PeerInfo {
+				address,
+				direction,
+				status: peer_statuses.get(peer).cloned(),
+			})
+		})
+		.collect();
+}
+
+/// Request the block with the given root from `peer`, unless too many
+/// requests to it are in flight.
+fn request_ancestor<C: Config>(
+	service: &mut Service<C>,
+	orphans: &mut OrphanQueue<C>,
+	peer: PeerId,
+	root: H256,
+) {
+	if !orphans.start_request(&peer) {
+		trace!("Too many requests in flight to {:?}, not requesting block {}", peer, root);
+		return
+	}
+
+	service.swarm.send_rpc(peer, RPCEvent::Request(
+		ANCESTOR_REQUEST_ID, RPCRequest::RecentBeaconBlocks(RecentBeaconBlocksRequest {
+			block_roots: vec![root],
+		})
+	));
+}
+
+/// Import a block fetched by root from `peer` as an ancestor of queued
+/// orphans, followed by its queued descendants. If its parent is not known
+/// either, it is queued as well, and the root of the block to fetch next is
+/// returned.
+fn import_fetched<C, I>(
+	importer: &mut I,
+	orphans: &mut OrphanQueue<C>,
+	is_known: impl Fn(&H256) -> bool,
+	peer: &PeerId,
+	block: BeaconBlock<C>,
+) -> Option<H256> where
+	C: Config,
+	I: BlockImporter<Block=Block<C>>,
+{
+	let root = Block(block.clone()).id();
+	if !orphans.has_children(&root) {
+		trace!("Fetched block {} is not an ancestor of a queued block", root);
+		return None
+	}
+
+	if !is_known(&block.parent_root) {
+		if !orphans.insert(root, peer.clone(), block) {
+			return None
+		}
+		return Some(orphans.missing_ancestor(&root))
+	}
+
+	import_with_descendants(importer, orphans, is_known, root, block);
+	None
+}
+
+/// Import a block whose parent is known, followed by its queued
+/// descendants.
+fn import_with_descendants<C, I>(
+	importer: &mut I,
+	orphans: &mut OrphanQueue<C>,
+	is_known: impl Fn(&H256) -> bool,
+	root: H256,
+	block: BeaconBlock<C>,
+) where
+	C: Config,
+	I: BlockImporter<Block=Block<C>>,
+{
+	let mut pending = vec![(root, block)];
+	while let Some((root, block)) = pending.pop() {
+		if !is_known(&root) {
+			if let Err(err) = importer.import_block(Block(block)) {
+				debug!("Queued block {} failed to import: {:?}", root, err);
+				continue
+			}
+		}
+		pending.extend(orphans.take_children(&root));
+	}
 }
 
 pub fn start_network_simple_sync<C, Ba, I>(
 	backend: Ba,
 	import_lock: ImportLock,
 	importer: I,
+	mut notifications: mpsc::UnboundedReceiver<ImportNotification<H256>>,
+	mut publish: PubsubReceiver<C>,
+	inbound: InboundSender<C>,
+	mut validations: ValidationReceiver,
+	mut orphan_blocks: OrphanReceiver<C>,
+	status: SharedNetworkStatus,
 	config: NetworkConfig,
 ) -> Result<(), Error> where
 	C: Config,
@@ -75,7 +250,7 @@ pub fn start_network_simple_sync<C, Ba, I>(
 	Ba::Block: Unpin + Send + Sync,
 	Ba::State: StateExternalities + AsExternalities<dyn StateExternalities<Config=C>>,
 	Ba::Auxiliary: Auxiliary<Block<C>> + Unpin,
-	I: BlockImporter<Block=Block<C>> + Unpin + Send + Sync + 'static,
+	I: BlockImporter<Block=Block<C>> + Clone + Unpin + Send + Sync + 'static,
 {
 	// Create a random PeerId
 	let local_key = identity::Keypair::generate_ed25519();
@@ -90,6 +265,10 @@ pub fn start_network_simple_sync<C, Ba, I>(
 
 	let handler = Handler::<C, Ba>::new(backend, import_lock);
 	let head_status = handler.status();
+	let mut orphan_importer = importer.clone();
+	let mut orphans = OrphanQueue::<C>::new(
+		MAX_ORPHAN_BLOCKS, MAX_ORPHANS_PER_PEER, ORPHAN_EXPIRY,
+	);
 	let mut sync = NetworkSync::<PeerId, HelloMessage, I>::new(
 		head_status,
 		importer,
@@ -98,8 +277,14 @@ pub fn start_network_simple_sync<C, Ba, I>(
 	);
 
 	let mut service = Service::new(config)?;
+	{
+		let mut status = status.write().expect("Network status lock poisoned");
+		status.peer_id = Some(service.local_peer_id.clone());
+		status.enr = Some(service.swarm.discovery().local_enr().clone());
+	}
 
 	let mut listening = false;
+	let mut peer_statuses = HashMap::<PeerId, HelloMessage>::new();
 
 	let poll = futures::future::poll_fn::<Result<(), ()>, _>(move |ctx| {
 		loop {
@@ -112,10 +297,15 @@ pub fn start_network_simple_sync<C, Ba, I>(
 						},
 						Libp2pEvent::PeerDisconnected(peer) => {
 							trace!("Peer noted to disconnect: {:?}", peer);
+							peer_statuses.remove(&peer);
+							orphans.remove_peer(&peer);
 							sync.note_disconnected(peer);
 						},
-						Libp2pEvent::Pubsub(peer, message) => {
-							warn!("Unhandled pubsub message {:?}, {:?}", peer, message);
+						Libp2pEvent::Pubsub(peer, id, message) => {
+							trace!("Received pubsub message {:?}, {:?}", peer, message);
+							if inbound.send((peer, id, message)).is_err() {
+								warn!("Inbound pubsub receiver is gone, dropping message");
+							}
 						},
 						Libp2pEvent::RPC(peer, event) => {
 							trace!("Received RPC event {:?}, {:?}", peer, event);
@@ -126,20 +316,30 @@ pub fn start_network_simple_sync<C, Ba, I>(
 											handler.blocks_by_slot(
 												request.head_block_root,
 												request.start_slot,
-												1, // TODO: request.count as usize,
+												request.count,
+												request.step,
 											)
 										)
 									));
 								},
+								RPCEvent::Request(request_id, RPCRequest::RecentBeaconBlocks(request)) => {
+									service.swarm.send_rpc(peer, RPCEvent::Response(
+										request_id, RPCResponse::RecentBeaconBlocks(
+											handler.blocks_by_root(&request.block_roots)
+										)
+									));
+								},
 								RPCEvent::Request(request_id, RPCRequest::Hello(hello)) => {
 									service.swarm.send_rpc(peer.clone(), RPCEvent::Response(
 										request_id, RPCResponse::Hello(
 											handler.status()
 										)
 									));
+									peer_statuses.insert(peer.clone(), hello.clone());
 									sync.note_peer_status(peer, hello);
 								},
 								RPCEvent::Response(_, RPCResponse::Hello(hello)) => {
+									peer_statuses.insert(peer.clone(), hello.clone());
 									sync.note_peer_status(peer, hello);
 								},
 								RPCEvent::Response(_, RPCResponse::BeaconBlocks(blocks)) => {
@@ -148,6 +348,24 @@ pub fn start_network_simple_sync<C, Ba, I>(
 										Some(peer)
 									);
 								},
+								RPCEvent::Response(_, RPCResponse::RecentBeaconBlocks(blocks)) => {
+									orphans.finish_request(&peer);
+									for block in blocks {
+										let missing = import_fetched(
+											&mut orphan_importer, &mut orphans,
+											|root| handler.contains(root), &peer, block,
+										);
+										if let Some(missing) = missing {
+											request_ancestor(
+												&mut service, &mut orphans, peer.clone(), missing,
+											);
+										}
+									}
+								},
+								RPCEvent::Error(ANCESTOR_REQUEST_ID, err) => {
+									debug!("Ancestor request to {:?} failed: {}", peer, err);
+									orphans.finish_request(&peer);
+								},
 								event => {
 									warn!("Unhandled RPC message {:?}, {:?}", peer, event);
 								},
@@ -162,11 +380,67 @@ pub fn start_network_simple_sync<C, Ba, I>(
 							listening = true;
 						}
 					}
+					update_status(&status, &service, &peer_statuses);
 					break
 				}
 			}
 		}
 
+		orphans.prune();
+
+		loop {
+			match notifications.poll_next_unpin(ctx) {
+				Poll::Pending | Poll::Ready(None) => break,
+				Poll::Ready(Some(ImportNotification::NewHead { .. })) =>
+					sync.note_status(handler.status()),
+				Poll::Ready(Some(ImportNotification::BlockRejected { id, reason })) =>
+					debug!("Block {} was rejected: {}", id, reason),
+				Poll::Ready(Some(_)) => (),
+			}
+		}
+
+		loop {
+			match validations.poll_next_unpin(ctx) {
+				Poll::Pending | Poll::Ready(None) => break,
+				Poll::Ready(Some((peer, id, validation))) => {
+					trace!("Pubsub message {:?} from {:?} validated: {:?}", id, peer, validation);
+					if validation == Validation::Accept {
+						service.swarm.propagate(&id, &peer);
+					}
+				},
+			}
+		}
+
+		loop {
+			match orphan_blocks.poll_next_unpin(ctx) {
+				Poll::Pending | Poll::Ready(None) => break,
+				Poll::Ready(Some((peer, block))) => {
+					let root = Block(block.clone()).id();
+					if handler.contains(&block.parent_root) {
+						trace!("Parent of gossiped block {} was imported meanwhile", root);
+						import_with_descendants(
+							&mut orphan_importer, &mut orphans,
+							|root| handler.contains(root), root, block,
+						);
+					} else if orphans.insert(root, peer.clone(), block) {
+						trace!("Parent of gossiped block {} is unknown, queueing it", root);
+						let missing = orphans.missing_ancestor(&root);
+						request_ancestor(&mut service, &mut orphans, peer, missing);
+					}
+				},
+			}
+		}
+
+		loop {
+			match publish.poll_next_unpin(ctx) {
+				Poll::Pending | Poll::Ready(None) => break,
+				Poll::Ready(Some(message)) => {
+					trace!("Publishing pubsub message {:?}", message);
+					service.swarm.publish(message);
+				},
+			}
+		}
+
 		loop {
 			match sync.poll_next_unpin(ctx) {
 				Poll::Pending | Poll::Ready(None) => break,
@@ -183,9 +457,10 @@ pub fn start_network_simple_sync<C, Ba, I>(
 				},
 				Poll::Ready(Some(SyncEvent::QueryBlocks(peer))) => {
 					trace!("Sync requested blocks query to {:?}", peer);
+					let request = handler.head_request(peer_statuses.get(&peer));
 					service.swarm.send_rpc(peer, RPCEvent::Request(
 						0,
-						RPCRequest::BeaconBlocks(handler.head_request(50))
+						RPCRequest::BeaconBlocks(request)
 					));
 				},
 			}
@@ -0,0 +1,254 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Parity Shasper.
+
+// Parity Shasper is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+
+// Parity Shasper is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more
+// details.
+
+// You should have received a copy of the GNU General Public License along with
+// Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Queue of blocks whose parent is not known yet, waiting for their
+//! ancestors to be fetched.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use libp2p::PeerId;
+use beacon::Config;
+use beacon::primitives::H256;
+use beacon::types::BeaconBlock;
+
+/// Maximum number of blocks kept in the orphan queue.
+pub const MAX_ORPHAN_BLOCKS: usize = 256;
+/// Maximum number of blocks from a single peer kept in the orphan queue.
+pub const MAX_ORPHANS_PER_PEER: usize = 16;
+/// Maximum number of ancestor requests in flight to a single peer.
+pub const MAX_REQUESTS_PER_PEER: usize = 2;
+/// Time after which a block still in the orphan queue is dropped.
+pub const ORPHAN_EXPIRY: Duration = Duration::from_secs(120);
+/// Time after which an ancestor request without response no longer counts
+/// as in flight.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Orphan<C: Config> {
+	block: BeaconBlock<C>,
+	peer: PeerId,
+	received: Instant,
+}
+
+/// Blocks with an unknown parent, indexed by block root, and the ancestor
+/// requests in flight for them.
+pub struct OrphanQueue<C: Config> {
+	orphans: HashMap<H256, Orphan<C>>,
+	requests: HashMap<PeerId, Vec<Instant>>,
+	max_blocks: usize,
+	max_per_peer: usize,
+	expiry: Duration,
+}
+
+impl<C: Config> OrphanQueue<C> {
+	pub fn new(max_blocks: usize, max_per_peer: usize, expiry: Duration) -> Self {
+		Self {
+			orphans: HashMap::new(),
+			requests: HashMap::new(),
+			max_blocks,
+			max_per_peer,
+			expiry,
+		}
+	}
+
+	/// Park a block received from `peer`. When the queue is full, the oldest
+	/// block is dropped. Returns whether the block was queued, which it is
+	/// not if it is already in the queue, or if the peer has too many blocks
+	/// in it.
+	pub fn insert(&mut self, root: H256, peer: PeerId, block: BeaconBlock<C>) -> bool {
+		if self.orphans.contains_key(&root) {
+			return false
+		}
+
+		if self.orphans.values().filter(|orphan| orphan.peer == peer).count() >= self.max_per_peer {
+			return false
+		}
+
+		if self.orphans.len() >= self.max_blocks {
+			let oldest = self.orphans.iter()
+				.min_by_key(|(_, orphan)| orphan.received)
+				.map(|(root, _)| *root);
+			if let Some(oldest) = oldest {
+				self.orphans.remove(&oldest);
+			}
+		}
+
+		self.orphans.insert(root, Orphan { block, peer, received: Instant::now() });
+		true
+	}
+
+	/// Root of the earliest missing ancestor of the block with the given
+	/// root, following parents through the queue.
+	pub fn missing_ancestor(&self, root: &H256) -> H256 {
+		let mut current = *root;
+		while let Some(orphan) = self.orphans.get(&current) {
+			current = orphan.block.parent_root;
+		}
+		current
+	}
+
+	/// Whether some block in the queue has the given parent.
+	pub fn has_children(&self, parent_root: &H256) -> bool {
+		self.orphans.values().any(|orphan| &orphan.block.parent_root == parent_root)
+	}
+
+	/// Remove and return the blocks whose parent has the given root.
+	pub fn take_children(&mut self, parent_root: &H256) -> Vec<(H256, BeaconBlock<C>)> {
+		let roots = self.orphans.iter()
+			.filter(|(_, orphan)| &orphan.block.parent_root == parent_root)
+			.map(|(root, _)| *root)
+			.collect::<Vec<_>>();
+
+		roots.into_iter()
+			.filter_map(|root| self.orphans.remove(&root).map(|orphan| (root, orphan.block)))
+			.collect()
+	}
+
+	/// Note an ancestor request to `peer`. Returns false, and the request
+	/// should not be sent, if too many requests to the peer are in flight.
+	pub fn start_request(&mut self, peer: &PeerId) -> bool {
+		let requests = self.requests.entry(peer.clone()).or_insert_with(Vec::new);
+		if requests.len() >= MAX_REQUESTS_PER_PEER {
+			return false
+		}
+
+		requests.push(Instant::now());
+		true
+	}
+
+	/// Note a response to an ancestor request from `peer`.
+	pub fn finish_request(&mut self, peer: &PeerId) {
+		if let Some(requests) = self.requests.get_mut(peer) {
+			if !requests.is_empty() {
+				requests.remove(0);
+			}
+		}
+	}
+
+	/// Forget requests in flight to a disconnected peer.
+	pub fn remove_peer(&mut self, peer: &PeerId) {
+		self.requests.remove(peer);
+	}
+
+	/// Drop blocks that have been in the queue for longer than the expiry,
+	/// and requests that timed out.
+	pub fn prune(&mut self) {
+		let expiry = self.expiry;
+		self.orphans.retain(|_, orphan| orphan.received.elapsed() < expiry);
+		for requests in self.requests.values_mut() {
+			requests.retain(|sent| sent.elapsed() < REQUEST_TIMEOUT);
+		}
+		self.requests.retain(|_, requests| !requests.is_empty());
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use beacon::MinimalConfig;
+	use libp2p::identity::Keypair;
+	use std::thread;
+
+	fn peer() -> PeerId {
+		PeerId::from(Keypair::generate_ed25519().public())
+	}
+
+	fn root(n: u8) -> H256 {
+		H256::repeat_byte(n)
+	}
+
+	fn block(slot: u64, parent: u8) -> BeaconBlock<MinimalConfig> {
+		BeaconBlock { slot, parent_root: root(parent), ..Default::default() }
+	}
+
+	#[test]
+	fn oldest_block_is_evicted_at_capacity() {
+		let mut queue = OrphanQueue::<MinimalConfig>::new(2, 16, ORPHAN_EXPIRY);
+		let peer = peer();
+		assert!(queue.insert(root(1), peer.clone(), block(1, 0)));
+		thread::sleep(Duration::from_millis(1));
+		assert!(queue.insert(root(2), peer.clone(), block(2, 0)));
+		assert!(!queue.insert(root(2), peer.clone(), block(2, 0)));
+		thread::sleep(Duration::from_millis(1));
+		assert!(queue.insert(root(3), peer.clone(), block(3, 0)));
+
+		let children = queue.take_children(&root(0));
+		let mut roots = children.iter().map(|(root, _)| *root).collect::<Vec<_>>();
+		roots.sort();
+		assert_eq!(roots, vec![root(2), root(3)]);
+	}
+
+	#[test]
+	fn blocks_per_peer_are_limited() {
+		let mut queue = OrphanQueue::<MinimalConfig>::new(16, 2, ORPHAN_EXPIRY);
+		let (first, second) = (peer(), peer());
+		assert!(queue.insert(root(1), first.clone(), block(1, 0)));
+		assert!(queue.insert(root(2), first.clone(), block(2, 0)));
+		assert!(!queue.insert(root(3), first.clone(), block(3, 0)));
+		assert!(queue.insert(root(3), second, block(3, 0)));
+	}
+
+	#[test]
+	fn expired_blocks_are_pruned() {
+		let mut queue = OrphanQueue::<MinimalConfig>::new(16, 16, Duration::from_millis(1));
+		assert!(queue.insert(root(1), peer(), block(1, 0)));
+		assert!(queue.has_children(&root(0)));
+
+		thread::sleep(Duration::from_millis(2));
+		queue.prune();
+		assert!(!queue.has_children(&root(0)));
+	}
+
+	#[test]
+	fn missing_ancestor_follows_queued_parents() {
+		let mut queue = OrphanQueue::<MinimalConfig>::new(16, 16, ORPHAN_EXPIRY);
+		let peer = peer();
+		queue.insert(root(3), peer.clone(), block(3, 2));
+		queue.insert(root(2), peer.clone(), block(2, 1));
+		assert_eq!(queue.missing_ancestor(&root(3)), root(1));
+		assert_eq!(queue.missing_ancestor(&root(5)), root(5));
+	}
+
+	#[test]
+	fn children_are_taken_once() {
+		let mut queue = OrphanQueue::<MinimalConfig>::new(16, 16, ORPHAN_EXPIRY);
+		let peer = peer();
+		queue.insert(root(2), peer.clone(), block(2, 1));
+		queue.insert(root(3), peer.clone(), block(3, 2));
+
+		let children = queue.take_children(&root(1));
+		assert_eq!(children.len(), 1);
+		assert_eq!(children[0].0, root(2));
+		assert_eq!(children[0].1.slot, 2);
+		assert!(queue.take_children(&root(1)).is_empty());
+		assert!(queue.has_children(&root(2)));
+	}
+
+	#[test]
+	fn requests_per_peer_are_limited() {
+		let mut queue = OrphanQueue::<MinimalConfig>::new(16, 16, ORPHAN_EXPIRY);
+		let peer = peer();
+		for _ in 0..MAX_REQUESTS_PER_PEER {
+			assert!(queue.start_request(&peer));
+		}
+		assert!(!queue.start_request(&peer));
+
+		queue.finish_request(&peer);
+		assert!(queue.start_request(&peer));
+
+		queue.remove_peer(&peer);
+		assert!(queue.start_request(&peer));
+	}
+}
@@ -0,0 +1,68 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Parity Shasper.
+
+// Parity Shasper is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+
+// Parity Shasper is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more
+// details.
+
+// You should have received a copy of the GNU General Public License along with
+// Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Status of the network service, shared with the node API.
+
+use libp2p::{Multiaddr, PeerId};
+use libp2p::enr::Enr;
+use network_messages::HelloMessage;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// Direction of a peer connection.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PeerDirection {
+	/// The peer dialed us.
+	Inbound,
+	/// We dialed the peer.
+	Outbound,
+}
+
+/// A connected peer.
+#[derive(Clone, Debug)]
+pub struct PeerInfo {
+	/// Address the peer is connected at.
+	pub address: Multiaddr,
+	/// Direction of the connection.
+	pub direction: PeerDirection,
+	/// Last hello status the peer sent, if any.
+	pub status: Option<HelloMessage>,
+}
+
+/// Status of the network service.
+#[derive(Clone, Debug, Default)]
+pub struct NetworkStatus {
+	/// Peer id of the node, once the service is started.
+	pub peer_id: Option<PeerId>,
+	/// ENR of the node, once the service is started.
+	pub enr: Option<Enr>,
+	/// Addresses the node listens on.
+	pub listen_addresses: Vec<Multiaddr>,
+	/// Connected peers.
+	pub peers: HashMap<PeerId, PeerInfo>,
+}
+
+impl NetworkStatus {
+	/// Highest head slot reported by any connected peer.
+	pub fn best_peer_slot(&self) -> Option<u64> {
+		self.peers.values()
+			.filter_map(|peer| peer.status.as_ref().map(|status| status.head_slot))
+			.max()
+	}
+}
+
+/// Network status shared between the network service and its readers.
+pub type SharedNetworkStatus = Arc<RwLock<NetworkStatus>>;
@@ -0,0 +1,414 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Parity Shasper.
+
+// Parity Shasper is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+
+// Parity Shasper is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more
+// details.
+
+// You should have received a copy of the GNU General Public License along with
+// Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
+//! Read endpoints of the standard Beacon API under `/eth/v1/beacon`.
+
+use beacon::{Config, BLSConfig, BeaconExecutive, BeaconState};
+use beacon::primitives::{H256, ValidatorId, Version, Uint};
+use beacon::types::{Checkpoint, SigningBeaconBlockHeader, UnsealedBeaconBlock, Validator};
+use beacon::consts::FAR_FUTURE_EPOCH;
+use beacon::utils;
+use blockchain::Block as BlockT;
+use blockchain::backend::{ChainQuery, Store};
+use bm_le::tree_root;
+use serde::Serialize;
+use std::collections::HashMap;
+use crate::{Block, StateExternalities, genesis};
+use super::{ApiContext, ApiError, SignedMessage, to_data_json, parse_uint, parse_bytes, backend_error};
+
+#[derive(Serialize)]
+struct GenesisInfo {
+	#[serde(serialize_with = "utils::serialize_uint")]
+	genesis_time: Uint,
+	genesis_validators_root: H256,
+	genesis_fork_version: Version,
+}
+
+#[derive(Serialize)]
+struct RootInfo {
+	root: H256,
+}
+
+#[derive(Serialize)]
+struct HeaderInfo {
+	root: H256,
+	canonical: bool,
+	header: SignedMessage<SigningBeaconBlockHeader>,
+}
+
+#[derive(Serialize)]
+struct FinalityCheckpoints {
+	previous_justified: Checkpoint,
+	current_justified: Checkpoint,
+	finalized: Checkpoint,
+}
+
+#[derive(Serialize)]
+struct ValidatorInfo {
+	#[serde(serialize_with = "utils::serialize_uint")]
+	index: Uint,
+	#[serde(serialize_with = "utils::serialize_uint")]
+	balance: Uint,
+	status: &'static str,
+	validator: Validator,
+}
+
+#[derive(Serialize)]
+struct BalanceInfo {
+	#[serde(serialize_with = "utils::serialize_uint")]
+	index: Uint,
+	#[serde(serialize_with = "utils::serialize_uint")]
+	balance: Uint,
+}
+
+#[derive(Serialize)]
+struct CommitteeInfo {
+	#[serde(serialize_with = "utils::serialize_uint")]
+	index: Uint,
+	#[serde(serialize_with = "utils::serialize_uint")]
+	slot: Uint,
+	#[serde(serialize_with = "utils::serialize_uint_seq")]
+	validators: Vec<Uint>,
+}
+
+/// Status of a validator at `epoch`, as named by the Beacon API.
+fn validator_status(validator: &Validator, balance: Uint, epoch: Uint) -> &'static str {
+	if validator.activation_epoch > epoch {
+		if validator.activation_eligibility_epoch == FAR_FUTURE_EPOCH {
+			"pending_initialized"
+		} else {
+			"pending_queued"
+		}
+	} else if validator.exit_epoch > epoch {
+		if validator.slashed {
+			"active_slashed"
+		} else if validator.exit_epoch == FAR_FUTURE_EPOCH {
+			"active_ongoing"
+		} else {
+			"active_exiting"
+		}
+	} else if validator.withdrawable_epoch > epoch {
+		if validator.slashed {
+			"exited_slashed"
+		} else {
+			"exited_unslashed"
+		}
+	} else if balance == 0 {
+		"withdrawal_done"
+	} else {
+		"withdrawal_possible"
+	}
+}
+
+/// Whether `status` is matched by a status filter, which is either a full
+/// status or its general prefix such as `active`.
+fn status_matches(filter: &[&str], status: &str) -> bool {
+	filter.is_empty() || filter.iter().any(|filter| {
+		*filter == status || status.split('_').next() == Some(*filter)
+	})
+}
+
+/// Root of the canonical block at `slot`, or the last one before it if the
+/// slot is empty.
+fn canonical_root_at_slot<B, C: Config>(backend: &B, slot: Uint) -> Result<Option<H256>, ApiError> where
+	B: ChainQuery + Store<Block=Block<C>>,
+{
+	let block_at_depth = |depth: usize| -> Result<(H256, Block<C>), ApiError> {
+		let root = backend.lookup_canon_depth(depth)
+			.map_err(backend_error)?
+			.ok_or_else(|| ApiError::Internal(format!("Missing canonical block at depth {}", depth)))?;
+		let block = backend.block_at(&root).map_err(backend_error)?;
+		Ok((root, block))
+	};
+
+	let (genesis_root, genesis_block) = block_at_depth(0)?;
+	if genesis_block.0.slot > slot {
+		return Ok(None)
+	}
+
+	// Slots strictly increase along the canonical chain, so binary search
+	// the last block at or before `slot`.
+	let (mut low, mut low_root) = (0, genesis_root);
+	let mut high = backend.depth_at(&backend.head()).map_err(backend_error)? + 1;
+	while high - low > 1 {
+		let mid = low + (high - low) / 2;
+		let (root, block) = block_at_depth(mid)?;
+		if block.0.slot <= slot {
+			low = mid;
+			low_root = root;
+		} else {
+			high = mid;
+		}
+	}
+	Ok(Some(low_root))
+}
+
+/// Root of the block identified by `id`, which is `head`, `genesis`,
+/// `finalized`, `justified`, a slot or a block root.
+fn resolve_block<B, C: Config>(backend: &B, id: &str) -> Result<H256, ApiError> where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C>,
+{
+	let checkpoint_root = |root: H256| if root == H256::default() { backend.genesis() } else { root };
+
+	match id {
+		"head" => Ok(backend.head()),
+		"genesis" => Ok(backend.genesis()),
+		"finalized" | "justified" => {
+			let state = backend.state_at(&backend.head()).map_err(backend_error)?;
+			Ok(checkpoint_root(if id == "finalized" {
+				state.state().finalized_checkpoint.root
+			} else {
+				state.state().current_justified_checkpoint.root
+			}))
+		},
+		id if id.starts_with("0x") => {
+			let root = H256::from_slice(&parse_bytes(id, 32)?);
+			if !backend.contains(&root).map_err(backend_error)? {
+				return Err(ApiError::NotFound)
+			}
+			Ok(root)
+		},
+		id => {
+			let slot = parse_uint(id)?;
+			let root = canonical_root_at_slot(backend, slot)?.ok_or(ApiError::NotFound)?;
+			let block = backend.block_at(&root).map_err(backend_error)?;
+			if block.0.slot != slot {
+				return Err(ApiError::NotFound)
+			}
+			Ok(root)
+		},
+	}
+}
+
+/// State identified by `id`, which is `head`, `genesis`, `finalized`,
+/// `justified`, a slot up to the head or a state root of a canonical block
+/// from the finalized one on.
+fn resolve_state<B, C: Config>(backend: &B, id: &str) -> Result<BeaconState<C>, ApiError> where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C>,
+{
+	match id {
+		"head" | "genesis" | "finalized" | "justified" => {
+			let root = resolve_block(backend, id)?;
+			Ok(backend.state_at(&root).map_err(backend_error)?.state().clone())
+		},
+		id if id.starts_with("0x") => {
+			let state_root = H256::from_slice(&parse_bytes(id, 32)?);
+			let finalized_epoch = backend.state_at(&backend.head()).map_err(backend_error)?
+				.state().finalized_checkpoint.epoch;
+			let finalized_slot = utils::start_slot_of_epoch::<C>(finalized_epoch);
+
+			// Only canonical blocks back to the finalized one are searched, so
+			// that a lookup does not walk the whole chain.
+			let mut depth = backend.depth_at(&backend.head()).map_err(backend_error)?;
+			loop {
+				let root = backend.lookup_canon_depth(depth)
+					.map_err(backend_error)?
+					.ok_or(ApiError::NotFound)?;
+				let block = backend.block_at(&root).map_err(backend_error)?;
+				if block.0.state_root == state_root {
+					return Ok(backend.state_at(&root).map_err(backend_error)?.state().clone())
+				}
+				if block.0.slot <= finalized_slot || depth == 0 {
+					return Err(ApiError::NotFound)
+				}
+				depth -= 1;
+			}
+		},
+		id => {
+			let slot = parse_uint(id)?;
+			let head_slot = backend.block_at(&backend.head()).map_err(backend_error)?.0.slot;
+			if slot > head_slot {
+				return Err(ApiError::NotFound)
+			}
+			let root = canonical_root_at_slot(backend, slot)?.ok_or(ApiError::NotFound)?;
+			let mut state = backend.state_at(&root).map_err(backend_error)?.state().clone();
+			if state.slot < slot {
+				beacon::initialize_block::<C>(&mut state, slot)
+					.map_err(|e| ApiError::Internal(format!("{:?}", e)))?;
+			}
+			Ok(state)
+		},
+	}
+}
+
+/// Index of the validator identified by `id`, which is an index or a public
+/// key.
+fn resolve_validator<C: Config>(state: &BeaconState<C>, id: &str) -> Result<Option<Uint>, ApiError> {
+	if id.starts_with("0x") {
+		let pubkey = ValidatorId::from_slice(&parse_bytes(id, 48)?);
+		Ok(state.validators.iter().position(|v| v.pubkey == pubkey).map(|index| index as Uint))
+	} else {
+		let index = parse_uint(id)?;
+		Ok(if (index as usize) < state.validators.len() { Some(index) } else { None })
+	}
+}
+
+/// Indices of the validators given by the comma-separated `id` parameter,
+/// or all validators if it is missing. Unknown validators are skipped.
+fn validator_indices<C: Config>(
+	state: &BeaconState<C>,
+	params: &HashMap<String, String>,
+) -> Result<Vec<Uint>, ApiError> {
+	match params.get("id") {
+		Some(ids) => {
+			let mut indices = Vec::new();
+			for id in ids.split(',').filter(|id| !id.is_empty()) {
+				indices.extend(resolve_validator(state, id)?);
+			}
+			Ok(indices)
+		},
+		None => Ok((0..state.validators.len() as Uint).collect()),
+	}
+}
+
+fn validator_info<C: Config>(state: &BeaconState<C>, index: Uint) -> ValidatorInfo {
+	let validator = state.validators[index as usize].clone();
+	let balance = state.balances[index as usize];
+	let status = validator_status(&validator, balance, utils::epoch_of_slot::<C>(state.slot));
+	ValidatorInfo { index, balance, status, validator }
+}
+
+fn committees<C: Config>(
+	state: &mut BeaconState<C>,
+	params: &HashMap<String, String>,
+) -> Result<Vec<CommitteeInfo>, ApiError> {
+	let executive = BeaconExecutive::new(state);
+	let current_epoch = executive.current_epoch();
+	let epoch = match params.get("epoch") {
+		Some(epoch) => parse_uint(epoch)?,
+		None => current_epoch,
+	};
+	if epoch + 1 < current_epoch || epoch > current_epoch + 1 {
+		return Err(ApiError::BadRequest(format!("Epoch {} is out of range of the state", epoch)))
+	}
+	let index = params.get("index").map(|index| parse_uint(index)).transpose()?;
+	let slot = params.get("slot").map(|slot| parse_uint(slot)).transpose()?;
+
+	let mut committees = Vec::new();
+	let start_slot = utils::start_slot_of_epoch::<C>(epoch);
+	for committee_slot in start_slot..(start_slot + C::slots_per_epoch()) {
+		if slot.map(|slot| slot != committee_slot).unwrap_or(false) {
+			continue
+		}
+
+		for committee_index in 0..executive.committee_count_at_slot(committee_slot) {
+			if index.map(|index| index != committee_index).unwrap_or(false) {
+				continue
+			}
+
+			committees.push(CommitteeInfo {
+				index: committee_index,
+				slot: committee_slot,
+				validators: executive.beacon_committee(committee_slot, committee_index)
+					.map_err(|e| ApiError::Internal(format!("{:?}", e)))?,
+			});
+		}
+	}
+	Ok(committees)
+}
+
+pub(super) fn handle<B, I, C: Config, BLS: BLSConfig>(
+	context: &ApiContext<B, I, C, BLS>,
+	path: &str,
+	params: &HashMap<String, String>,
+) -> Result<String, ApiError> where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C>,
+{
+	let backend = &context.backend;
+	let segments = path.split('/').collect::<Vec<_>>();
+
+	match segments.as_slice() {
+		["genesis"] => {
+			let state = backend.state_at(&backend.genesis()).map_err(backend_error)?;
+			to_data_json(GenesisInfo {
+				genesis_time: state.state().genesis_time,
+				genesis_validators_root: genesis::genesis_validators_root(state.state()),
+				genesis_fork_version: state.state().fork.current_version,
+			})
+		},
+		["headers", id] => {
+			let root = resolve_block(backend, id)?;
+			let block = backend.block_at(&root).map_err(backend_error)?;
+			to_data_json(HeaderInfo {
+				root,
+				canonical: backend.is_canon(&root).map_err(backend_error)?,
+				header: SignedMessage {
+					message: SigningBeaconBlockHeader {
+						slot: block.0.slot,
+						parent_root: block.0.parent_root,
+						state_root: block.0.state_root,
+						body_root: tree_root::<C::Digest, _>(&block.0.body),
+					},
+					signature: block.0.signature,
+				},
+			})
+		},
+		["blocks", id] => {
+			let root = resolve_block(backend, id)?;
+			let block = backend.block_at(&root).map_err(backend_error)?;
+			to_data_json(SignedMessage {
+				message: UnsealedBeaconBlock::from(&block.0),
+				signature: block.0.signature,
+			})
+		},
+		["states", id, "root"] => {
+			let state = resolve_state(backend, id)?;
+			to_data_json(RootInfo { root: tree_root::<C::Digest, _>(&state) })
+		},
+		["states", id, "fork"] => {
+			let state = resolve_state(backend, id)?;
+			to_data_json(state.fork)
+		},
+		["states", id, "finality_checkpoints"] => {
+			let state = resolve_state(backend, id)?;
+			to_data_json(FinalityCheckpoints {
+				previous_justified: state.previous_justified_checkpoint,
+				current_justified: state.current_justified_checkpoint,
+				finalized: state.finalized_checkpoint,
+			})
+		},
+		["states", id, "validators"] => {
+			let state = resolve_state(backend, id)?;
+			let statuses = params.get("status")
+				.map(|statuses| statuses.split(',').filter(|s| !s.is_empty()).collect::<Vec<_>>())
+				.unwrap_or_default();
+			let validators = validator_indices(&state, params)?.into_iter()
+				.map(|index| validator_info(&state, index))
+				.filter(|info| status_matches(&statuses, info.status))
+				.collect::<Vec<_>>();
+			to_data_json(validators)
+		},
+		["states", id, "validators", validator_id] => {
+			let state = resolve_state(backend, id)?;
+			let index = resolve_validator(&state, validator_id)?.ok_or(ApiError::NotFound)?;
+			to_data_json(validator_info(&state, index))
+		},
+		["states", id, "validator_balances"] => {
+			let state = resolve_state(backend, id)?;
+			let balances = validator_indices(&state, params)?.into_iter()
+				.map(|index| BalanceInfo { index, balance: state.balances[index as usize] })
+				.collect::<Vec<_>>();
+			to_data_json(balances)
+		},
+		["states", id, "committees"] => {
+			let mut state = resolve_state(backend, id)?;
+			to_data_json(committees(&mut state, params)?)
+		},
+		_ => Err(ApiError::NotFound),
+	}
+}
@@ -0,0 +1,283 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Parity Shasper.
+
+// Parity Shasper is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+
+// Parity Shasper is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more
+// details.
+
+// You should have received a copy of the GNU General Public License along with
+// Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
+//! Server-sent event stream of the standard Beacon API at `/eth/v1/events`.
+//! Clients choose the topics to follow with the `topics` parameter, and
+//! receive each event as it happens until they disconnect.
+
+use beacon::Config;
+use beacon::primitives::{H256, Uint};
+use beacon::utils;
+use blockchain::backend::{ChainQuery, Store};
+use lmd_ghost::archive::ImportNotification;
+use serde::Serialize;
+use tiny_http::Request;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use log::{trace, warn};
+use crate::{Error, Block, StateExternalities};
+use super::{ApiError, param};
+
+/// Interval of keep-alive comments on idle streams, which is also how soon a
+/// disconnected client is noticed.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Topic of events a client can subscribe to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Topic {
+	Head,
+	Block,
+	Attestation,
+	VoluntaryExit,
+	FinalizedCheckpoint,
+	ChainReorg,
+}
+
+impl Topic {
+	fn name(&self) -> &'static str {
+		match self {
+			Topic::Head => "head",
+			Topic::Block => "block",
+			Topic::Attestation => "attestation",
+			Topic::VoluntaryExit => "voluntary_exit",
+			Topic::FinalizedCheckpoint => "finalized_checkpoint",
+			Topic::ChainReorg => "chain_reorg",
+		}
+	}
+
+	fn from_name(name: &str) -> Option<Topic> {
+		match name {
+			"head" => Some(Topic::Head),
+			"block" => Some(Topic::Block),
+			"attestation" => Some(Topic::Attestation),
+			"voluntary_exit" => Some(Topic::VoluntaryExit),
+			"finalized_checkpoint" => Some(Topic::FinalizedCheckpoint),
+			"chain_reorg" => Some(Topic::ChainReorg),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Serialize)]
+struct HeadEvent {
+	#[serde(serialize_with = "utils::serialize_uint")]
+	slot: Uint,
+	block: H256,
+	state: H256,
+	epoch_transition: bool,
+}
+
+#[derive(Serialize)]
+struct BlockEvent {
+	#[serde(serialize_with = "utils::serialize_uint")]
+	slot: Uint,
+	block: H256,
+}
+
+#[derive(Serialize)]
+struct FinalizedCheckpointEvent {
+	block: H256,
+	state: H256,
+	#[serde(serialize_with = "utils::serialize_uint")]
+	epoch: Uint,
+}
+
+#[derive(Serialize)]
+struct ChainReorgEvent {
+	#[serde(serialize_with = "utils::serialize_uint")]
+	slot: Uint,
+	#[serde(serialize_with = "utils::serialize_uint")]
+	depth: Uint,
+	old_head_block: H256,
+	new_head_block: H256,
+	old_head_state: H256,
+	new_head_state: H256,
+	#[serde(serialize_with = "utils::serialize_uint")]
+	epoch: Uint,
+}
+
+struct Subscriber {
+	topics: Vec<Topic>,
+	sender: mpsc::Sender<String>,
+}
+
+/// Sender of events to every stream subscribed to their topic.
+#[derive(Clone, Default)]
+pub struct EventSender {
+	subscribers: Arc<Mutex<Vec<Subscriber>>>,
+}
+
+impl EventSender {
+	/// Send an event of `topic`. Streams that have been closed are dropped.
+	pub fn send<T: Serialize>(&self, topic: Topic, data: &T) {
+		let mut subscribers = self.subscribers.lock().expect("Event subscribers lock poisoned");
+		if !subscribers.iter().any(|subscriber| subscriber.topics.contains(&topic)) {
+			return
+		}
+
+		let data = match serde_json::to_string(data) {
+			Ok(data) => data,
+			Err(err) => {
+				warn!("Unable to encode {} event: {}", topic.name(), err);
+				return
+			},
+		};
+		let event = format!("event: {}\ndata: {}\n\n", topic.name(), data);
+		subscribers.retain(|subscriber| {
+			!subscriber.topics.contains(&topic) || subscriber.sender.send(event.clone()).is_ok()
+		});
+	}
+
+	fn is_subscribed(&self, topic: Topic) -> bool {
+		self.subscribers.lock().expect("Event subscribers lock poisoned")
+			.iter()
+			.any(|subscriber| subscriber.topics.contains(&topic))
+	}
+
+	fn subscribe(&self, topics: Vec<Topic>) -> mpsc::Receiver<String> {
+		let (sender, receiver) = mpsc::channel();
+		self.subscribers.lock().expect("Event subscribers lock poisoned")
+			.push(Subscriber { topics, sender });
+		receiver
+	}
+}
+
+/// Topics of the comma-separated `topics` parameter.
+fn topics(params: &HashMap<String, String>) -> Result<Vec<Topic>, ApiError> {
+	param(params, "topics")?.split(',')
+		.map(|name| Topic::from_name(name).ok_or_else(|| {
+			ApiError::BadRequest(format!("Unknown topic {}", name))
+		}))
+		.collect()
+}
+
+fn write_events(writer: &mut dyn Write, receiver: mpsc::Receiver<String>) -> io::Result<()> {
+	writer.write_all(
+		b"HTTP/1.1 200 OK\r\n\
+		  Content-Type: text/event-stream\r\n\
+		  Cache-Control: no-cache\r\n\
+		  Connection: close\r\n\r\n"
+	)?;
+	writer.flush()?;
+
+	loop {
+		match receiver.recv_timeout(KEEP_ALIVE_INTERVAL) {
+			Ok(event) => writer.write_all(event.as_bytes())?,
+			Err(mpsc::RecvTimeoutError::Timeout) => writer.write_all(b": keep-alive\n\n")?,
+			Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(()),
+		}
+		writer.flush()?;
+	}
+}
+
+/// Answer an event stream request, streaming events of the requested topics
+/// on a new thread.
+pub(super) fn stream(
+	events: &EventSender,
+	request: Request,
+	params: &HashMap<String, String>,
+) -> Result<(), (Request, ApiError)> {
+	let topics = match topics(params) {
+		Ok(topics) => topics,
+		Err(err) => return Err((request, err)),
+	};
+	let receiver = events.subscribe(topics);
+
+	thread::spawn(move || {
+		let mut writer = request.into_writer();
+		if let Err(err) = write_events(&mut writer, receiver) {
+			trace!("Event stream closed: {}", err);
+		}
+	});
+	Ok(())
+}
+
+fn send_notification_events<B, C: Config>(
+	backend: &B,
+	events: &EventSender,
+	notification: ImportNotification<H256>,
+) -> Result<(), Error> where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C>,
+{
+	match notification {
+		ImportNotification::BlockImported { id, .. } => {
+			let block = backend.block_at(&id).map_err(Error::backend)?;
+			events.send(Topic::Block, &BlockEvent { slot: block.0.slot, block: id });
+		},
+		ImportNotification::NewHead { old, new, reorg_depth } => {
+			let old_block = backend.block_at(&old).map_err(Error::backend)?;
+			let new_block = backend.block_at(&new).map_err(Error::backend)?;
+			let epoch = utils::epoch_of_slot::<C>(new_block.0.slot);
+			events.send(Topic::Head, &HeadEvent {
+				slot: new_block.0.slot,
+				block: new,
+				state: new_block.0.state_root,
+				epoch_transition: epoch != utils::epoch_of_slot::<C>(old_block.0.slot),
+			});
+
+			if reorg_depth > 0 {
+				events.send(Topic::ChainReorg, &ChainReorgEvent {
+					slot: new_block.0.slot,
+					depth: reorg_depth as Uint,
+					old_head_block: old,
+					new_head_block: new,
+					old_head_state: old_block.0.state_root,
+					new_head_state: new_block.0.state_root,
+					epoch,
+				});
+			}
+		},
+		ImportNotification::FinalizedChanged { new, head, .. } => {
+			if events.is_subscribed(Topic::FinalizedCheckpoint) {
+				let epoch = backend.state_at(&head).map_err(Error::backend)?
+					.state().finalized_checkpoint.epoch;
+				let state = backend.block_at(&new).map_err(Error::backend)?.0.state_root;
+				events.send(Topic::FinalizedCheckpoint, &FinalizedCheckpointEvent {
+					block: new,
+					state,
+					epoch,
+				});
+			}
+		},
+		ImportNotification::JustifiedChanged { .. } |
+		ImportNotification::BlockRejected { .. } => (),
+	}
+
+	Ok(())
+}
+
+/// Start sending `block`, `head`, `chain_reorg` and `finalized_checkpoint`
+/// events for importer notifications on a new thread, until the importer is
+/// dropped.
+pub fn start_event_thread<B, C: Config>(
+	backend: B,
+	notifications: mpsc::Receiver<ImportNotification<H256>>,
+	events: EventSender,
+) -> thread::JoinHandle<()> where
+	B: ChainQuery + Store<Block=Block<C>> + Send + 'static,
+	B::State: StateExternalities<Config=C>,
+{
+	thread::spawn(move || {
+		for notification in notifications {
+			if let Err(err) = send_notification_events(&backend, &events, notification.clone()) {
+				warn!("Unable to send events of {:?}: {}", notification, err);
+			}
+		}
+	})
+}
@@ -0,0 +1,249 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Parity Shasper.
+
+// Parity Shasper is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+
+// Parity Shasper is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more
+// details.
+
+// You should have received a copy of the GNU General Public License along with
+// Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
+//! HTTP API of the node, serving the standard Beacon API under `/eth/v1`.
+//! Besides reading chain and node state, validator clients use it to fetch
+//! duties, produce blocks and attestations, and submit them back, and tools
+//! can follow the chain through its event stream.
+
+mod chain;
+mod events;
+mod node;
+mod validator;
+
+pub use events::{EventSender, Topic, start_event_thread};
+
+use beacon::{Config, BLSConfig};
+use beacon::primitives::{Signature, Uint};
+use beacon::types::Eth1Data;
+use blockchain::AsExternalities;
+use blockchain::backend::{ChainQuery, Store};
+use blockchain::import::SharedBlockImporter;
+use shasper_eth1::Eth1Follower;
+use shasper_network::{PubsubSender, SharedNetworkStatus};
+use serde::{Serialize, Deserialize};
+use tiny_http::{Server, Request, Response, Method, Header};
+use std::collections::HashMap;
+use std::io::Read;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use log::{info, warn};
+use crate::{Error, Block, OperationPool, StateExternalities};
+use crate::clock::SlotClock;
+
+/// Number of threads serving API requests, so that a slow request such as
+/// block production does not hold up others arriving in the same slot.
+const API_WORKERS: usize = 4;
+
+/// Everything the API needs from the node.
+pub struct ApiContext<B, I, C: Config, BLS: BLSConfig> {
+	pub backend: B,
+	pub importer: I,
+	pub pool: Arc<Mutex<OperationPool<C, BLS>>>,
+	pub eth1: Option<Arc<RwLock<Eth1Follower<C>>>>,
+	pub eth1_data: Eth1Data,
+	pub network: SharedNetworkStatus,
+	pub publish: PubsubSender<C>,
+	pub clock: SlotClock<C>,
+	pub events: EventSender,
+}
+
+/// Response body of the standard Beacon API.
+#[derive(Serialize)]
+struct Data<T> {
+	data: T,
+}
+
+/// Error response body of the standard Beacon API.
+#[derive(Serialize)]
+struct ErrorMessage {
+	code: u16,
+	message: String,
+}
+
+/// Signed message of the standard Beacon API.
+#[derive(Serialize, Deserialize)]
+struct SignedMessage<T> {
+	message: T,
+	signature: Signature,
+}
+
+enum ApiError {
+	NotFound,
+	BadRequest(String),
+	Internal(String),
+}
+
+impl From<Error> for ApiError {
+	fn from(error: Error) -> ApiError {
+		match error {
+			Error::Beacon(error) => ApiError::BadRequest(format!("{:?}", error)),
+			error => ApiError::Internal(format!("{}", error)),
+		}
+	}
+}
+
+fn query_params(query: &str) -> HashMap<String, String> {
+	url::form_urlencoded::parse(query.as_bytes()).into_owned().collect()
+}
+
+fn param<'a>(params: &'a HashMap<String, String>, name: &str) -> Result<&'a str, ApiError> {
+	params.get(name)
+		.map(|value| value.as_str())
+		.ok_or_else(|| ApiError::BadRequest(format!("Missing parameter {}", name)))
+}
+
+fn parse_uint(value: &str) -> Result<Uint, ApiError> {
+	value.parse().map_err(|_| ApiError::BadRequest(format!("Invalid integer {}", value)))
+}
+
+fn parse_bytes(value: &str, len: usize) -> Result<Vec<u8>, ApiError> {
+	let bytes = hex::decode(value.trim_start_matches("0x"))
+		.map_err(|_| ApiError::BadRequest(format!("Invalid hex {}", value)))?;
+	if bytes.len() != len {
+		return Err(ApiError::BadRequest(format!("Invalid length of {}", value)))
+	}
+	Ok(bytes)
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(request: &mut Request) -> Result<T, ApiError> {
+	let mut body = String::new();
+	request.as_reader().read_to_string(&mut body)
+		.map_err(|e| ApiError::BadRequest(format!("Unable to read body: {}", e)))?;
+	serde_json::from_str(&body)
+		.map_err(|e| ApiError::BadRequest(format!("Invalid JSON body: {}", e)))
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, ApiError> {
+	serde_json::to_string(value).map_err(|e| ApiError::Internal(format!("{}", e)))
+}
+
+fn to_data_json<T: Serialize>(data: T) -> Result<String, ApiError> {
+	to_json(&Data { data })
+}
+
+fn backend_error<E: std::fmt::Debug>(error: E) -> ApiError {
+	ApiError::Internal(format!("{:?}", error))
+}
+
+fn handle<B, I, C: Config, BLS: BLSConfig>(
+	context: &ApiContext<B, I, C, BLS>,
+	request: &mut Request,
+) -> Result<String, ApiError> where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C> + AsExternalities<dyn StateExternalities<Config=C>>,
+	I: SharedBlockImporter<Block=Block<C>>,
+{
+	let method = request.method().clone();
+	let url = request.url().to_string();
+	let mut parts = url.splitn(2, '?');
+	let path = parts.next().unwrap_or("");
+	let params = query_params(parts.next().unwrap_or(""));
+
+	match (method, path) {
+		(Method::Post, "/eth/v1/beacon/blocks") =>
+			validator::publish_block(context, request),
+		(Method::Post, "/eth/v1/beacon/pool/attestations") =>
+			validator::publish_attestations(context, request),
+		(Method::Post, "/eth/v1/beacon/pool/voluntary_exits") =>
+			validator::publish_voluntary_exit(context, request),
+		(Method::Post, "/eth/v1/beacon/pool/proposer_slashings") =>
+			validator::publish_proposer_slashing(context, request),
+		(Method::Post, "/eth/v1/beacon/pool/attester_slashings") =>
+			validator::publish_attester_slashing(context, request),
+		(Method::Post, "/eth/v1/validator/aggregate_and_proofs") =>
+			validator::publish_aggregate_and_proofs(context, request),
+		(Method::Post, path) if path.starts_with("/eth/v1/validator/duties/attester/") =>
+			validator::attester_duties(
+				context, &path["/eth/v1/validator/duties/attester/".len()..], request,
+			),
+		(Method::Get, path) if path.starts_with("/eth/v1/validator/") =>
+			validator::handle(context, &path["/eth/v1/validator/".len()..], &params),
+		(Method::Get, path) if path.starts_with("/eth/v1/beacon/") =>
+			chain::handle(context, &path["/eth/v1/beacon/".len()..], &params),
+		(Method::Get, path) if path.starts_with("/eth/v1/node/") =>
+			node::handle(context, &path["/eth/v1/node/".len()..]),
+		_ => Err(ApiError::NotFound),
+	}
+}
+
+fn respond(request: Request, result: Result<String, ApiError>) {
+	let (status, body) = match result {
+		Ok(body) => (200, body),
+		Err(error) => {
+			let (code, message) = match error {
+				ApiError::NotFound => (404, "Not found".to_string()),
+				ApiError::BadRequest(message) => (400, message),
+				ApiError::Internal(message) => {
+					warn!("Internal API error: {}", message);
+					(500, message)
+				},
+			};
+			let body = serde_json::to_string(&ErrorMessage { code, message })
+				.expect("Error message serialization is infallible; qed");
+			(code, body)
+		},
+	};
+
+	let response = Response::from_string(body)
+		.with_status_code(status)
+		.with_header(
+			Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+				.expect("Header is valid ASCII; qed")
+		);
+	if let Err(err) = request.respond(response) {
+		warn!("Error when sending API response: {}", err);
+	}
+}
+
+/// Start serving the API at `address` on a pool of `API_WORKERS` new
+/// threads.
+pub fn start_api_server<B, I, C: Config, BLS: BLSConfig>(
+	address: &SocketAddr,
+	context: ApiContext<B, I, C, BLS>,
+) -> Result<Vec<thread::JoinHandle<()>>, Error> where
+	B: ChainQuery + Store<Block=Block<C>> + Send + Sync + 'static,
+	B::State: StateExternalities<Config=C> + AsExternalities<dyn StateExternalities<Config=C>>,
+	I: SharedBlockImporter<Block=Block<C>> + Send + Sync + 'static,
+	C: Send + Sync + 'static,
+	BLS: Send + Sync + 'static,
+{
+	let server = Arc::new(Server::http(address)
+		.map_err(|e| Error::Api(format!("Unable to start API server: {}", e)))?);
+	info!("API server listening on {}", address);
+
+	let context = Arc::new(context);
+	Ok((0..API_WORKERS).map(|_| {
+		let server = server.clone();
+		let context = context.clone();
+		thread::spawn(move || {
+			for mut request in server.incoming_requests() {
+				let url = request.url().to_string();
+				let mut parts = url.splitn(2, '?');
+				if *request.method() == Method::Get && parts.next() == Some("/eth/v1/events") {
+					let params = query_params(parts.next().unwrap_or(""));
+					if let Err((request, err)) = events::stream(&context.events, request, &params) {
+						respond(request, Err(err));
+					}
+					continue
+				}
+
+				let result = handle(&*context, &mut request);
+				respond(request, result);
+			}
+		})
+	}).collect())
+}
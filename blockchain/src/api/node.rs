@@ -0,0 +1,117 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Parity Shasper.
+
+// Parity Shasper is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+
+// Parity Shasper is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more
+// details.
+
+// You should have received a copy of the GNU General Public License along with
+// Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
+//! Read endpoints of the standard Beacon API under `/eth/v1/node`.
+
+use beacon::{Config, BLSConfig};
+use beacon::primitives::Uint;
+use beacon::utils;
+use blockchain::backend::{ChainQuery, Store};
+use shasper_network::{PeerInfo, PeerDirection};
+use serde::Serialize;
+use crate::{Block, StateExternalities};
+use super::{ApiContext, ApiError, to_data_json, backend_error};
+
+/// Distance to the best peer head, in slots, up to which the node is not
+/// considered syncing. Peers regularly run a slot ahead while blocks
+/// propagate.
+const SYNC_TOLERANCE: Uint = 1;
+
+#[derive(Serialize)]
+struct SyncingInfo {
+	#[serde(serialize_with = "utils::serialize_uint")]
+	head_slot: Uint,
+	#[serde(serialize_with = "utils::serialize_uint")]
+	sync_distance: Uint,
+	is_syncing: bool,
+}
+
+#[derive(Serialize)]
+struct IdentityInfo {
+	peer_id: String,
+	enr: String,
+	p2p_addresses: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct PeerData {
+	peer_id: String,
+	last_seen_p2p_address: String,
+	state: &'static str,
+	direction: &'static str,
+}
+
+impl PeerData {
+	fn new(peer_id: String, peer: &PeerInfo) -> Self {
+		Self {
+			peer_id,
+			last_seen_p2p_address: peer.address.to_string(),
+			state: "connected",
+			direction: match peer.direction {
+				PeerDirection::Inbound => "inbound",
+				PeerDirection::Outbound => "outbound",
+			},
+		}
+	}
+}
+
+pub(super) fn handle<B, I, C: Config, BLS: BLSConfig>(
+	context: &ApiContext<B, I, C, BLS>,
+	path: &str,
+) -> Result<String, ApiError> where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C>,
+{
+	let status = context.network.read().expect("Network status lock poisoned");
+
+	match path.split('/').collect::<Vec<_>>().as_slice() {
+		["syncing"] => {
+			let head_slot = context.backend.block_at(&context.backend.head())
+				.map_err(backend_error)?.0.slot;
+			let sync_distance = status.best_peer_slot()
+				.map(|slot| slot.saturating_sub(head_slot))
+				.unwrap_or(0);
+			to_data_json(SyncingInfo {
+				head_slot,
+				sync_distance,
+				is_syncing: sync_distance > SYNC_TOLERANCE,
+			})
+		},
+		["identity"] => {
+			let peer_id = status.peer_id.as_ref()
+				.ok_or_else(|| ApiError::Internal("Network is not started".to_string()))?;
+			to_data_json(IdentityInfo {
+				peer_id: peer_id.to_base58(),
+				enr: status.enr.as_ref().map(|enr| enr.to_base64()).unwrap_or_default(),
+				p2p_addresses: status.listen_addresses.iter()
+					.map(|address| format!("{}/p2p/{}", address, peer_id.to_base58()))
+					.collect(),
+			})
+		},
+		["peers"] => {
+			let peers = status.peers.iter()
+				.map(|(peer_id, peer)| PeerData::new(peer_id.to_base58(), peer))
+				.collect::<Vec<_>>();
+			to_data_json(peers)
+		},
+		["peers", peer_id] => {
+			let (peer_id, peer) = status.peers.iter()
+				.find(|(id, _)| id.to_base58() == *peer_id)
+				.ok_or(ApiError::NotFound)?;
+			to_data_json(PeerData::new(peer_id.to_base58(), peer))
+		},
+		_ => Err(ApiError::NotFound),
+	}
+}
@@ -0,0 +1,266 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Parity Shasper.
+
+// Parity Shasper is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+
+// Parity Shasper is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more
+// details.
+
+// You should have received a copy of the GNU General Public License along with
+// Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
+//! Validator endpoints of the standard Beacon API, for fetching duties,
+//! producing blocks and attestations, and submitting them for import and
+//! gossip.
+
+use beacon::{Config, BLSConfig};
+use beacon::primitives::{H256, Signature, Uint};
+use beacon::types::{
+	Attestation, BeaconBlock, SignedAggregateAndProof, UnsealedBeaconBlock, VoluntaryExit,
+	ProposerSlashing, AttesterSlashing,
+};
+use beacon::utils;
+use blockchain::AsExternalities;
+use blockchain::backend::{ChainQuery, Store};
+use blockchain::import::SharedBlockImporter;
+use shasper_network::PubsubMessage;
+use serde::Deserialize;
+use tiny_http::Request;
+use std::collections::HashMap;
+use log::{info, warn};
+use crate::{Block, StateExternalities, production};
+use super::{
+	ApiContext, ApiError, Topic, SignedMessage, to_data_json, read_json, param, parse_uint, parse_bytes,
+};
+
+/// Validator index in a request body, either quoted or not.
+#[derive(Deserialize)]
+struct ValidatorIndex(#[serde(deserialize_with = "utils::deserialize_uint")] Uint);
+
+/// Item of submitted operations that failed.
+struct Failure {
+	index: usize,
+	message: String,
+}
+
+fn publish<B, I, C: Config, BLS: BLSConfig>(
+	context: &ApiContext<B, I, C, BLS>,
+	message: PubsubMessage<C>,
+) {
+	if let Err(err) = context.publish.unbounded_send(message) {
+		warn!("Network is not running, unable to publish: {:?}", err);
+	}
+}
+
+/// Report submitted operations that failed, if any.
+fn failures(failures: Vec<Failure>) -> Result<String, ApiError> {
+	if failures.is_empty() {
+		return Ok(String::new())
+	}
+
+	let message = failures.iter()
+		.map(|failure| format!("{}: {}", failure.index, failure.message))
+		.collect::<Vec<_>>()
+		.join(", ");
+	Err(ApiError::BadRequest(format!("Some items failed: {}", message)))
+}
+
+pub(super) fn handle<B, I, C: Config, BLS: BLSConfig>(
+	context: &ApiContext<B, I, C, BLS>,
+	path: &str,
+	params: &HashMap<String, String>,
+) -> Result<String, ApiError> where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C> + AsExternalities<dyn StateExternalities<Config=C>>,
+{
+	match path.split('/').collect::<Vec<_>>().as_slice() {
+		["duties", "proposer", epoch] => {
+			let epoch = parse_uint(epoch)?;
+			to_data_json(production::proposer_duties(&context.backend, epoch)?)
+		},
+		["blocks", slot] => {
+			let slot = parse_uint(slot)?;
+			let randao_reveal = Signature::from_slice(
+				&parse_bytes(param(params, "randao_reveal")?, 96)?
+			);
+			let graffiti = match params.get("graffiti") {
+				Some(graffiti) => H256::from_slice(&parse_bytes(graffiti, 32)?),
+				None => H256::default(),
+			};
+
+			let mut pool = context.pool.lock().expect("Operation pool lock poisoned");
+			let block = production::produce_block(
+				&context.backend,
+				&mut pool,
+				context.eth1.as_ref().map(|eth1| eth1.as_ref()),
+				&context.eth1_data,
+				slot,
+				randao_reveal,
+				graffiti,
+			)?;
+			to_data_json(UnsealedBeaconBlock::from(&block))
+		},
+		["attestation_data"] => {
+			let slot = parse_uint(param(params, "slot")?)?;
+			let index = parse_uint(param(params, "committee_index")?)?;
+			to_data_json(production::attestation_data(&context.backend, slot, index)?)
+		},
+		["aggregate_attestation"] => {
+			let data_root = H256::from_slice(
+				&parse_bytes(param(params, "attestation_data_root")?, 32)?
+			);
+			let slot = parse_uint(param(params, "slot")?)?;
+			let pool = context.pool.lock().expect("Operation pool lock poisoned");
+			let aggregate = pool.attestations.aggregate_by_root(&data_root)
+				.filter(|aggregate| aggregate.data.slot == slot)
+				.ok_or(ApiError::NotFound)?;
+			to_data_json(aggregate)
+		},
+		_ => Err(ApiError::NotFound),
+	}
+}
+
+/// Attestation duties in an epoch of the validators in the request body.
+pub(super) fn attester_duties<B, I, C: Config, BLS: BLSConfig>(
+	context: &ApiContext<B, I, C, BLS>,
+	epoch: &str,
+	request: &mut Request,
+) -> Result<String, ApiError> where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C>,
+{
+	let epoch = parse_uint(epoch)?;
+	let indices = read_json::<Vec<ValidatorIndex>>(request)?.into_iter()
+		.map(|index| index.0)
+		.collect::<Vec<_>>();
+	to_data_json(production::attester_duties(&context.backend, epoch, &indices)?)
+}
+
+/// Import a signed block, and publish it on gossip.
+pub(super) fn publish_block<B, I, C: Config, BLS: BLSConfig>(
+	context: &ApiContext<B, I, C, BLS>,
+	request: &mut Request,
+) -> Result<String, ApiError> where
+	I: SharedBlockImporter<Block=Block<C>>,
+{
+	let signed = read_json::<SignedMessage<UnsealedBeaconBlock<C>>>(request)?;
+	let block = BeaconBlock {
+		signature: signed.signature,
+		..signed.message.fake_seal()
+	};
+	info!("Importing block at slot {} submitted over the API", block.slot);
+
+	context.importer.import_block(Block(block.clone()))
+		.map_err(|e| ApiError::BadRequest(format!("Block import failed: {:?}", e)))?;
+	publish(context, PubsubMessage::Block(block));
+	Ok(String::new())
+}
+
+/// Add signed attestations to the pool, and publish them on gossip.
+pub(super) fn publish_attestations<B, I, C: Config, BLS: BLSConfig>(
+	context: &ApiContext<B, I, C, BLS>,
+	request: &mut Request,
+) -> Result<String, ApiError> where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C>,
+{
+	let attestations = read_json::<Vec<Attestation<C>>>(request)?;
+	let mut pool = context.pool.lock().expect("Operation pool lock poisoned");
+
+	let mut failed = Vec::new();
+	for (index, attestation) in attestations.into_iter().enumerate() {
+		match production::submit_attestation(&context.backend, &mut pool, attestation.clone()) {
+			Ok(()) => {
+				context.events.send(Topic::Attestation, &attestation);
+				publish(context, PubsubMessage::Attestation(attestation));
+			},
+			Err(err) => failed.push(Failure { index, message: format!("{}", err) }),
+		}
+	}
+	failures(failed)
+}
+
+/// Validate signed aggregates and add them to the pool, and publish them on
+/// gossip.
+pub(super) fn publish_aggregate_and_proofs<B, I, C: Config, BLS: BLSConfig>(
+	context: &ApiContext<B, I, C, BLS>,
+	request: &mut Request,
+) -> Result<String, ApiError> where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C>,
+{
+	let aggregates = read_json::<Vec<SignedAggregateAndProof<C>>>(request)?;
+	let current_slot = context.clock.now()
+		.ok_or_else(|| ApiError::BadRequest("Chain has not started".to_string()))?;
+	let mut pool = context.pool.lock().expect("Operation pool lock poisoned");
+
+	let mut failed = Vec::new();
+	for (index, signed) in aggregates.into_iter().enumerate() {
+		match production::submit_aggregate_and_proof(
+			&context.backend, &mut pool, current_slot, current_slot, signed.clone(),
+		) {
+			Ok(()) => {
+				context.events.send(Topic::Attestation, &signed.message.aggregate);
+				publish(context, PubsubMessage::AggregateAndProof(signed));
+			},
+			Err(err) => failed.push(Failure { index, message: format!("{}", err) }),
+		}
+	}
+	failures(failed)
+}
+
+/// Validate a signed voluntary exit and add it to the pool, and publish it on
+/// gossip.
+pub(super) fn publish_voluntary_exit<B, I, C: Config, BLS: BLSConfig>(
+	context: &ApiContext<B, I, C, BLS>,
+	request: &mut Request,
+) -> Result<String, ApiError> where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C>,
+{
+	let exit = read_json::<VoluntaryExit>(request)?;
+	let mut pool = context.pool.lock().expect("Operation pool lock poisoned");
+	if production::submit_voluntary_exit(&context.backend, &mut pool, exit.clone())? {
+		context.events.send(Topic::VoluntaryExit, &exit);
+		publish(context, PubsubMessage::VoluntaryExit(exit));
+	}
+	Ok(String::new())
+}
+
+/// Validate a proposer slashing and add it to the pool, and publish it on
+/// gossip.
+pub(super) fn publish_proposer_slashing<B, I, C: Config, BLS: BLSConfig>(
+	context: &ApiContext<B, I, C, BLS>,
+	request: &mut Request,
+) -> Result<String, ApiError> where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C>,
+{
+	let slashing = read_json::<ProposerSlashing>(request)?;
+	let mut pool = context.pool.lock().expect("Operation pool lock poisoned");
+	if production::submit_proposer_slashing(&context.backend, &mut pool, slashing.clone())? {
+		publish(context, PubsubMessage::ProposerSlashing(slashing));
+	}
+	Ok(String::new())
+}
+
+/// Validate an attester slashing and add it to the pool, and publish it on
+/// gossip.
+pub(super) fn publish_attester_slashing<B, I, C: Config, BLS: BLSConfig>(
+	context: &ApiContext<B, I, C, BLS>,
+	request: &mut Request,
+) -> Result<String, ApiError> where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C>,
+{
+	let slashing = read_json::<AttesterSlashing<C>>(request)?;
+	let mut pool = context.pool.lock().expect("Operation pool lock poisoned");
+	if production::submit_attester_slashing(&context.backend, &mut pool, slashing.clone())? {
+		publish(context, PubsubMessage::AttesterSlashing(slashing));
+	}
+	Ok(String::new())
+}
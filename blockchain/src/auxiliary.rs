@@ -0,0 +1,43 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Parity Shasper.
+
+// Parity Shasper is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+
+// Parity Shasper is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more
+// details.
+
+// You should have received a copy of the GNU General Public License along with
+// Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
+use blockchain::Auxiliary;
+use parity_codec::{Encode, Decode};
+use beacon::Config;
+use crate::Block;
+
+/// Key of an auxiliary item stored in the backend.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug, Encode, Decode)]
+pub enum AuxiliaryKey {
+	/// Operation pools of the local node.
+	OperationPool,
+}
+
+/// Auxiliary item stored in the backend alongside blocks and states.
+#[derive(Clone, Eq, PartialEq, Debug, Encode, Decode)]
+pub enum ShasperAuxiliary {
+	/// SSZ-encoded operation pools of the local node.
+	OperationPool(Vec<u8>),
+}
+
+impl<C: Config> Auxiliary<Block<C>> for ShasperAuxiliary {
+	type Key = AuxiliaryKey;
+
+	fn key(&self) -> AuxiliaryKey {
+		match self {
+			ShasperAuxiliary::OperationPool(_) => AuxiliaryKey::OperationPool,
+		}
+	}
+}
@@ -0,0 +1,143 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Parity Shasper.
+
+// Parity Shasper is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+
+// Parity Shasper is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more
+// details.
+
+// You should have received a copy of the GNU General Public License along with
+// Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
+//! Wall-clock slot timing derived from the genesis time.
+
+use beacon::Config;
+use beacon::primitives::Slot;
+use core::marker::PhantomData;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use log::info;
+
+/// Maximum amount by which clocks of other nodes are tolerated to be ahead
+/// of the local one.
+pub const MAXIMUM_CLOCK_DISPARITY: Duration = Duration::from_millis(500);
+
+/// Time since the unix epoch.
+pub fn unix_now() -> Duration {
+	SystemTime::now().duration_since(UNIX_EPOCH)
+		.expect("System time is after unix epoch; qed")
+}
+
+/// Slot clock, mapping wall-clock time to slots.
+#[derive(Clone, Debug)]
+pub struct SlotClock<C: Config> {
+	genesis_time: u64,
+	_marker: PhantomData<C>,
+}
+
+impl<C: Config> SlotClock<C> {
+	pub fn new(genesis_time: u64) -> Self {
+		Self { genesis_time, _marker: PhantomData }
+	}
+
+	pub fn genesis_time(&self) -> u64 {
+		self.genesis_time
+	}
+
+	/// Slot at the given time since the unix epoch, or `None` if it is
+	/// before genesis.
+	pub fn slot_at(&self, time: Duration) -> Option<Slot> {
+		let since_genesis = time.checked_sub(Duration::from_secs(self.genesis_time))?;
+		Some(C::genesis_slot() + since_genesis.as_secs() / C::seconds_per_slot())
+	}
+
+	/// Start of the given slot, as time since the unix epoch.
+	pub fn start_of(&self, slot: Slot) -> Duration {
+		Duration::from_secs(
+			self.genesis_time + (slot - C::genesis_slot()) * C::seconds_per_slot()
+		)
+	}
+
+	/// Current slot, or `None` before genesis.
+	pub fn now(&self) -> Option<Slot> {
+		self.slot_at(unix_now())
+	}
+
+	/// Current slot, allowing clocks of other nodes to be ahead by up to
+	/// `MAXIMUM_CLOCK_DISPARITY`.
+	pub fn now_with_disparity(&self) -> Option<Slot> {
+		self.slot_at(unix_now() + MAXIMUM_CLOCK_DISPARITY)
+	}
+
+	/// Current slot, allowing clocks of other nodes to be behind by up to
+	/// `MAXIMUM_CLOCK_DISPARITY`.
+	pub fn now_with_past_disparity(&self) -> Option<Slot> {
+		self.slot_at(unix_now().checked_sub(MAXIMUM_CLOCK_DISPARITY)?)
+	}
+
+	/// Time left until the start of the given slot, zero if it has started.
+	pub fn duration_to_slot(&self, slot: Slot) -> Duration {
+		self.start_of(slot).checked_sub(unix_now()).unwrap_or_default()
+	}
+
+	/// Offset into a slot at which attestations are produced, one third of
+	/// the slot.
+	pub fn attestation_offset() -> Duration {
+		Duration::from_secs(C::seconds_per_slot()) / 3
+	}
+
+	/// Offset into a slot at which aggregates are produced, two thirds of
+	/// the slot.
+	pub fn aggregation_offset() -> Duration {
+		Duration::from_secs(C::seconds_per_slot()) * 2 / 3
+	}
+
+	/// Time left until `offset` into the given slot, zero if it has passed.
+	pub fn duration_until(&self, slot: Slot, offset: Duration) -> Duration {
+		(self.start_of(slot) + offset).checked_sub(unix_now()).unwrap_or_default()
+	}
+
+	/// Block the current thread until `offset` into the given slot.
+	pub fn wait_until(&self, slot: Slot, offset: Duration) {
+		thread::sleep(self.duration_until(slot, offset));
+	}
+
+	/// Block the current thread until genesis.
+	pub fn wait_for_genesis(&self) {
+		let wait = self.duration_to_slot(C::genesis_slot());
+		if wait > Duration::from_secs(0) {
+			info!("Waiting {} seconds for genesis", wait.as_secs());
+			thread::sleep(wait);
+		}
+	}
+
+	/// Block the current thread until the next slot starts, and return it.
+	pub fn wait_for_next_slot(&self) -> Slot {
+		let next = self.now().map(|slot| slot + 1).unwrap_or(C::genesis_slot());
+		thread::sleep(self.duration_to_slot(next));
+		next
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use beacon::MinimalConfig;
+
+	#[test]
+	fn slots_follow_genesis_time() {
+		let clock = SlotClock::<MinimalConfig>::new(1000);
+		let seconds_per_slot = MinimalConfig::seconds_per_slot();
+
+		assert_eq!(clock.slot_at(Duration::from_secs(999)), None);
+		assert_eq!(clock.slot_at(Duration::from_secs(1000)), Some(0));
+		assert_eq!(clock.slot_at(Duration::from_secs(1000 + seconds_per_slot - 1)), Some(0));
+		assert_eq!(clock.slot_at(Duration::from_secs(1000 + 2 * seconds_per_slot)), Some(2));
+		assert_eq!(clock.start_of(3), Duration::from_secs(1000 + 3 * seconds_per_slot));
+		assert_eq!(clock.slot_at(clock.start_of(5)), Some(5));
+	}
+}
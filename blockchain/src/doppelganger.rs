@@ -0,0 +1,282 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Parity Shasper.
+
+// Parity Shasper is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+
+// Parity Shasper is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more
+// details.
+
+// You should have received a copy of the GNU General Public License along with
+// Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Doppelganger detection. Before local validators start signing, the node
+//! watches gossip and blocks for a number of epochs for messages signed by
+//! their keys. If any shows up, the same keys are running elsewhere, and
+//! signing is never enabled.
+
+use beacon::{Config, BeaconExecutive, utils};
+use beacon::primitives::{H256, ValidatorId, Uint};
+use beacon::types::Attestation;
+use blockchain::Block as BlockT;
+use blockchain::backend::{Store, ChainQuery};
+use std::collections::HashSet;
+use core::marker::PhantomData;
+use log::warn;
+use crate::{Error, Block, StateExternalities};
+use crate::production::head_state_at;
+
+/// Signing status of local validators.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Status {
+	/// Still watching for doppelgangers until the given epoch.
+	Watching(Uint),
+	/// No doppelganger was found, and signing is enabled.
+	Enabled,
+	/// A message of the given validator index was signed elsewhere.
+	Detected(Uint),
+}
+
+/// Doppelganger detector of local validators.
+pub struct DoppelgangerDetector<C: Config> {
+	indices: HashSet<Uint>,
+	start_epoch: Uint,
+	end_epoch: Uint,
+	detected: Option<Uint>,
+	scanned: HashSet<H256>,
+	_marker: PhantomData<C>,
+}
+
+impl<C: Config> DoppelgangerDetector<C> {
+	/// Watch for messages of `pubkeys` from `start_epoch` on, for `epochs`
+	/// epochs.
+	pub fn new<B>(
+		backend: &B,
+		pubkeys: &[ValidatorId],
+		start_epoch: Uint,
+		epochs: Uint,
+	) -> Result<Self, Error> where
+		B: ChainQuery + Store<Block=Block<C>>,
+		B::State: StateExternalities<Config=C>,
+	{
+		let mut state = backend.state_at(&backend.head())
+			.map_err(Error::backend)?;
+		let executive = BeaconExecutive::new(state.state_mut());
+		let indices = pubkeys.iter()
+			.filter_map(|pubkey| executive.validator_index(pubkey))
+			.collect();
+
+		Ok(Self {
+			indices,
+			start_epoch,
+			end_epoch: start_epoch + epochs,
+			detected: None,
+			scanned: HashSet::new(),
+			_marker: PhantomData,
+		})
+	}
+
+	/// Signing status at `current_epoch`.
+	pub fn status(&self, current_epoch: Uint) -> Status {
+		match self.detected {
+			Some(index) => Status::Detected(index),
+			None if current_epoch < self.end_epoch => Status::Watching(self.end_epoch),
+			None => Status::Enabled,
+		}
+	}
+
+	fn observe<I: IntoIterator<Item=Uint>>(&mut self, indices: I) {
+		if self.detected.is_some() {
+			return
+		}
+
+		if let Some(index) = indices.into_iter().find(|index| self.indices.contains(index)) {
+			warn!("Validator {} signed a message elsewhere, local signing will not be enabled", index);
+			self.detected = Some(index);
+		}
+	}
+
+	/// Observe an attestation received on gossip. Its signature is not
+	/// checked, so it must have passed gossip validation first.
+	pub fn observe_attestation<B>(
+		&mut self,
+		backend: &B,
+		attestation: &Attestation<C>,
+	) -> Result<(), Error> where
+		B: ChainQuery + Store<Block=Block<C>>,
+		B::State: StateExternalities<Config=C>,
+	{
+		if attestation.data.target.epoch < self.start_epoch ||
+			attestation.data.target.epoch >= self.end_epoch
+		{
+			return Ok(())
+		}
+
+		let (_, mut state) = head_state_at(backend, attestation.data.slot)?;
+		let executive = BeaconExecutive::new(state.state_mut());
+		let indexed = executive.indexed_attestation(attestation.clone())?;
+		self.observe(
+			indexed.custody_bit_0_indices.iter().cloned()
+				.chain(indexed.custody_bit_1_indices.iter().cloned())
+		);
+
+		Ok(())
+	}
+
+	/// Observe proposers of, and attestations included in, blocks of the
+	/// canonical chain not yet scanned.
+	///
+	/// Attestations are only considered in blocks at least an epoch after
+	/// the start of detection, so that all of them were made after it.
+	pub fn scan_chain<B>(&mut self, backend: &B) -> Result<(), Error> where
+		B: ChainQuery + Store<Block=Block<C>>,
+		B::State: StateExternalities<Config=C>,
+	{
+		let start_slot = utils::start_slot_of_epoch::<C>(self.start_epoch);
+		let end_slot = utils::start_slot_of_epoch::<C>(self.end_epoch);
+
+		let mut id = backend.head();
+		while !self.scanned.contains(&id) {
+			let block = backend.block_at(&id).map_err(Error::backend)?;
+			let parent_id = match block.parent_id() {
+				Some(parent_id) => parent_id,
+				None => break,
+			};
+			if block.0.slot < start_slot {
+				break
+			}
+			self.scanned.insert(id);
+
+			if block.0.slot < end_slot {
+				let mut state = backend.state_at(&parent_id)
+					.map_err(Error::backend)?;
+				beacon::initialize_block::<C>(state.state_mut(), block.0.slot)?;
+				let executive = BeaconExecutive::new(state.state_mut());
+
+				self.observe(Some(executive.beacon_proposer_index()?));
+				if block.0.slot >= start_slot + C::slots_per_epoch() {
+					let votes = executive.block_vote_targets(&block.0)?;
+					self.observe(votes.into_iter().map(|(index, _)| index));
+				}
+			}
+
+			id = parent_id;
+		}
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use beacon::primitives::Signature;
+	use beacon::types::{AttestationData, Checkpoint};
+	use blockchain::backend::ImportLock;
+	use crate::production::unaggregated_attestation;
+	use crate::testing::{self, TestConfig, TestBackend};
+
+	const VALIDATORS: u64 = 16;
+
+	fn pubkeys(indices: &[u64]) -> Vec<ValidatorId> {
+		indices.iter()
+			.map(|i| crate::genesis::validator_id(&crypto::interop::secret(*i)))
+			.collect()
+	}
+
+	fn attestation(
+		backend: &TestBackend,
+		slot: Uint,
+		target_epoch: Uint,
+	) -> (Uint, Attestation<TestConfig>) {
+		let (_, mut state) = head_state_at(backend, slot).unwrap();
+		let executive = BeaconExecutive::new(state.state_mut());
+		let committee = executive.beacon_committee(slot, 0).unwrap();
+		let data = AttestationData {
+			slot,
+			index: 0,
+			target: Checkpoint { epoch: target_epoch, ..Default::default() },
+			..Default::default()
+		};
+
+		(committee[0], unaggregated_attestation(data, Signature::default(), 0, committee.len()))
+	}
+
+	#[test]
+	fn status_follows_detection_window() {
+		let backend = testing::backend(testing::genesis_state(VALIDATORS));
+		let mut detector = DoppelgangerDetector::<TestConfig>::new(
+			&backend, &pubkeys(&[0, 1]), 1, 2,
+		).unwrap();
+
+		assert_eq!(detector.status(1), Status::Watching(3));
+		assert_eq!(detector.status(2), Status::Watching(3));
+		assert_eq!(detector.status(3), Status::Enabled);
+
+		detector.observe(vec![5, 1]);
+		assert_eq!(detector.status(2), Status::Detected(1));
+		assert_eq!(detector.status(3), Status::Detected(1));
+	}
+
+	#[test]
+	fn attestations_are_observed_within_window() {
+		let backend = testing::backend(testing::genesis_state(VALIDATORS));
+		let slot = TestConfig::slots_per_epoch() + 1;
+		let (index, within) = attestation(&backend, slot, 1);
+		let (_, before) = attestation(&backend, slot, 0);
+		let (_, after) = attestation(&backend, slot, 3);
+
+		let mut detector = DoppelgangerDetector::<TestConfig>::new(
+			&backend, &pubkeys(&[index]), 1, 2,
+		).unwrap();
+		detector.observe_attestation(&backend, &before).unwrap();
+		detector.observe_attestation(&backend, &after).unwrap();
+		assert_eq!(detector.status(1), Status::Watching(3));
+
+		let mut others = DoppelgangerDetector::<TestConfig>::new(
+			&backend, &pubkeys(&[(index + 1) % VALIDATORS]), 1, 2,
+		).unwrap();
+		others.observe_attestation(&backend, &within).unwrap();
+		assert_eq!(others.status(1), Status::Watching(3));
+
+		detector.observe_attestation(&backend, &within).unwrap();
+		assert_eq!(detector.status(1), Status::Detected(index));
+	}
+
+	#[test]
+	fn chain_scan_observes_proposers_after_start() {
+		let state = testing::genesis_state(VALIDATORS);
+		let backend = testing::backend(state.clone());
+		let import_lock = ImportLock::new();
+		let genesis = backend.head();
+		let slots_per_epoch = TestConfig::slots_per_epoch();
+
+		let proposer_at = |slot| {
+			let mut state = state.clone();
+			beacon::initialize_block::<TestConfig>(&mut state, slot).unwrap();
+			BeaconExecutive::new(&mut state).beacon_proposer_index().unwrap()
+		};
+
+		let early = testing::insert_block(
+			&backend, &import_lock, genesis, slots_per_epoch - 1, state.clone(), true,
+		);
+		let mut detector = DoppelgangerDetector::<TestConfig>::new(
+			&backend, &pubkeys(&[proposer_at(slots_per_epoch - 1)]), 1, 2,
+		).unwrap();
+		detector.scan_chain(&backend).unwrap();
+		assert_eq!(detector.status(1), Status::Watching(3));
+
+		testing::insert_block(
+			&backend, &import_lock, early, slots_per_epoch + 1, state.clone(), true,
+		);
+		let mut detector = DoppelgangerDetector::<TestConfig>::new(
+			&backend, &pubkeys(&[proposer_at(slots_per_epoch + 1)]), 1, 2,
+		).unwrap();
+		detector.scan_chain(&backend).unwrap();
+		assert_eq!(detector.status(1), Status::Detected(proposer_at(slots_per_epoch + 1)));
+	}
+}
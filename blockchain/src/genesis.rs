@@ -0,0 +1,150 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Parity Shasper.
+
+// Parity Shasper is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+
+// Parity Shasper is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more
+// details.
+
+// You should have received a copy of the GNU General Public License along with
+// Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
+//! Genesis state generation for development and interop networks.
+
+use beacon::{Config, BLSConfig, BeaconState, Error as BeaconError};
+use beacon::primitives::{H256, ValidatorId, Signature};
+use beacon::types::{Deposit, DepositData, SigningDepositData};
+use beacon::utils::DepositTree;
+use core::convert::TryInto;
+use serde::{Serialize, Deserialize};
+use bm_le::tree_root;
+use crypto::bls;
+
+/// Eth1 block hash used for interop genesis.
+pub const INTEROP_ETH1_BLOCK_HASH: [u8; 32] = [0x42; 32];
+/// Eth1 timestamp used for interop genesis.
+pub const INTEROP_ETH1_TIMESTAMP: u64 = 1 << 40;
+/// Default genesis time of interop genesis states, so that nodes started
+/// separately agree on the genesis state.
+pub const INTEROP_GENESIS_TIME: u64 = 1_578_009_600;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+/// Validator key pair, as stored in validator keys YAML files.
+pub struct ValidatorKey {
+	pub privkey: String,
+	pub pubkey: String,
+}
+
+fn string_to_bytes(string: &str) -> Result<Vec<u8>, String> {
+	let string = if string.starts_with("0x") {
+		&string[2..]
+	} else {
+		string
+	};
+
+	hex::decode(string).map_err(|e| format!("Unable to decode public or private key: {}", e))
+}
+
+impl ValidatorKey {
+	/// Create a key entry from a secret key.
+	pub fn from_secret(secret: &bls::Secret) -> Self {
+		// Secret keys are smaller than the curve order, so only the last 32
+		// bytes of the big-endian encoding are meaningful.
+		let secret_bytes = secret.as_bytes();
+
+		Self {
+			privkey: format!("0x{}", hex::encode(&secret_bytes[(secret_bytes.len() - 32)..])),
+			pubkey: format!("0x{}", hex::encode(&bls::Public::from_secret_key(secret).as_bytes()[..])),
+		}
+	}
+
+	/// Decode the secret key of the entry.
+	pub fn secret(&self) -> Result<bls::Secret, String> {
+		const PRIVATE_KEY_BYTES: usize = 48;
+
+		let privkey = string_to_bytes(&self.privkey)?;
+		if privkey.len() > PRIVATE_KEY_BYTES {
+			return Err("Private key is too long".to_string())
+		}
+
+		let mut bytes = vec![0; PRIVATE_KEY_BYTES - privkey.len()];
+		bytes.extend_from_slice(&privkey);
+		bls::Secret::from_bytes(&bytes)
+			.map_err(|e| format!("Failed to decode bytes into secret key: {:?}", e))
+	}
+}
+
+/// Public key of a secret key.
+pub fn validator_id(secret: &bls::Secret) -> ValidatorId {
+	ValidatorId::from_slice(&bls::Public::from_secret_key(secret).as_bytes()[..])
+}
+
+/// BLS withdrawal credentials of a withdrawal public key.
+pub fn bls_withdrawal_credentials<C: Config>(withdrawal_pubkey: &ValidatorId) -> H256 {
+	let mut withdrawal_credentials = C::hash(&[withdrawal_pubkey.as_ref()]);
+	withdrawal_credentials[0] = C::bls_withdrawal_prefix_byte();
+	withdrawal_credentials
+}
+
+/// Deposit data for the given key with BLS withdrawal credentials, signed
+/// with the genesis deposit domain.
+pub fn signed_deposit_data<C: Config>(secret: &bls::Secret, amount: u64) -> DepositData {
+	let pubkey = validator_id(secret);
+
+	let mut data = DepositData {
+		withdrawal_credentials: bls_withdrawal_credentials::<C>(&pubkey),
+		pubkey,
+		amount,
+		signature: Default::default(),
+	};
+	data.signature = Signature::from_slice(&bls::Signature::new(
+		&tree_root::<C::Digest, _>(&SigningDepositData::from(data.clone()))[..],
+		beacon::genesis_domain(C::domain_deposit()),
+		secret,
+	).as_bytes()[..]);
+	data
+}
+
+/// Deposits with proofs against the deposit root at the time each of them
+/// was made, as expected by `initialize_beacon_state_from_eth1`.
+pub fn genesis_deposits<C: Config>(deposit_datas: &[DepositData]) -> Vec<Deposit> {
+	let mut deposit_tree = DepositTree::<C>::new();
+	deposit_datas.iter().map(|data| {
+		deposit_tree.push(data.clone());
+		let count = deposit_tree.deposit_count();
+
+		Deposit {
+			proof: deposit_tree.proof(count - 1, count)
+				.expect("Deposit was just pushed to the tree; qed")
+				.try_into().ok()
+				.expect("Proof length is tree depth plus one; qed"),
+			data: data.clone(),
+		}
+	}).collect()
+}
+
+/// Build an interop genesis state from deposit data, overriding the genesis
+/// time.
+pub fn interop_genesis_state<C: Config, BLS: BLSConfig>(
+	deposit_datas: &[DepositData],
+	genesis_time: u64,
+) -> Result<BeaconState<C>, BeaconError> {
+	let mut state = beacon::initialize_beacon_state_from_eth1::<C, BLS>(
+		H256::from(INTEROP_ETH1_BLOCK_HASH),
+		INTEROP_ETH1_TIMESTAMP,
+		&genesis_deposits::<C>(deposit_datas),
+	)?;
+	state.genesis_time = genesis_time;
+	Ok(state)
+}
+
+/// Root of the validator registry at genesis, identifying the chain of a
+/// genesis state.
+pub fn genesis_validators_root<C: Config>(genesis_state: &BeaconState<C>) -> H256 {
+	tree_root::<C::Digest, _>(&genesis_state.validators)
+}
@@ -0,0 +1,306 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Parity Shasper.
+
+// Parity Shasper is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+
+// Parity Shasper is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more
+// details.
+
+// You should have received a copy of the GNU General Public License along with
+// Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Validation of messages received on gossip topics, before they are
+//! forwarded to other peers.
+
+use beacon::{Config, BLSConfig, BeaconExecutive, Error as BeaconError, utils};
+use beacon::primitives::{Uint, Slot, Epoch, ValidatorIndex};
+use beacon::types::{Attestation, BeaconBlock, SignedAggregateAndProof, UnsealedBeaconBlock};
+use blockchain::backend::{Store, ChainQuery};
+use bm_le::tree_root;
+use std::collections::HashSet;
+use crate::{Error, Block, StateExternalities};
+use crate::production::head_state_at;
+
+/// Reason of rejecting a gossip message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GossipError {
+	/// Message is outside of the slot range it may be propagated in.
+	SlotOutOfRange,
+	/// Message votes for a block that is not known.
+	UnknownBlock,
+	/// Aggregator is not a member of the aggregate committee.
+	AggregatorNotInCommittee,
+	/// Selection proof does not select the validator as aggregator.
+	NotAggregator,
+	/// Selection proof is not a valid slot signature.
+	InvalidSelectionProof,
+	/// Signature of the aggregator is invalid.
+	InvalidAggregatorSignature,
+	/// Block is from a slot that has not started yet.
+	FromFuture,
+	/// Block is not after the finalized slot.
+	BeforeFinalized,
+	/// Parent of the block is not known. The block is otherwise valid, and
+	/// its ancestors should be fetched.
+	UnknownParent,
+	/// Parent of the block is not known, and the block is from an epoch
+	/// before the head, so that its proposer cannot be checked.
+	UnknownParentBeforeHead,
+	/// A block of the proposer in the slot was already seen.
+	DuplicateProposal,
+	/// Signature of the proposer is invalid.
+	InvalidProposerSignature,
+	/// Attestation does not have exactly one aggregation bit set.
+	NotUnaggregated,
+	/// An attestation of the validator for the target epoch was already seen.
+	DuplicateVote,
+}
+
+impl GossipError {
+	/// Whether the message is not useful to forward, but is not invalid, so
+	/// that it is ignored instead of rejected.
+	pub fn is_ignored(&self) -> bool {
+		match self {
+			GossipError::SlotOutOfRange | GossipError::UnknownBlock |
+			GossipError::FromFuture | GossipError::BeforeFinalized |
+			GossipError::UnknownParent | GossipError::UnknownParentBeforeHead |
+			GossipError::DuplicateProposal |
+			GossipError::DuplicateVote => true,
+			_ => false,
+		}
+	}
+}
+
+/// Messages already seen on gossip, so that only the first block of a
+/// proposer in a slot, and the first attestation of a validator for a target
+/// epoch, is processed.
+#[derive(Default)]
+pub struct SeenMessages {
+	proposals: HashSet<(ValidatorIndex, Slot)>,
+	votes: HashSet<(ValidatorIndex, Epoch)>,
+}
+
+impl SeenMessages {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Forget messages that can no longer be propagated at `current_slot`.
+	pub fn prune<C: Config>(&mut self, current_slot: Slot) {
+		let current_epoch = utils::epoch_of_slot::<C>(current_slot);
+		self.proposals.retain(|(_, slot)| slot + C::slots_per_epoch() >= current_slot);
+		self.votes.retain(|(_, epoch)| epoch + 1 >= current_epoch);
+	}
+}
+
+/// Validate a block received on the `beacon_block` topic. `latest_slot` is
+/// the latest slot the clock of an honest peer may be at. The block is noted
+/// as seen if it is valid.
+///
+/// If the parent of the block is not known, its proposer is checked against
+/// the head state instead, and `GossipError::UnknownParent` is returned if
+/// it is otherwise valid.
+pub fn validate_block<B, C: Config, BLS: BLSConfig>(
+	backend: &B,
+	seen: &mut SeenMessages,
+	latest_slot: Slot,
+	block: &BeaconBlock<C>,
+) -> Result<(), Error> where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C>,
+{
+	if block.slot > latest_slot {
+		return Err(GossipError::FromFuture.into())
+	}
+
+	let finalized_epoch = backend.state_at(&backend.head())
+		.map_err(Error::backend)?
+		.state().finalized_checkpoint.epoch;
+	if block.slot <= utils::start_slot_of_epoch::<C>(finalized_epoch) {
+		return Err(GossipError::BeforeFinalized.into())
+	}
+
+	let parent_known = backend.contains(&block.parent_root)
+		.map_err(Error::backend)?;
+	let mut state = if parent_known {
+		let mut state = backend.state_at(&block.parent_root)
+			.map_err(Error::backend)?;
+		if block.slot <= state.state().slot {
+			return Err(BeaconError::BlockSlotInvalid.into())
+		}
+		beacon::initialize_block::<C>(state.state_mut(), block.slot)?;
+		state
+	} else {
+		let (_, state) = head_state_at(backend, block.slot)?;
+		if utils::epoch_of_slot::<C>(block.slot) != utils::epoch_of_slot::<C>(state.state().slot) {
+			return Err(GossipError::UnknownParentBeforeHead.into())
+		}
+		state
+	};
+	let executive = BeaconExecutive::new(state.state_mut());
+
+	let proposer_index = executive.beacon_proposer_index_at_slot(block.slot)?;
+	if seen.proposals.contains(&(proposer_index, block.slot)) {
+		return Err(GossipError::DuplicateProposal.into())
+	}
+
+	let proposer = executive.validator_pubkey(proposer_index)
+		.ok_or(BeaconError::IndexOutOfRange)?;
+	if !BLS::verify(
+		&proposer,
+		&tree_root::<C::Digest, _>(&UnsealedBeaconBlock::from(block)),
+		&block.signature,
+		executive.domain(C::domain_beacon_proposer(), None),
+	) {
+		return Err(GossipError::InvalidProposerSignature.into())
+	}
+
+	seen.proposals.insert((proposer_index, block.slot));
+	if !parent_known {
+		return Err(GossipError::UnknownParent.into())
+	}
+	Ok(())
+}
+
+/// Validate an unaggregated attestation received on the
+/// `beacon_attestation` topic. `earliest_slot` and `latest_slot` are the
+/// range of slots the clock of an honest peer may be at. The attester is
+/// noted as seen if the attestation is valid.
+pub fn validate_attestation<B, C: Config, BLS: BLSConfig>(
+	backend: &B,
+	seen: &mut SeenMessages,
+	earliest_slot: Slot,
+	latest_slot: Slot,
+	attestation: &Attestation<C>,
+) -> Result<(), Error> where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C>,
+{
+	let data = &attestation.data;
+	if data.slot > latest_slot ||
+		data.slot + C::attestation_propagation_slot_range() < earliest_slot
+	{
+		return Err(GossipError::SlotOutOfRange.into())
+	}
+
+	if attestation.aggregation_bits.iter().filter(|bit| **bit).count() != 1 {
+		return Err(GossipError::NotUnaggregated.into())
+	}
+
+	if !backend.contains(&data.beacon_block_root).map_err(Error::backend)? {
+		return Err(GossipError::UnknownBlock.into())
+	}
+
+	let (_, mut state) = head_state_at(backend, data.slot)?;
+	let executive = BeaconExecutive::new(state.state_mut());
+	let indexed = executive.indexed_attestation(attestation.clone())?;
+	let attester = indexed.custody_bit_0_indices.iter()
+		.chain(indexed.custody_bit_1_indices.iter())
+		.next()
+		.cloned()
+		.ok_or(GossipError::NotUnaggregated)?;
+	if seen.votes.contains(&(attester, data.target.epoch)) {
+		return Err(GossipError::DuplicateVote.into())
+	}
+
+	if !executive.is_valid_indexed_attestation::<BLS>(&indexed) {
+		return Err(BeaconError::AttestationInvalidSignature.into())
+	}
+
+	seen.votes.insert((attester, data.target.epoch));
+	Ok(())
+}
+
+/// Validate an aggregate received on the `beacon_aggregate_and_proof` topic.
+/// `earliest_slot` and `latest_slot` are the range of slots the clock of an
+/// honest peer may be at.
+pub fn validate_aggregate_and_proof<B, C: Config, BLS: BLSConfig>(
+	backend: &B,
+	earliest_slot: Slot,
+	latest_slot: Slot,
+	signed: &SignedAggregateAndProof<C>,
+) -> Result<(), Error> where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C>,
+{
+	let aggregate_and_proof = &signed.message;
+	let data = &aggregate_and_proof.aggregate.data;
+
+	if data.slot > latest_slot ||
+		data.slot + C::attestation_propagation_slot_range() < earliest_slot
+	{
+		return Err(GossipError::SlotOutOfRange.into())
+	}
+
+	if !backend.contains(&data.beacon_block_root).map_err(Error::backend)? {
+		return Err(GossipError::UnknownBlock.into())
+	}
+
+	let (_, mut state) = head_state_at(backend, data.slot)?;
+	let executive = BeaconExecutive::new(state.state_mut());
+
+	let committee = executive.beacon_committee(data.slot, data.index)?;
+	if !committee.contains(&aggregate_and_proof.index) {
+		return Err(GossipError::AggregatorNotInCommittee.into())
+	}
+
+	if !utils::is_aggregator::<C>(committee.len() as Uint, &aggregate_and_proof.selection_proof) {
+		return Err(GossipError::NotAggregator.into())
+	}
+
+	let aggregator = executive.validator_pubkey(aggregate_and_proof.index)
+		.ok_or(BeaconError::IndexOutOfRange)?;
+	let epoch = utils::epoch_of_slot::<C>(data.slot);
+
+	if !BLS::verify(
+		&aggregator,
+		&tree_root::<C::Digest, _>(&data.slot),
+		&aggregate_and_proof.selection_proof,
+		executive.domain(C::domain_beacon_attester(), Some(epoch)),
+	) {
+		return Err(GossipError::InvalidSelectionProof.into())
+	}
+
+	if !BLS::verify(
+		&aggregator,
+		&tree_root::<C::Digest, _>(aggregate_and_proof),
+		&signed.signature,
+		executive.domain(C::domain_aggregate_and_proof(), Some(epoch)),
+	) {
+		return Err(GossipError::InvalidAggregatorSignature.into())
+	}
+
+	let indexed = executive.indexed_attestation(aggregate_and_proof.aggregate.clone())?;
+	if !executive.is_valid_indexed_attestation::<BLS>(&indexed) {
+		return Err(BeaconError::AttestationInvalidSignature.into())
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use beacon::MinimalConfig;
+
+	#[test]
+	fn seen_messages_are_pruned() {
+		let slots_per_epoch = MinimalConfig::slots_per_epoch();
+		let mut seen = SeenMessages::new();
+		seen.proposals.insert((0, 1));
+		seen.proposals.insert((0, slots_per_epoch + 1));
+		seen.votes.insert((0, 0));
+		seen.votes.insert((0, 1));
+
+		seen.prune::<MinimalConfig>(2 * slots_per_epoch + 1);
+		assert_eq!(seen.proposals.len(), 1);
+		assert!(seen.proposals.contains(&(0, slots_per_epoch + 1)));
+		assert_eq!(seen.votes.len(), 1);
+		assert!(seen.votes.contains(&(0, 1)));
+	}
+}
@@ -0,0 +1,144 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Parity Shasper.
+
+// Parity Shasper is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+
+// Parity Shasper is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more
+// details.
+
+// You should have received a copy of the GNU General Public License along with
+// Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
+//! Loading and writing of EIP-2335 validator keystores, and derivation of
+//! validator keys from a mnemonic.
+
+use beacon::Config;
+use crypto::bls;
+use crypto::keystore::{Keystore, Kdf};
+use crypto::derivation;
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::Path;
+use log::info;
+
+/// Read a password file, ignoring a trailing newline.
+pub fn read_password_file(path: &Path) -> Result<String, String> {
+	let password = fs::read_to_string(path)
+		.map_err(|e| format!("Unable to read password file {}: {}", path.display(), e))?;
+	Ok(password.trim_end_matches(|c| c == '\n' || c == '\r').to_string())
+}
+
+/// Prompt for a password on the terminal.
+pub fn prompt_password(prompt: &str) -> Result<String, String> {
+	rpassword::read_password_from_tty(Some(prompt))
+		.map_err(|e| format!("Unable to read password: {}", e))
+}
+
+/// Load and decrypt all `.json` keystores in a directory. When no password
+/// file is given, the password of each keystore is prompted for.
+pub fn load_keystores(
+	dir: &Path,
+	password_file: Option<&Path>,
+) -> Result<Vec<bls::Secret>, String> {
+	let password = password_file.map(read_password_file).transpose()?;
+
+	let mut paths = fs::read_dir(dir)
+		.map_err(|e| format!("Unable to read keystore directory {}: {}", dir.display(), e))?
+		.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+		.filter(|path| path.extension().map(|ext| ext == "json").unwrap_or(false))
+		.collect::<Vec<_>>();
+	paths.sort();
+
+	let mut secrets = Vec::new();
+	for path in paths {
+		let mut json = String::new();
+		File::open(&path).and_then(|mut file| file.read_to_string(&mut json))
+			.map_err(|e| format!("Unable to read keystore {}: {}", path.display(), e))?;
+		let keystore = Keystore::from_json(&json)
+			.map_err(|e| format!("Invalid keystore {}: {}", path.display(), e))?;
+
+		let password = match password.as_ref() {
+			Some(password) => password.clone(),
+			None => prompt_password(&format!("Password for keystore 0x{}: ", keystore.pubkey))?,
+		};
+		let secret = keystore.decrypt(&password)
+			.map_err(|e| format!("Unable to decrypt keystore {}: {}", path.display(), e))?;
+
+		info!("Loaded keystore 0x{}", keystore.pubkey);
+		secrets.push(secret);
+	}
+
+	Ok(secrets)
+}
+
+/// Encrypt secret keys into keystores written to a directory, one
+/// `keystore-<pubkey>.json` file per key. Each key is paired with its
+/// derivation path, which is empty for keys not derived from a seed.
+pub fn write_keystores(
+	dir: &Path,
+	secrets: &[(bls::Secret, String)],
+	password: &str,
+) -> Result<(), String> {
+	fs::create_dir_all(dir)
+		.map_err(|e| format!("Unable to create keystore directory {}: {}", dir.display(), e))?;
+
+	for (secret, derivation_path) in secrets {
+		let keystore = Keystore::encrypt(secret, password, derivation_path, Kdf::default())
+			.map_err(|e| format!("Unable to encrypt keystore: {}", e))?;
+
+		let path = dir.join(format!("keystore-{}.json", keystore.pubkey));
+		File::create(&path).and_then(|mut file| file.write_all(keystore.to_json().as_bytes()))
+			.map_err(|e| format!("Unable to write keystore {}: {}", path.display(), e))?;
+		info!("Written keystore {}", path.display());
+	}
+
+	Ok(())
+}
+
+/// Public keys and withdrawal credentials of a validator derived from a
+/// seed.
+#[derive(Serialize, Debug)]
+pub struct DerivedValidator {
+	pub index: u32,
+	pub signing_path: String,
+	pub signing_pubkey: String,
+	pub withdrawal_path: String,
+	pub withdrawal_pubkey: String,
+	pub withdrawal_credentials: String,
+}
+
+/// Derive the signing key of the validator at `index` from a seed, along
+/// EIP-2334 paths. The withdrawal key is only used for the withdrawal
+/// credentials and is not returned.
+pub fn derive_validator<C: Config>(
+	seed: &[u8],
+	index: u32,
+) -> Result<(bls::Secret, DerivedValidator), String> {
+	let signing_path = derivation::signing_key_path(index);
+	let withdrawal_path = derivation::withdrawal_key_path(index);
+
+	let derive = |path: &str| derivation::derive_path(seed, path)
+		.map(|sk| derivation::to_secret(&sk))
+		.map_err(|e| format!("Unable to derive key {}: {}", path, e));
+	let signing = derive(&signing_path)?;
+	let withdrawal = derive(&withdrawal_path)?;
+
+	let signing_pubkey = crate::genesis::validator_id(&signing);
+	let withdrawal_pubkey = crate::genesis::validator_id(&withdrawal);
+	let withdrawal_credentials =
+		crate::genesis::bls_withdrawal_credentials::<C>(&withdrawal_pubkey);
+
+	Ok((signing, DerivedValidator {
+		index,
+		signing_path,
+		signing_pubkey: format!("0x{}", hex::encode(&signing_pubkey[..])),
+		withdrawal_path,
+		withdrawal_pubkey: format!("0x{}", hex::encode(&withdrawal_pubkey[..])),
+		withdrawal_credentials: format!("0x{}", hex::encode(&withdrawal_credentials[..])),
+	}))
+}
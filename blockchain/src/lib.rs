@@ -14,10 +14,22 @@
 // You should have received a copy of the GNU General Public License along with
 // Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
 mod pool;
+mod auxiliary;
 pub mod backend;
 pub mod preset;
-
-pub use pool::AttestationPool;
+pub mod genesis;
+pub mod keys;
+pub mod production;
+pub mod api;
+pub mod clock;
+pub mod gossip;
+pub mod slashing_protection;
+pub mod doppelganger;
+#[cfg(test)]
+mod testing;
+
+pub use pool::{AttestationPool, OperationPool};
+pub use auxiliary::{ShasperAuxiliary, AuxiliaryKey};
 pub use shasper_runtime::{Block, StateExternalities};
 
 use beacon::primitives::H256;
@@ -28,6 +40,7 @@ use std::sync::Arc;
 use blockchain::{Block as BlockT, BlockExecutor, AsExternalities};
 use lmd_ghost::JustifiableExecutor;
 use core::marker::PhantomData;
+use crate::clock::SlotClock;
 
 use blockchain_rocksdb::RocksState as RocksStateT;
 
@@ -116,6 +129,10 @@ impl<C: Config> RocksStateT for RocksState<C> {
 #[derive(Debug)]
 pub enum Error {
 	Beacon(BeaconError),
+	Backend(Box<dyn std::error::Error + Send + Sync>),
+	Api(String),
+	BlockFromFuture { slot: u64, current: Option<u64> },
+	Gossip(gossip::GossipError),
 }
 
 impl std::fmt::Display for Error {
@@ -126,20 +143,41 @@ impl std::fmt::Display for Error {
 
 impl std::error::Error for Error { }
 
+impl Error {
+	/// Wrap an error of a backend. Backend error types are not required to be
+	/// thread-safe, so only their description is kept.
+	pub fn backend<E: std::error::Error>(error: E) -> Error {
+		Error::Backend(format!("{:?}", error).into())
+	}
+}
+
 impl From<BeaconError> for Error {
 	fn from(error: BeaconError) -> Error {
 		Error::Beacon(error)
 	}
 }
 
+impl From<gossip::GossipError> for Error {
+	fn from(error: gossip::GossipError) -> Error {
+		Error::Gossip(error)
+	}
+}
+
 #[derive(Clone)]
 pub struct Executor<C: Config, BLS: BLSConfig> {
+	clock: Option<SlotClock<C>>,
 	_marker: PhantomData<(C, BLS)>,
 }
 
 impl<C: Config, BLS: BLSConfig> Executor<C, BLS> {
 	pub fn new() -> Self {
-		Self { _marker: PhantomData }
+		Self { clock: None, _marker: PhantomData }
+	}
+
+	/// Create an executor that rejects blocks from future slots of the given
+	/// clock.
+	pub fn with_clock(clock: SlotClock<C>) -> Self {
+		Self { clock: Some(clock), _marker: PhantomData }
 	}
 
 	pub fn initialize_block(
@@ -187,6 +225,13 @@ impl<C: Config, BLS: BLSConfig> BlockExecutor for Executor<C, BLS> {
 		block: &Block<C>,
 		state: &mut Self::Externalities,
 	) -> Result<(), Error> {
+		if let Some(clock) = self.clock.as_ref() {
+			let current = clock.now_with_disparity();
+			if current.map(|current| block.0.slot > current).unwrap_or(true) {
+				return Err(Error::BlockFromFuture { slot: block.0.slot, current })
+			}
+		}
+
 		Ok(beacon::execute_block::<C, BLS>(&block.0, state.state_mut())?)
 	}
 }
@@ -214,6 +259,18 @@ impl<C: Config, BLS: BLSConfig> JustifiableExecutor for Executor<C, BLS> {
 		}
 	}
 
+	fn finalized_block_id(
+		&self,
+		state: &mut Self::Externalities,
+	) -> Result<Option<<Self::Block as BlockT>::Identifier>, Self::Error> {
+		let finalized_root = state.state().finalized_checkpoint.root;
+		if finalized_root == H256::default() {
+			Ok(None)
+		} else {
+			Ok(Some(finalized_root))
+		}
+	}
+
 	fn votes(
 		&self,
 		block: &Self::Block,
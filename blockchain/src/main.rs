@@ -13,129 +13,55 @@
 
 // You should have received a copy of the GNU General Public License along with
 // Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
-use beacon::{genesis_beacon_state, Config, Inherent, Transaction, BeaconExecutive};
+use beacon::Config;
 use beacon::primitives::*;
 use beacon::types::*;
-use blockchain::{AsExternalities, Auxiliary, Block as BlockT};
+use blockchain::{AsExternalities, Block as BlockT};
 use blockchain::backend::{SharedMemoryBackend, SharedCommittable, ChainQuery, Store, ImportLock, Operation};
 use blockchain::import::{SharedBlockImporter, MutexImporter};
 use blockchain_rocksdb::RocksBackend;
-use shasper_blockchain::{Block, Executor, MemoryState, RocksState, Error, StateExternalities, AttestationPool};
+use shasper_blockchain::{Block, Executor, MemoryState, RocksState, StateExternalities,
+						  OperationPool, ShasperAuxiliary, production, gossip};
+use shasper_blockchain::api::{ApiContext, EventSender, Topic, start_event_thread};
+use shasper_blockchain::clock::{self, SlotClock};
 use shasper_blockchain::preset::Preset;
+use shasper_blockchain::genesis::{self as interop, ValidatorKey};
 use shasper_blockchain::backend::ShasperBackend;
-use shasper_network::NetworkConfig;
-use lmd_ghost::archive::{ArchiveGhostImporter, AncestorQuery};
-use clap::{App, Arg, ArgMatches};
+use shasper_blockchain::slashing_protection::{SlashingProtection, Interchange};
+use shasper_blockchain::doppelganger::{self, DoppelgangerDetector};
+use shasper_network::{
+	NetworkConfig, PubsubMessage, PubsubSender, InboundReceiver, SharedNetworkStatus,
+	Validation, ValidationSender, OrphanSender, PeerId,
+};
+use shasper_eth1::{Eth1Follower, Eth1FollowerConfig};
+use lmd_ghost::archive::{ArchiveGhostImporter, AncestorQuery, ImportNotification};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use libp2p::Multiaddr;
 use std::thread;
+use std::sync::{mpsc, Arc, Mutex, RwLock};
+use std::net::SocketAddr;
 use std::str::FromStr;
 use std::fs::File;
-use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::io::{BufReader, Read, Write};
 use std::collections::HashMap;
 use ssz::Decode;
 use core::time::Duration;
-use core::convert::TryInto;
-use serde::{Serialize, Deserialize};
-use log::{info, warn, trace};
+use log::{info, warn, debug, trace};
 use bm_le::tree_root;
 use crypto::bls;
+use crypto::signer::{Signer, SigningMessage, LocalSigner, RemoteSigner};
 
 type BLS = bls::BLSVerification;
 
-fn deposit_tree<C: Config>(deposits: &[DepositData]) -> Vec<Vec<H256>> {
-	let mut zerohashes = vec![H256::default()];
-	for layer in 1..32 {
-		zerohashes.push(C::hash(&[
-			zerohashes[layer - 1].as_ref(),
-			zerohashes[layer - 1].as_ref(),
-		]));
-	}
-
-	let mut values = deposits.iter().map(|d| {
-		tree_root::<C::Digest, _>(d)
-	}).collect::<Vec<_>>();
-	let values_len = values.len();
-	let mut tree = vec![values.clone()];
-
-	for h in 0..(beacon::consts::DEPOSIT_CONTRACT_TREE_DEPTH as usize) {
-		if values.len() % 2 == 1 {
-			values.push(zerohashes[h]);
-		}
-		let mut new_values = Vec::new();
-		for i in 0..(values.len() / 2) {
-			new_values.push(C::hash(&[
-				values[i * 2].as_ref(),
-				values[i * 2 + 1].as_ref()
-			]));
-		}
-		values = new_values;
-		tree.push(values.clone());
-	}
-	assert!(values.len() == 1);
-	values.push({
-		let mut ret = values_len.to_le_bytes().to_vec();
-		while ret.len() < 32 {
-			ret.push(0);
-		}
-		H256::from_slice(&ret[..])
-	});
-	tree[32].push(values[1]);
-	tree.push(vec![C::hash(&[
-		values[0].as_ref(),
-		values[1].as_ref(),
-	])]);
-	assert!(tree.len() == 34);
-
-	tree
-}
-
-fn deposit_root(tree: &Vec<Vec<H256>>) -> H256 {
-	tree.last().expect("Merkle tree cannot be empty; qed")[0]
-}
-
-fn deposit_proof<C: Config>(tree: &Vec<Vec<H256>>, item_index: usize) -> Vec<H256> {
-	let mut zerohashes = vec![H256::default()];
-	for layer in 1..32 {
-		zerohashes.push(C::hash(&[
-			zerohashes[layer - 1].as_ref(),
-			zerohashes[layer - 1].as_ref(),
-		]));
-	}
-
-	let mut proof = Vec::new();
-	for i in 0..(beacon::consts::DEPOSIT_CONTRACT_TREE_DEPTH as usize) {
-		let subindex = (item_index / 2usize.pow(i as u32)) ^ 1;
-		if subindex < tree[i].len() {
-			proof.push(tree[i][subindex]);
-		} else {
-			proof.push(zerohashes[i]);
-		}
-	}
-	proof.push(tree[32][1]);
-	proof
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(deny_unknown_fields)]
-struct ValidatorKey {
-	pub privkey: String,
-	pub pubkey: String,
-}
-
-fn string_to_bytes(string: &str) -> Result<Vec<u8>, String> {
-    let string = if string.starts_with("0x") {
-        &string[2..]
-    } else {
-        string
-    };
-
-    hex::decode(string).map_err(|e| format!("Unable to decode public or private key: {}", e))
-}
+/// Interval at which the operation pools are persisted while authoring.
+const POOL_PERSIST_INTERVAL: Duration = Duration::from_secs(60);
 
 fn main() {
 	pretty_env_logger::init();
 
 	let matches = App::new("Shasper blockchain client")
+		.setting(AppSettings::SubcommandsNegateReqs)
 		.arg(Arg::with_name("port")
 			 .short("p")
 			 .long("port")
@@ -162,6 +88,14 @@ fn main() {
 			 .long("validator-keys")
 			 .takes_value(true)
 			 .help("Yaml validator keys"))
+		.arg(Arg::with_name("validator-keystores")
+			 .long("validator-keystores")
+			 .takes_value(true)
+			 .help("Directory of EIP-2335 validator keystores"))
+		.arg(Arg::with_name("keystore-password-file")
+			 .long("keystore-password-file")
+			 .takes_value(true)
+			 .help("File containing the keystore password, prompted for if not given"))
 		.arg(Arg::with_name("chain")
 			 .long("chain")
 			 .takes_value(true)
@@ -170,8 +104,152 @@ fn main() {
 			 .long("config")
 			 .takes_value(true)
 			 .help("Config to use"))
+		.arg(Arg::with_name("genesis-time")
+			 .long("genesis-time")
+			 .takes_value(true)
+			 .help("Genesis time of the dev interop genesis state, in unix seconds or \"now\", defaults to a fixed time"))
+		.arg(Arg::with_name("eth1-endpoint")
+			 .long("eth1-endpoint")
+			 .takes_value(true)
+			 .requires("deposit-contract")
+			 .help("Eth1 JSON-RPC endpoint to follow deposits from"))
+		.arg(Arg::with_name("deposit-contract")
+			 .long("deposit-contract")
+			 .takes_value(true)
+			 .help("Address of the Eth1 deposit contract"))
+		.arg(Arg::with_name("deposit-contract-deploy-block")
+			 .long("deposit-contract-deploy-block")
+			 .takes_value(true)
+			 .help("Eth1 block number the deposit contract was deployed at"))
+		.arg(Arg::with_name("api-address")
+			 .long("api-address")
+			 .takes_value(true)
+			 .default_value("127.0.0.1:5052")
+			 .help("Address to serve the Beacon API and validator client API on"))
+		.arg(Arg::with_name("slashing-protection")
+			 .long("slashing-protection")
+			 .takes_value(true)
+			 .help("Slashing protection file, defaults to slashing_protection.json in the data directory"))
+		.arg(Arg::with_name("doppelganger-epochs")
+			 .long("doppelganger-epochs")
+			 .takes_value(true)
+			 .default_value("2")
+			 .help("Epochs to watch for other instances of the validator keys before signing, 0 to disable"))
+		.arg(Arg::with_name("remote-signer")
+			 .long("remote-signer")
+			 .takes_value(true)
+			 .conflicts_with_all(&["validator-keys", "validator-keystores"])
+			 .help("Remote signer taking Web3Signer requests with this client's signing roots, instead of local validator keys"))
+		.subcommand(SubCommand::with_name("genesis")
+			.about("Generate an interop genesis state and validator keys")
+			.arg(Arg::with_name("validator-count")
+				 .long("validator-count")
+				 .takes_value(true)
+				 .help("Number of deterministic interop validators to generate, defaults to 64"))
+			.arg(Arg::with_name("deposits")
+				 .long("deposits")
+				 .takes_value(true)
+				 .conflicts_with("validator-count")
+				 .help("Yaml or ssz list of deposit data to use instead of generated keys"))
+			.arg(Arg::with_name("genesis-time")
+				 .long("genesis-time")
+				 .takes_value(true)
+				 .help("Genesis time of the generated state, in unix seconds or \"now\", defaults to a fixed time"))
+			.arg(Arg::with_name("output-state")
+				 .long("output-state")
+				 .takes_value(true)
+				 .default_value("genesis.ssz")
+				 .help("Path to write ssz genesis state to"))
+			.arg(Arg::with_name("output-keys")
+				 .long("output-keys")
+				 .takes_value(true)
+				 .default_value("keys.yaml")
+				 .help("Path to write yaml validator keys to")))
+		.subcommand(SubCommand::with_name("convert-keys")
+			.about("Convert yaml validator keys into EIP-2335 keystores")
+			.arg(Arg::with_name("validator-keys")
+				 .long("validator-keys")
+				 .takes_value(true)
+				 .required(true)
+				 .help("Yaml validator keys to convert"))
+			.arg(Arg::with_name("output-dir")
+				 .long("output-dir")
+				 .takes_value(true)
+				 .required(true)
+				 .help("Directory to write keystores to"))
+			.arg(Arg::with_name("password-file")
+				 .long("password-file")
+				 .takes_value(true)
+				 .help("File containing the keystore password, prompted for if not given")))
+		.subcommand(SubCommand::with_name("derive-keys")
+			.about("Derive validator keys and withdrawal credentials from a BIP-39 mnemonic")
+			.arg(Arg::with_name("mnemonic-file")
+				 .long("mnemonic-file")
+				 .takes_value(true)
+				 .help("File containing the mnemonic, prompted for if not given"))
+			.arg(Arg::with_name("passphrase-file")
+				 .long("passphrase-file")
+				 .takes_value(true)
+				 .help("File containing the BIP-39 passphrase, empty if not given"))
+			.arg(Arg::with_name("start-index")
+				 .long("start-index")
+				 .takes_value(true)
+				 .default_value("0")
+				 .help("Index of the first validator to derive"))
+			.arg(Arg::with_name("count")
+				 .long("count")
+				 .takes_value(true)
+				 .default_value("1")
+				 .help("Number of validators to derive"))
+			.arg(Arg::with_name("output-dir")
+				 .long("output-dir")
+				 .takes_value(true)
+				 .help("Directory to write signing key keystores to"))
+			.arg(Arg::with_name("password-file")
+				 .long("password-file")
+				 .takes_value(true)
+				 .help("File containing the keystore password, prompted for if not given")))
+		.subcommand(SubCommand::with_name("import-slashing-protection")
+			.about("Import an EIP-3076 slashing protection interchange file")
+			.arg(Arg::with_name("slashing-protection")
+				 .long("slashing-protection")
+				 .takes_value(true)
+				 .required(true)
+				 .help("Slashing protection file to import into, created if it does not exist"))
+			.arg(Arg::with_name("interchange")
+				 .long("interchange")
+				 .takes_value(true)
+				 .required(true)
+				 .help("Interchange file to import")))
+		.subcommand(SubCommand::with_name("export-slashing-protection")
+			.about("Export slashing protection records as an EIP-3076 interchange file")
+			.arg(Arg::with_name("slashing-protection")
+				 .long("slashing-protection")
+				 .takes_value(true)
+				 .required(true)
+				 .help("Slashing protection file to export"))
+			.arg(Arg::with_name("interchange")
+				 .long("interchange")
+				 .takes_value(true)
+				 .required(true)
+				 .help("Path to write the interchange file to")))
 		.get_matches();
 
+	if let Some(matches) = matches.subcommand_matches("convert-keys") {
+		convert_keys(matches);
+		return
+	}
+
+	if let Some(matches) = matches.subcommand_matches("import-slashing-protection") {
+		import_slashing_protection(matches);
+		return
+	}
+
+	if let Some(matches) = matches.subcommand_matches("export-slashing-protection") {
+		export_slashing_protection(matches);
+		return
+	}
+
 	let preset = matches.value_of("chain").map(|name| {
 		shasper_blockchain::preset::presets().get(&name)
 			.expect("Unknown preset").clone()
@@ -185,6 +263,26 @@ fn main() {
 		});
 
 	info!("Using chain config: {}", config_name);
+	if let Some(matches) = matches.subcommand_matches("genesis") {
+		match config_name {
+			"minimal" => genesis_with_config::<beacon::MinimalConfig>(matches),
+			"mainnet" => genesis_with_config::<beacon::MainnetConfig>(matches),
+			"sapphire" => genesis_with_config::<beacon::SapphireConfig>(matches),
+			e => panic!("Unknown config name: {:?}", e),
+		}
+		return
+	}
+
+	if let Some(matches) = matches.subcommand_matches("derive-keys") {
+		match config_name {
+			"minimal" => derive_keys_with_config::<beacon::MinimalConfig>(matches),
+			"mainnet" => derive_keys_with_config::<beacon::MainnetConfig>(matches),
+			"sapphire" => derive_keys_with_config::<beacon::SapphireConfig>(matches),
+			e => panic!("Unknown config name: {:?}", e),
+		}
+		return
+	}
+
 	match config_name {
 		"minimal" => main_with_config::<beacon::MinimalConfig>(matches, preset),
 		"mainnet" => main_with_config::<beacon::MainnetConfig>(matches, preset),
@@ -200,25 +298,23 @@ fn main_with_config<C: Config>(matches: ArgMatches, preset: Option<Preset>) wher
 	let mut keys: HashMap<ValidatorId, bls::Secret> = HashMap::new();
 
 	if let Some(validator_keys) = matches.value_of("validator-keys") {
-		const PRIVATE_KEY_BYTES: usize = 48;
-
 		let file = File::open(validator_keys).unwrap();
 		let coll = serde_yaml::from_reader::<_, Vec<ValidatorKey>>(BufReader::new(file)).unwrap();
 
 		for key in coll {
-			let privkey = string_to_bytes(&key.privkey).unwrap();
-
-			let sk = {
-				let mut bytes = vec![0; PRIVATE_KEY_BYTES - privkey.len()];
-				bytes.extend_from_slice(&privkey);
-				bls::Secret::from_bytes(&bytes)
-					.map_err(|e| format!("Failed to decode bytes into secret key: {:?}", e))
-					.unwrap()
-			};
+			let sk = key.secret().unwrap();
+			keys.insert(interop::validator_id(&sk), sk);
+		}
+	}
 
-			let pubkey = ValidatorId::from_slice(&bls::Public::from_secret_key(&sk).as_bytes()[..]);
+	if let Some(keystores) = matches.value_of("validator-keystores") {
+		let secrets = shasper_blockchain::keys::load_keystores(
+			Path::new(keystores),
+			matches.value_of("keystore-password-file").map(Path::new),
+		).unwrap();
 
-			keys.insert(pubkey, sk);
+		for sk in secrets {
+			keys.insert(interop::validator_id(&sk), sk);
 		}
 	}
 
@@ -233,46 +329,16 @@ fn main_with_config<C: Config>(matches: ArgMatches, preset: Option<Preset>) wher
 	} else {
 		let mut deposit_datas = Vec::new();
 		for i in 0..10 {
-			let seckey = bls::Secret::random(&mut rand::thread_rng());
-			let pubkey = ValidatorId::from_slice(&bls::Public::from_secret_key(&seckey).as_bytes()[..]);
-			let mut data = DepositData {
-				pubkey: pubkey.clone(),
-				withdrawal_credentials: H256::from_low_u64_le(i as u64),
-				amount: 32000000000,
-				signature: Default::default(),
-			};
-			let signature = Signature::from_slice(&bls::Signature::new(
-				&tree_root::<sha2::Sha256, _>(&SigningDepositData::from(data.clone()))[..],
-				beacon::genesis_domain(C::domain_deposit()),
-				&seckey
-			).as_bytes()[..]);
-			data.signature = signature;
-			deposit_datas.push(data);
-			keys.insert(pubkey, seckey);
+			let seckey = crypto::interop::secret(i);
+			deposit_datas.push(
+				interop::signed_deposit_data::<C>(&seckey, C::max_effective_balance())
+			);
+			keys.insert(interop::validator_id(&seckey), seckey);
 		}
 
-		let deposit_tree = deposit_tree::<C>(&deposit_datas);
-		let deposits = deposit_datas.clone().into_iter()
-			.enumerate()
-			.map(|(i, deposit_data)| {
-				Deposit {
-					proof: deposit_proof::<C>(&deposit_tree, i).try_into().ok().unwrap(),
-					data: deposit_data,
-				}
-			})
-			.collect::<Vec<_>>();
-		let deposit_root = deposit_root(&deposit_tree);
-		let eth1_data = Eth1Data {
-			deposit_root,
-			deposit_count: deposits.len() as u64,
-			block_hash: Default::default(),
-		};
-		let genesis_state =
-			genesis_beacon_state::<C, BLS>(
-				&deposits, 0, eth1_data.clone()
-			).unwrap();
-
-		genesis_state
+		interop::interop_genesis_state::<C, BLS>(
+			&deposit_datas, genesis_time(matches.value_of("genesis-time"))
+		).unwrap()
 	};
 	let genesis_block = Block(BeaconBlock {
 		state_root: tree_root::<<C as Config>::Digest, _>(&genesis_state),
@@ -280,6 +346,46 @@ fn main_with_config<C: Config>(matches: ArgMatches, preset: Option<Preset>) wher
 	});
 	let eth1_data = genesis_state.eth1_data.clone();
 
+	let slashing_protection = if matches.is_present("author") {
+		let path = match matches.value_of("slashing-protection") {
+			Some(path) => PathBuf::from(path),
+			None => Path::new(matches.value_of("data").unwrap_or(".")).join("slashing_protection.json"),
+		};
+		info!("Using slashing protection file {}", path.display());
+		Some(SlashingProtection::open(
+			&path, interop::genesis_validators_root(&genesis_state),
+		).expect("Opening slashing protection failed"))
+	} else {
+		None
+	};
+
+	let eth1 = matches.value_of("eth1-endpoint").map(|endpoint| {
+		info!("Following Eth1 deposits from {}", endpoint);
+		shasper_eth1::spawn_follower::<C>(Eth1FollowerConfig {
+			endpoint: endpoint.to_string(),
+			deposit_contract: matches.value_of("deposit-contract")
+				.expect("Deposit contract is required by eth1 endpoint; qed")
+				.to_string(),
+			deploy_block: matches.value_of("deposit-contract-deploy-block")
+				.map(|v| u64::from_str(v).expect("Invalid deploy block"))
+				.unwrap_or(0),
+			poll_interval: Duration::from_secs(C::seconds_per_eth1_block()),
+		})
+	});
+
+	let signer: Box<dyn Signer<C> + Send> = match matches.value_of("remote-signer") {
+		Some(endpoint) => {
+			info!("Signing with remote signer {}", endpoint);
+			Box::new(RemoteSigner::new(endpoint))
+		},
+		None => Box::new(LocalSigner::new(keys)),
+	};
+
+	let api_address = SocketAddr::from_str(matches.value_of("api-address").unwrap())
+		.expect("Invalid API address");
+	let doppelganger_epochs = u64::from_str(matches.value_of("doppelganger-epochs").unwrap())
+		.expect("Invalid doppelganger epochs");
+
 	let mut network_config = NetworkConfig::default();
 	network_config.libp2p_port = u16::from_str(matches.value_of("port").unwrap()).unwrap();
 	network_config.discovery_port = u16::from_str(matches.value_of("port").unwrap()).unwrap();
@@ -299,22 +405,25 @@ fn main_with_config<C: Config>(matches: ArgMatches, preset: Option<Preset>) wher
 	if let Some(path) = matches.value_of("data") {
 		info!("Using RocksDB backend");
 		let backend = ShasperBackend::new(
-			RocksBackend::<_, (), RocksState<C>>::open_or_create(path, |_| {
+			RocksBackend::<_, ShasperAuxiliary, RocksState<C>>::open_or_create(path, |_| {
 				Ok((genesis_block.clone(), genesis_state.into()))
 			}).unwrap()
 		);
 		let lock = ImportLock::new();
 
 		run(network_config,
-			matches.is_present("author"),
+			slashing_protection,
 			backend,
 			lock,
 			eth1_data,
-			keys);
+			eth1.clone(),
+			signer,
+			doppelganger_epochs,
+			api_address);
 	} else {
 		info!("Using in-memory backend");
 		let backend = ShasperBackend::new(
-			SharedMemoryBackend::<_, (), MemoryState<C>>::new_with_genesis(
+			SharedMemoryBackend::<_, ShasperAuxiliary, MemoryState<C>>::new_with_genesis(
 				genesis_block.clone(),
 				genesis_state.into(),
 			)
@@ -322,214 +431,801 @@ fn main_with_config<C: Config>(matches: ArgMatches, preset: Option<Preset>) wher
 		let lock = ImportLock::new();
 
 		run(network_config,
-			matches.is_present("author"),
+			slashing_protection,
 			backend,
 			lock,
 			eth1_data,
-			keys);
+			eth1,
+			signer,
+			doppelganger_epochs,
+			api_address);
+	}
+}
+
+fn new_keystore_password(matches: &ArgMatches) -> String {
+	match matches.value_of("password-file") {
+		Some(path) => shasper_blockchain::keys::read_password_file(Path::new(path)).unwrap(),
+		None => {
+			let password = shasper_blockchain::keys::prompt_password("New keystore password: ")
+				.unwrap();
+			let confirm = shasper_blockchain::keys::prompt_password("Confirm password: ")
+				.unwrap();
+			assert!(password == confirm, "Passwords do not match");
+			password
+		},
+	}
+}
+
+fn convert_keys(matches: &ArgMatches) {
+	let file = File::open(matches.value_of("validator-keys").unwrap()).unwrap();
+	let secrets = serde_yaml::from_reader::<_, Vec<ValidatorKey>>(BufReader::new(file)).unwrap()
+		.into_iter()
+		.map(|key| (key.secret().unwrap(), String::new()))
+		.collect::<Vec<_>>();
+
+	let password = new_keystore_password(matches);
+	shasper_blockchain::keys::write_keystores(
+		Path::new(matches.value_of("output-dir").unwrap()), &secrets, &password
+	).unwrap();
+}
+
+fn import_slashing_protection(matches: &ArgMatches) {
+	let path = Path::new(matches.value_of("slashing-protection").unwrap());
+	let interchange = Interchange::read(Path::new(matches.value_of("interchange").unwrap()))
+		.unwrap();
+
+	let imported = interchange.data.len();
+	if path.exists() {
+		SlashingProtection::open_existing(path).unwrap().import(interchange).unwrap();
+	} else {
+		let genesis_validators_root = interchange.metadata.genesis_validators_root;
+		SlashingProtection::open(path, genesis_validators_root).unwrap()
+			.import(interchange).unwrap();
+	}
+	info!("Imported slashing protection records of {} validators", imported);
+}
+
+fn export_slashing_protection(matches: &ArgMatches) {
+	let protection = SlashingProtection::open_existing(
+		Path::new(matches.value_of("slashing-protection").unwrap())
+	).unwrap();
+	protection.export().write(Path::new(matches.value_of("interchange").unwrap())).unwrap();
+}
+
+fn derive_keys_with_config<C: Config>(matches: &ArgMatches) {
+	let mnemonic = match matches.value_of("mnemonic-file") {
+		Some(path) => shasper_blockchain::keys::read_password_file(Path::new(path)).unwrap(),
+		None => shasper_blockchain::keys::prompt_password("Mnemonic: ").unwrap(),
+	};
+	let passphrase = matches.value_of("passphrase-file")
+		.map(|path| shasper_blockchain::keys::read_password_file(Path::new(path)).unwrap())
+		.unwrap_or_default();
+	let seed = crypto::derivation::mnemonic_to_seed(mnemonic.trim(), &passphrase);
+
+	let start_index = u32::from_str(matches.value_of("start-index").unwrap())
+		.expect("Invalid start index");
+	let count = u32::from_str(matches.value_of("count").unwrap())
+		.expect("Invalid count");
+
+	let mut secrets = Vec::new();
+	let mut validators = Vec::new();
+	for index in start_index..(start_index + count) {
+		let (secret, validator) = shasper_blockchain::keys::derive_validator::<C>(&seed, index)
+			.unwrap();
+		secrets.push((secret, validator.signing_path.clone()));
+		validators.push(validator);
+	}
+
+	serde_yaml::to_writer(std::io::stdout(), &validators).unwrap();
+	println!();
+
+	if let Some(output_dir) = matches.value_of("output-dir") {
+		let password = new_keystore_password(matches);
+		shasper_blockchain::keys::write_keystores(Path::new(output_dir), &secrets, &password)
+			.unwrap();
+	}
+}
+
+/// Parse a genesis time given in unix seconds, or as `now` for the current
+/// time. Without one, the fixed interop genesis time is used.
+fn genesis_time(value: Option<&str>) -> u64 {
+	match value {
+		Some("now") => clock::unix_now().as_secs(),
+		Some(value) => u64::from_str(value).expect("Invalid genesis time"),
+		None => interop::INTEROP_GENESIS_TIME,
+	}
+}
+
+fn genesis_with_config<C: Config>(matches: &ArgMatches) {
+	let genesis_time = genesis_time(matches.value_of("genesis-time"));
+
+	let mut keys = Vec::new();
+	let deposit_datas = if let Some(deposits) = matches.value_of("deposits") {
+		let mut file = File::open(deposits).unwrap();
+		let mut data = Vec::new();
+		file.read_to_end(&mut data).unwrap();
+
+		if deposits.ends_with(".ssz") {
+			Decode::decode(&mut &data[..]).expect("Invalid ssz deposit data list")
+		} else {
+			serde_yaml::from_slice::<Vec<DepositData>>(&data).expect("Invalid yaml deposit data list")
+		}
+	} else {
+		let validator_count = u64::from_str(matches.value_of("validator-count").unwrap_or("64"))
+			.expect("Invalid validator count");
+
+		(0..validator_count).map(|i| {
+			let seckey = crypto::interop::secret(i);
+			keys.push(ValidatorKey::from_secret(&seckey));
+			interop::signed_deposit_data::<C>(&seckey, C::max_effective_balance())
+		}).collect::<Vec<_>>()
+	};
+
+	let genesis_state = interop::interop_genesis_state::<C, BLS>(&deposit_datas, genesis_time)
+		.expect("Building genesis state failed");
+	info!("Generated genesis state with {} validators", genesis_state.validators.len());
+
+	let output_state = matches.value_of("output-state").unwrap();
+	File::create(output_state).unwrap()
+		.write_all(&ssz::Encode::encode(&genesis_state)).unwrap();
+	info!("Written genesis state to {}", output_state);
+
+	if !keys.is_empty() {
+		let output_keys = matches.value_of("output-keys").unwrap();
+		serde_yaml::to_writer(File::create(output_keys).unwrap(), &keys).unwrap();
+		info!("Written validator keys to {}", output_keys);
 	}
 }
 
 fn run<B, C: Config>(
 	config: NetworkConfig,
-	author: bool,
+	slashing_protection: Option<SlashingProtection>,
 	backend: B,
 	import_lock: ImportLock,
 	eth1_data: Eth1Data,
-	keys: HashMap<ValidatorId, bls::Secret>,
+	eth1: Option<Arc<RwLock<Eth1Follower<C>>>>,
+	signer: Box<dyn Signer<C> + Send>,
+	doppelganger_epochs: Uint,
+	api_address: SocketAddr,
 ) where
 	Block<C>: ssz::Encode + ssz::Decode + Unpin + Send + Sync,
-	B: ChainQuery + AncestorQuery + Store<Block=Block<C>>,
-	B::State: StateExternalities + AsExternalities<dyn StateExternalities<Config=C>>,
-	B::Auxiliary: Auxiliary<Block<C>> + Unpin,
+	B: ChainQuery + AncestorQuery + Store<Block=Block<C>, Auxiliary=ShasperAuxiliary>,
+	B::State: StateExternalities<Config=C> + AsExternalities<dyn StateExternalities<Config=C>>,
 	B: SharedCommittable<Operation=Operation<<B as Store>::Block, <B as Store>::State, <B as Store>::Auxiliary>>,
 	B: Send + Sync + 'static,
 	C: Unpin + Clone + Send + Sync + 'static,
 {
-	let executor = Executor::<C, BLS>::new();
-	let importer = MutexImporter::new(
-		ArchiveGhostImporter::new(executor, backend.clone(), import_lock.clone())
-	);
+	let genesis_time = backend.state_at(&backend.head())
+		.expect("Head state exists in backend; qed")
+		.state().genesis_time;
+	// Networking, the API and imports start right away, so that peers and
+	// the validator client can reach the node before genesis. Only the block
+	// builder waits for genesis.
+	let clock = SlotClock::<C>::new(genesis_time);
+
+	let executor = Executor::<C, BLS>::with_clock(clock.clone());
+	let mut importer = ArchiveGhostImporter::new(executor, backend.clone(), import_lock.clone());
+	let events = EventSender::default();
+	start_event_thread(backend.clone(), importer.subscribe(), events.clone());
+	let pool_notifications = importer.subscribe();
+	let builder_notifications = if slashing_protection.is_some() {
+		Some(importer.subscribe())
+	} else {
+		None
+	};
+	let network_notifications = importer.subscribe_async();
+	let votes = importer.vote_sender();
+	let importer = MutexImporter::new(importer);
 
-	if author {
+	let pool = Arc::new(Mutex::new(
+		OperationPool::<C, BLS>::load(&backend).unwrap_or_else(|err| {
+			warn!("Error when loading persisted operation pool: {}", err);
+			OperationPool::new()
+		})
+	));
+
+	let backend_prune = backend.clone();
+	let pool_prune = pool.clone();
+	thread::spawn(move || {
+		prune_thread(backend_prune, pool_prune, pool_notifications);
+	});
+
+	let backend_persist = backend.clone();
+	let pool_persist = pool.clone();
+	let import_lock_persist = import_lock.clone();
+	let (shutdown_sender, shutdown_receiver) = mpsc::channel::<mpsc::Sender<()>>();
+	thread::spawn(move || {
+		persist_thread(backend_persist, pool_persist, import_lock_persist, shutdown_receiver);
+	});
+
+	ctrlc::set_handler(move || {
+		let (done_sender, done_receiver) = mpsc::channel();
+		if shutdown_sender.send(done_sender).is_ok() {
+			let _ = done_receiver.recv_timeout(Duration::from_secs(10));
+		}
+		std::process::exit(0);
+	}).expect("Setting Ctrl-C handler failed");
+
+	let (publish_sender, publish_receiver) = shasper_network::pubsub_channel::<C>();
+	let (inbound_sender, inbound_receiver) = shasper_network::inbound_channel::<C>();
+	let (validation_sender, validation_receiver) = shasper_network::validation_channel();
+	let (orphan_sender, orphan_receiver) = shasper_network::orphan_channel::<C>();
+
+	let network_status = SharedNetworkStatus::default();
+	shasper_blockchain::api::start_api_server(&api_address, ApiContext {
+		backend: backend.clone(),
+		importer: importer.clone(),
+		pool: pool.clone(),
+		eth1: eth1.clone(),
+		eth1_data: eth1_data.clone(),
+		network: network_status.clone(),
+		publish: publish_sender.clone(),
+		clock: clock.clone(),
+		events: events.clone(),
+	}).expect("Starting API server failed");
+
+	let pubkeys = if slashing_protection.is_some() {
+		signer.pubkeys().expect("Fetching validator keys from signer failed")
+	} else {
+		Vec::new()
+	};
+
+	let doppelganger = if slashing_protection.is_some() && doppelganger_epochs > 0 {
+		let start_epoch = beacon::utils::epoch_of_slot::<C>(
+			clock.now().unwrap_or(C::genesis_slot())
+		) + 1;
+		info!(
+			"Watching for doppelgangers from epoch {} to {}",
+			start_epoch, start_epoch + doppelganger_epochs,
+		);
+		Some(Arc::new(Mutex::new(
+			DoppelgangerDetector::new(&backend, &pubkeys, start_epoch, doppelganger_epochs)
+				.expect("Starting doppelganger detection failed")
+		)))
+	} else {
+		None
+	};
+
+	let backend_inbound = backend.clone();
+	let importer_inbound = importer.clone();
+	let pool_inbound = pool.clone();
+	let clock_inbound = clock.clone();
+	let events_inbound = events.clone();
+	let doppelganger_inbound = doppelganger.clone();
+	thread::spawn(move || {
+		inbound_thread(
+			backend_inbound, importer_inbound, pool_inbound, clock_inbound, events_inbound, votes,
+			inbound_receiver, validation_sender, orphan_sender, doppelganger_inbound,
+		);
+	});
+
+	if let (Some(slashing_protection), Some(notifications)) =
+		(slashing_protection, builder_notifications)
+	{
+		let publish_build = publish_sender.clone();
 		let backend_build = backend.clone();
 		let importer_build = importer.clone();
 		thread::spawn(move || {
-			builder_thread(backend_build, importer_build, eth1_data, keys);
+			builder_thread(
+				backend_build, importer_build, notifications, clock, pool, publish_build, events,
+				slashing_protection,
+				doppelganger, eth1_data, eth1, signer, pubkeys,
+			);
 		});
 	}
 
-	shasper_network::start_network_simple_sync(backend, import_lock, importer, config)
-		.expect("Starting networking thread failed");
+	shasper_network::start_network_simple_sync(
+		backend, import_lock, importer, network_notifications, publish_receiver, inbound_sender,
+		validation_receiver, orphan_receiver, network_status, config,
+	).expect("Starting networking thread failed");
+}
+
+fn inbound_thread<B, I, C: Config>(
+	backend: B,
+	importer: I,
+	pool: Arc<Mutex<OperationPool<C, BLS>>>,
+	clock: SlotClock<C>,
+	events: EventSender,
+	votes: mpsc::Sender<(ValidatorIndex, H256)>,
+	inbound: InboundReceiver<C>,
+	validations: ValidationSender,
+	orphans: OrphanSender<C>,
+	doppelganger: Option<Arc<Mutex<DoppelgangerDetector<C>>>>,
+) where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C>,
+	I: SharedBlockImporter<Block=Block<C>>,
+{
+	let mut seen = gossip::SeenMessages::new();
+	let mut pruned_slot = None;
+
+	for (peer, id, message) in inbound {
+		let current_slot = clock.now();
+		if current_slot != pruned_slot {
+			if let Some(current_slot) = current_slot {
+				seen.prune::<C>(current_slot);
+			}
+			pruned_slot = current_slot;
+		}
+
+		// Only attestations that passed gossip validation, and thus carry a
+		// valid signature, are observed for doppelgangers.
+		let attestation = match (doppelganger.as_ref(), &message) {
+			(Some(_), PubsubMessage::Attestation(attestation)) => Some(attestation.clone()),
+			(Some(_), PubsubMessage::AggregateAndProof(signed)) =>
+				Some(signed.message.aggregate.clone()),
+			_ => None,
+		};
+
+		let validation = match process_gossip(
+			&backend, &importer, &pool, &clock, &events, &votes, &mut seen, &orphans, &peer,
+			message,
+		) {
+			Ok(true) => Validation::Accept,
+			Ok(false) => Validation::Ignore,
+			Err(err) => {
+				debug!("Rejected gossip message from {:?}: {}", peer, err);
+				Validation::Reject
+			},
+		};
+
+		if let (Some(doppelganger), Some(attestation), Validation::Accept) =
+			(doppelganger.as_ref(), attestation, validation)
+		{
+			let mut doppelganger = doppelganger.lock().expect("Doppelganger lock poisoned");
+			if let Err(err) = doppelganger.observe_attestation(&backend, &attestation) {
+				trace!("Unable to observe attestation from {:?}: {}", peer, err);
+			}
+		}
+
+		if validations.unbounded_send((peer, id, validation)).is_err() {
+			warn!("Network is not running, stopping processing gossip");
+			return
+		}
+	}
+}
+
+/// Whether to go on processing a message after its gossip validation.
+/// Messages that are not useful but not invalid are ignored instead of
+/// rejected.
+fn gossip_result(result: Result<(), shasper_blockchain::Error>) -> Result<bool, String> {
+	match result {
+		Ok(()) => Ok(true),
+		Err(shasper_blockchain::Error::Gossip(err)) if err.is_ignored() => {
+			trace!("Ignoring gossip message: {:?}", err);
+			Ok(false)
+		},
+		Err(err) => Err(err.to_string()),
+	}
+}
+
+/// Validate a message received on gossip, and import it or add it to the
+/// pool. Returns whether it is new and should be forwarded.
+fn process_gossip<B, I, C: Config>(
+	backend: &B,
+	importer: &I,
+	pool: &Mutex<OperationPool<C, BLS>>,
+	clock: &SlotClock<C>,
+	events: &EventSender,
+	votes: &mpsc::Sender<(ValidatorIndex, H256)>,
+	seen: &mut gossip::SeenMessages,
+	orphans: &OrphanSender<C>,
+	peer: &PeerId,
+	message: PubsubMessage<C>,
+) -> Result<bool, String> where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C>,
+	I: SharedBlockImporter<Block=Block<C>>,
+{
+	let note_votes = |attestation: &Attestation<C>| {
+		if !backend.contains(&attestation.data.beacon_block_root).unwrap_or(false) {
+			return
+		}
+		match production::attestation_votes(backend, attestation) {
+			Ok(attestation_votes) => for vote in attestation_votes {
+				let _ = votes.send(vote);
+			},
+			Err(err) => warn!("Error when counting attestation votes: {}", err),
+		}
+	};
+
+	match message {
+		PubsubMessage::Block(block) => {
+			let latest_slot = match clock.now_with_disparity() {
+				Some(slot) => slot,
+				None => return Ok(false),
+			};
+			let block = Block(block);
+			if backend.contains(&block.id()).map_err(|e| format!("{:?}", e))? {
+				return Ok(false)
+			}
+			match gossip::validate_block::<_, _, BLS>(backend, seen, latest_slot, &block.0) {
+				Err(shasper_blockchain::Error::Gossip(gossip::GossipError::UnknownParent)) => {
+					// Ancestors are fetched by the network, and the block is
+					// imported once they are, without being forwarded.
+					let _ = orphans.unbounded_send((peer.clone(), block.0));
+					return Ok(false)
+				},
+				result => if !gossip_result(result)? {
+					return Ok(false)
+				},
+			}
+
+			importer.import_block(block).map_err(|e| format!("{:?}", e))?;
+			Ok(true)
+		},
+		PubsubMessage::Attestation(attestation) => {
+			let (earliest_slot, latest_slot) = match (
+				clock.now_with_past_disparity(), clock.now_with_disparity(),
+			) {
+				(Some(earliest_slot), Some(latest_slot)) => (earliest_slot, latest_slot),
+				_ => return Ok(false),
+			};
+			if !gossip_result(gossip::validate_attestation::<_, _, BLS>(
+				backend, seen, earliest_slot, latest_slot, &attestation,
+			))? {
+				return Ok(false)
+			}
+			{
+				let mut pool = pool.lock().expect("Operation pool lock poisoned");
+				production::submit_attestation(backend, &mut pool, attestation.clone())
+					.map_err(|e| e.to_string())?;
+			}
+			note_votes(&attestation);
+			events.send(Topic::Attestation, &attestation);
+			Ok(true)
+		},
+		PubsubMessage::AggregateAndProof(signed) => {
+			let (earliest_slot, latest_slot) = match (
+				clock.now_with_past_disparity(), clock.now_with_disparity(),
+			) {
+				(Some(earliest_slot), Some(latest_slot)) => (earliest_slot, latest_slot),
+				_ => return Ok(false),
+			};
+			{
+				let mut pool = pool.lock().expect("Operation pool lock poisoned");
+				if !gossip_result(production::submit_aggregate_and_proof(
+					backend, &mut pool, earliest_slot, latest_slot, signed.clone(),
+				))? {
+					return Ok(false)
+				}
+			}
+			note_votes(&signed.message.aggregate);
+			events.send(Topic::Attestation, &signed.message.aggregate);
+			Ok(true)
+		},
+		PubsubMessage::VoluntaryExit(exit) => {
+			let mut pool = pool.lock().expect("Operation pool lock poisoned");
+			let new = production::submit_voluntary_exit(backend, &mut pool, exit.clone())
+				.map_err(|e| e.to_string())?;
+			if new {
+				events.send(Topic::VoluntaryExit, &exit);
+			}
+			Ok(new)
+		},
+		PubsubMessage::ProposerSlashing(slashing) => {
+			let mut pool = pool.lock().expect("Operation pool lock poisoned");
+			production::submit_proposer_slashing(backend, &mut pool, slashing)
+				.map_err(|e| e.to_string())
+		},
+		PubsubMessage::AttesterSlashing(slashing) => {
+			let mut pool = pool.lock().expect("Operation pool lock poisoned");
+			production::submit_attester_slashing(backend, &mut pool, slashing)
+				.map_err(|e| e.to_string())
+		},
+	}
+}
+
+fn prune_thread<B, C: Config>(
+	backend: B,
+	pool: Arc<Mutex<OperationPool<C, BLS>>>,
+	notifications: mpsc::Receiver<ImportNotification<H256>>,
+) where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C>,
+{
+	for notification in notifications {
+		if let ImportNotification::NewHead { new, .. } = notification {
+			let mut pool = pool.lock().expect("Operation pool lock poisoned");
+			if let Err(err) = pool.prune_at(&backend, &new) {
+				warn!("Error when pruning operation pool: {}", err);
+			}
+		}
+	}
+}
+
+/// Block the current thread until attestations of `slot` are due, which is
+/// when a block of the slot becomes the head, or one third into the slot.
+fn wait_for_attestation<B, C: Config>(
+	backend: &B,
+	clock: &SlotClock<C>,
+	notifications: &mpsc::Receiver<ImportNotification<H256>>,
+	slot: Slot,
+) where
+	B: ChainQuery + Store<Block=Block<C>>,
+{
+	loop {
+		let timeout = clock.duration_until(slot, SlotClock::<C>::attestation_offset());
+		match notifications.recv_timeout(timeout) {
+			Ok(ImportNotification::NewHead { new, .. }) => {
+				if backend.block_at(&new).map(|block| block.0.slot == slot).unwrap_or(false) {
+					trace!("Block of slot {} became head, attesting early", slot);
+					return
+				}
+			},
+			Ok(ImportNotification::BlockRejected { id, reason }) =>
+				trace!("Block {} was rejected: {}", id, reason),
+			Ok(_) => (),
+			Err(mpsc::RecvTimeoutError::Timeout) => return,
+			Err(mpsc::RecvTimeoutError::Disconnected) => {
+				clock.wait_until(slot, SlotClock::<C>::attestation_offset());
+				return
+			},
+		}
+	}
+}
+
+fn persist_thread<B, C: Config>(
+	backend: B,
+	pool: Arc<Mutex<OperationPool<C, BLS>>>,
+	import_lock: ImportLock,
+	shutdown: mpsc::Receiver<mpsc::Sender<()>>,
+) where
+	B: ChainQuery + Store<Block=Block<C>, Auxiliary=ShasperAuxiliary>,
+	B: SharedCommittable<Operation=Operation<<B as Store>::Block, <B as Store>::State, <B as Store>::Auxiliary>>,
+{
+	loop {
+		match shutdown.recv_timeout(POOL_PERSIST_INTERVAL) {
+			Ok(done) => {
+				info!("Persisting operation pool before shutdown");
+				let pool = pool.lock().expect("Operation pool lock poisoned");
+				if let Err(err) = pool.persist(&backend, &import_lock) {
+					warn!("Error when persisting operation pool: {}", err);
+				}
+				let _ = done.send(());
+				return
+			},
+			Err(mpsc::RecvTimeoutError::Timeout) => {
+				let pool = pool.lock().expect("Operation pool lock poisoned");
+				if let Err(err) = pool.persist(&backend, &import_lock) {
+					warn!("Error when persisting operation pool: {}", err);
+				}
+			},
+			Err(mpsc::RecvTimeoutError::Disconnected) => return,
+		}
+	}
 }
 
 fn builder_thread<B, I, C: Config + Clone>(
 	backend: B,
 	importer: I,
+	notifications: mpsc::Receiver<ImportNotification<H256>>,
+	clock: SlotClock<C>,
+	pool: Arc<Mutex<OperationPool<C, BLS>>>,
+	publish: PubsubSender<C>,
+	events: EventSender,
+	mut slashing_protection: SlashingProtection,
+	doppelganger: Option<Arc<Mutex<DoppelgangerDetector<C>>>>,
 	eth1_data: Eth1Data,
-	keys: HashMap<ValidatorId, bls::Secret>,
+	eth1: Option<Arc<RwLock<Eth1Follower<C>>>>,
+	signer: Box<dyn Signer<C> + Send>,
+	pubkeys: Vec<ValidatorId>,
 ) where
-	B: ChainQuery + Store<Block=Block<C>>,
-	B::State: StateExternalities + AsExternalities<dyn StateExternalities<Config=C>>,
-	B::Auxiliary: Auxiliary<Block<C>>,
+	B: ChainQuery + Store<Block=Block<C>, Auxiliary=ShasperAuxiliary>,
+	B::State: StateExternalities<Config=C> + AsExternalities<dyn StateExternalities<Config=C>>,
 	I: SharedBlockImporter<Block=Block<C>>
 {
-	let executor = Executor::<C, BLS>::new();
-	let mut attestations = AttestationPool::<C, BLS>::new();
+	clock.wait_for_genesis();
 
 	loop {
-		thread::sleep(Duration::new(1, 0));
-
-		let head = backend.head();
-		info!("Building on top of {}", head);
-
-		let block = {
-			let head_block = backend.block_at(&head).unwrap();
-			let head_state = backend.state_at(&head).unwrap();
-			trace!("Justified epoch {}, finalized epoch {}",
-				   { head_state.state().current_justified_checkpoint.epoch },
-				   { head_state.state().finalized_checkpoint.epoch });
-
-			let mut state = backend.state_at(&head).unwrap();
-			let externalities = state.as_externalities();
-			let current_slot = head_block.0.slot + 1;
-			executor.initialize_block(externalities, current_slot).unwrap();
-			let executive = BeaconExecutive::new(externalities.state_mut());
-			let current_epoch = executive.current_epoch();
-
-			let randao_domain = executive.domain(C::domain_randao(), None);
-			let proposer_domain = executive.domain(C::domain_beacon_proposer(), None);
-			let attestation_domain = executive.domain(C::domain_beacon_attester(), None);
-
-			for (validator_id, validator_seckey) in &keys {
-				let validator_index = executive.validator_index(validator_id);
-
-				if let Some(validator_index) = validator_index {
-					let committee_assignment = executive
-						.committee_assignment(current_epoch, validator_index).unwrap();
-					if let Some(committee_assignment) = committee_assignment {
-						if committee_assignment.slot == current_slot {
-							trace!(
-								"Found validator {} attesting slot {} with index {}",
-								validator_id, current_slot, committee_assignment.index);
-							let committee = committee_assignment.validators;
-
-							let target_epoch = current_epoch;
-							let target_slot = beacon::utils::start_slot_of_epoch::<C>(target_epoch);
-							let target_root = if target_slot == current_slot {
-								head
-							} else {
-								executive.block_root(target_epoch).unwrap()
-							};
-							let source_epoch = executive.current_justified_checkpoint.epoch;
-							let source_root = executive.current_justified_checkpoint.root;
-							trace!(
-								"Casper source {} ({}) to target {} ({})",
-								source_epoch, source_root, target_epoch, target_root,
-							);
-
-							let data = AttestationData {
-								beacon_block_root: head_block.id(),
-								source: Checkpoint {
-									epoch: source_epoch,
-									root: source_root,
-								},
-								target: Checkpoint {
-									epoch: target_epoch,
-									root: target_root,
-								},
-								slot: committee_assignment.slot,
-								index: committee_assignment.index,
-							};
-							let signature = Signature::from_slice(&bls::Signature::new(
-								&tree_root::<C::Digest, _>(&AttestationDataAndCustodyBit {
-									data: data.clone(),
-									custody_bit: false,
-								})[..],
-								attestation_domain,
-								&validator_seckey,
-							).as_bytes()[..]);
-
-							let index_into_committee = committee.iter()
-								.position(|v| *v == validator_index).unwrap();
-							let mut aggregation_bitfield = Vec::new();
-							aggregation_bitfield.resize(committee.len(), false);
-							aggregation_bitfield[index_into_committee] = true;
-							let mut custody_bitfield = Vec::new();
-							custody_bitfield.resize(committee.len(), false);
-
-							let attestation = Attestation {
-								aggregation_bits: aggregation_bitfield.into(),
-								data,
-								custody_bits: custody_bitfield.into(),
-								signature
-							};
-
-							attestations.push(attestation);
-						}
-					}
+		let current_slot = clock.wait_for_next_slot();
+		let current_epoch = beacon::utils::epoch_of_slot::<C>(current_slot);
+
+		if let Some(doppelganger) = doppelganger.as_ref() {
+			let mut doppelganger = doppelganger.lock().expect("Doppelganger lock poisoned");
+			if let doppelganger::Status::Watching(_) = doppelganger.status(current_epoch) {
+				if let Err(err) = doppelganger.scan_chain(&backend) {
+					warn!("Error when scanning chain for doppelgangers: {}", err);
 				}
 			}
 
-			let proposer_index = executive.beacon_proposer_index().unwrap();
-			let proposer_pubkey = executive.validator_pubkey(proposer_index).unwrap();
-			trace!("Current proposer {} ({}) on epoch {}", proposer_index, proposer_pubkey, current_epoch);
-
-			let seckey = match keys.get(&proposer_pubkey) {
-				Some(value) => value.clone(),
-				None => {
-					warn!("No secret key, skip building block.");
-					continue;
+			match doppelganger.status(current_epoch) {
+				doppelganger::Status::Enabled => (),
+				doppelganger::Status::Watching(epoch) => {
+					info!("Watching for doppelgangers, signing is enabled at epoch {}", epoch);
+					continue
 				},
-			};
-			let randao_reveal = Signature::from_slice(&bls::Signature::new(
-				&tree_root::<C::Digest, _>(&current_epoch)[..],
-				randao_domain,
-				&seckey
-			).as_bytes()[..]);
-
-			let mut unsealed_block = executor.apply_inherent(
-				&head_block, state.as_externalities(),
-				Inherent {
-					randao_reveal,
-					eth1_data: eth1_data.clone(),
-				}
-			).unwrap();
+				doppelganger::Status::Detected(index) => {
+					warn!("Validator {} is running elsewhere, refusing to sign", index);
+					continue
+				},
+			}
+		}
 
-			let mut collected_attestations = Vec::new();
-			for (hash, attestation) in attestations.iter() {
-				match executor.apply_extrinsic(
-					&mut unsealed_block, state.as_externalities(),
-					Transaction::Attestation(attestation.clone())
+		let duties = match production::duties(&backend, current_epoch, &pubkeys) {
+			Ok(duties) => duties,
+			Err(err) => {
+				warn!("Error when computing duties: {}", err);
+				continue
+			},
+		};
+
+		match duties.iter().find(|duty| duty.block_proposal_slots.contains(&current_slot)) {
+			Some(duty) => {
+				info!("Slot {} started, building on top of {}", current_slot, backend.head());
+				if let Err(err) = build_block(
+					&backend, &importer, &publish, &pool, &mut slashing_protection, &eth1_data,
+					eth1.as_ref(), &*signer, &duty.validator_pubkey, current_slot,
 				) {
-					Ok(()) => {
-						collected_attestations.push(*hash);
-					},
-					Err(Error::Beacon(ref err)) if err == &beacon::Error::AttestationSubmittedTooQuickly => {},
-					Err(err) => {
-						warn!("Error when submitting an attestation: {}", err);
-					},
+					warn!("Error when building block at slot {}: {}", current_slot, err);
 				}
+			},
+			None => trace!("No local proposer at slot {}", current_slot),
+		}
+
+		wait_for_attestation(&backend, &clock, &notifications, current_slot);
+		let mut attested = Vec::new();
+		for duty in &duties {
+			match (
+				duty.attestation_slot,
+				duty.attestation_committee_index,
+				duty.attestation_committee_position,
+				duty.committee_length,
+			) {
+				(Some(slot), Some(index), Some(position), Some(length)) if slot == current_slot => {
+					trace!(
+						"Found validator {} attesting slot {} with index {}",
+						duty.validator_pubkey, current_slot, index,
+					);
+					match attest(
+						&backend, &pool, &publish, &events, &mut slashing_protection, &*signer,
+						&duty.validator_pubkey, current_slot, index, position as usize, length as usize,
+					) {
+						Ok(data) => attested.push((duty, data)),
+						Err(err) => warn!("Error when attesting at slot {}: {}", current_slot, err),
+					}
+				},
+				_ => (),
 			}
-			info!("Pushed {} attestations", collected_attestations.len());
-			for hash in collected_attestations {
-				attestations.pop(&hash);
+		}
+
+		if attested.is_empty() {
+			continue
+		}
+
+		clock.wait_until(current_slot, SlotClock::<C>::aggregation_offset());
+		for (duty, data) in attested {
+			if let Err(err) = aggregate(
+				&backend, &pool, &publish, &*signer, duty, &data,
+			) {
+				warn!("Error when aggregating at slot {}: {}", current_slot, err);
 			}
+		}
+	}
+}
 
-			executor.finalize_block(
-				&mut unsealed_block, state.as_externalities()
-			).unwrap();
-
-			let mut block = unsealed_block.fake_seal();
-			let signature = Signature::from_slice(&bls::Signature::new(
-				&tree_root::<C::Digest, _>(&UnsealedBeaconBlock::<C>::from(&block))[..],
-				proposer_domain,
-				&seckey
-			).as_bytes()[..]);
-			block.signature = signature;
-			Block(block)
-		};
+fn build_block<B, I, C: Config>(
+	backend: &B,
+	importer: &I,
+	publish: &PubsubSender<C>,
+	pool: &Mutex<OperationPool<C, BLS>>,
+	slashing_protection: &mut SlashingProtection,
+	eth1_data: &Eth1Data,
+	eth1: Option<&Arc<RwLock<Eth1Follower<C>>>>,
+	signer: &dyn Signer<C>,
+	pubkey: &ValidatorId,
+	slot: Slot,
+) -> Result<(), String> where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C> + AsExternalities<dyn StateExternalities<Config=C>>,
+	I: SharedBlockImporter<Block=Block<C>>
+{
+	let fork_info = production::head_fork_info(backend).map_err(|e| e.to_string())?;
+	let randao_reveal = signer.sign(
+		pubkey, &fork_info, &SigningMessage::RandaoReveal(beacon::utils::epoch_of_slot::<C>(slot)),
+	).map_err(|e| e.to_string())?;
+
+	let mut block = {
+		let mut pool = pool.lock().expect("Operation pool lock poisoned");
+		production::produce_block(
+			backend, &mut pool, eth1.map(|eth1| eth1.as_ref()), eth1_data, slot, randao_reveal,
+			H256::default(),
+		).map_err(|e| e.to_string())?
+	};
+	let message = SigningMessage::Block(block.clone());
+	slashing_protection.sign_block(pubkey, slot, message.signing_root())
+		.map_err(|e| e.to_string())?;
+	block.signature = signer.sign(pubkey, &fork_info, &message).map_err(|e| e.to_string())?;
+
+	importer.import_block(Block(block.clone())).map_err(|e| format!("{:?}", e))?;
+	publish.unbounded_send(PubsubMessage::Block(block))
+		.map_err(|e| format!("Network is not running: {:?}", e))
+}
 
-		importer.import_block(block).unwrap();
+fn attest<B, C: Config>(
+	backend: &B,
+	pool: &Mutex<OperationPool<C, BLS>>,
+	publish: &PubsubSender<C>,
+	events: &EventSender,
+	slashing_protection: &mut SlashingProtection,
+	signer: &dyn Signer<C>,
+	pubkey: &ValidatorId,
+	slot: Slot,
+	index: u64,
+	position: usize,
+	length: usize,
+) -> Result<AttestationData, String> where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C>,
+{
+	let fork_info = production::head_fork_info(backend).map_err(|e| e.to_string())?;
+	let data = production::attestation_data(backend, slot, index)
+		.map_err(|e| e.to_string())?;
+	trace!(
+		"Casper source {} ({}) to target {} ({})",
+		data.source.epoch, data.source.root, data.target.epoch, data.target.root,
+	);
+
+	let message = SigningMessage::Attestation(data.clone());
+	slashing_protection.sign_attestation(pubkey, &data, message.signing_root())
+		.map_err(|e| e.to_string())?;
+	let signature = signer.sign(pubkey, &fork_info, &message).map_err(|e| e.to_string())?;
+	let attestation = production::unaggregated_attestation::<C>(
+		data.clone(), signature, position, length,
+	);
+	{
+		let mut pool = pool.lock().expect("Operation pool lock poisoned");
+		production::submit_attestation(backend, &mut pool, attestation.clone())
+			.map_err(|e| e.to_string())?;
 	}
+
+	events.send(Topic::Attestation, &attestation);
+	publish.unbounded_send(PubsubMessage::Attestation(attestation))
+		.map_err(|e| format!("Network is not running: {:?}", e))?;
+	Ok(data)
+}
+
+fn aggregate<B, C: Config>(
+	backend: &B,
+	pool: &Mutex<OperationPool<C, BLS>>,
+	publish: &PubsubSender<C>,
+	signer: &dyn Signer<C>,
+	duty: &production::ValidatorDuty,
+	data: &AttestationData,
+) -> Result<(), String> where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C>,
+{
+	let (validator_index, length) = match (duty.validator_index, duty.committee_length) {
+		(Some(validator_index), Some(length)) => (validator_index, length),
+		_ => return Ok(()),
+	};
+
+	let fork_info = production::head_fork_info(backend).map_err(|e| e.to_string())?;
+	let selection_proof = signer.sign(
+		&duty.validator_pubkey, &fork_info, &SigningMessage::AggregationSlot(data.slot),
+	).map_err(|e| e.to_string())?;
+	if !beacon::utils::is_aggregator::<C>(length, &selection_proof) {
+		return Ok(())
+	}
+
+	let aggregate_and_proof = {
+		let pool = pool.lock().expect("Operation pool lock poisoned");
+		match production::aggregate_and_proof(&pool, validator_index, selection_proof, data) {
+			Some(aggregate_and_proof) => aggregate_and_proof,
+			None => return Ok(()),
+		}
+	};
+	trace!(
+		"Validator {} aggregating slot {} with index {}",
+		duty.validator_pubkey, data.slot, data.index,
+	);
+
+	let signature = signer.sign(
+		&duty.validator_pubkey, &fork_info,
+		&SigningMessage::AggregateAndProof(aggregate_and_proof.clone()),
+	).map_err(|e| e.to_string())?;
+	let signed = SignedAggregateAndProof { message: aggregate_and_proof, signature };
+	publish.unbounded_send(PubsubMessage::AggregateAndProof(signed))
+		.map_err(|e| format!("Network is not running: {:?}", e))
 }
@@ -13,13 +13,50 @@
 
 // You should have received a copy of the GNU General Public License along with
 // Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
-use beacon::{Config, BLSConfig};
-use beacon::primitives::H256;
-use beacon::types::{Attestation, AttestationDataAndCustodyBit};
-use std::collections::HashMap;
+use beacon::{Config, BLSConfig, BeaconExecutive, Error as BeaconError, consts};
+use beacon::primitives::{H256, ValidatorIndex, Epoch};
+use beacon::types::{
+	Attestation, AttestationData, VoluntaryExit, ProposerSlashing, AttesterSlashing,
+};
+use std::collections::{HashMap, HashSet};
 use core::marker::PhantomData;
 use bm_le::tree_root;
+use blockchain::backend::{Store, SharedCommittable, ChainQuery, ImportLock, Operation};
+use blockchain::import::ImportAction;
+use ssz::{Codec, Encode, Decode};
+use log::{info, warn};
+use crate::{Error, Block, StateExternalities, ShasperAuxiliary, AuxiliaryKey};
 
+/// Whether all bits set in `a` are also set in `b`.
+fn is_subset(a: &[bool], b: &[bool]) -> bool {
+	a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| !*a || *b)
+}
+
+/// Whether `a` and `b` have no set bits in common.
+fn is_disjoint(a: &[bool], b: &[bool]) -> bool {
+	a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| !(*a && *b))
+}
+
+/// Merge the bits and signature of `other` into `attestation`. Their
+/// aggregation bitfields must be disjoint.
+fn merge<C: Config, BLS: BLSConfig>(attestation: &mut Attestation<C>, other: &Attestation<C>) {
+	for (i, bit) in other.aggregation_bits.iter().cloned().enumerate() {
+		attestation.aggregation_bits[i] |= bit;
+	}
+	for (i, bit) in other.custody_bits.iter().cloned().enumerate() {
+		attestation.custody_bits[i] |= bit;
+	}
+	attestation.signature = BLS::aggregate_signatures(&[
+		attestation.signature, other.signature,
+	]);
+}
+
+/// Pool of attestations waiting to be included in a block.
+///
+/// Attestations are grouped by their attestation data. Within a group, an
+/// incoming attestation is aggregated into an existing one only when their
+/// aggregation bitfields are disjoint, and any attestation whose bits are
+/// covered by another one in the same group is dropped.
 pub struct AttestationPool<C: Config, BLS: BLSConfig> {
 	pool: HashMap<H256, Vec<Attestation<C>>>,
 	_marker: PhantomData<BLS>,
@@ -33,61 +70,515 @@ impl<C: Config, BLS: BLSConfig> AttestationPool<C, BLS> {
 		}
 	}
 
-	pub fn push(&mut self, attestation: Attestation<C>) {
-		let hash = tree_root::<C::Digest, _>(&AttestationDataAndCustodyBit {
-			data: attestation.data.clone(),
-			custody_bit: false,
+	/// Validate an attestation against the given state, and add it to the
+	/// pool.
+	pub fn push(
+		&mut self,
+		attestation: Attestation<C>,
+		executive: &BeaconExecutive<C>,
+	) -> Result<(), Error> {
+		if attestation.data.slot + C::slots_per_epoch() < executive.slot ||
+			attestation.data.target.epoch < executive.previous_epoch()
+		{
+			return Err(BeaconError::AttestationTooFarInHistory.into())
+		}
+
+		let indexed = executive.indexed_attestation(attestation.clone())?;
+		if !executive.is_valid_indexed_attestation::<BLS>(&indexed) {
+			return Err(BeaconError::AttestationInvalidSignature.into())
+		}
+
+		let hash = tree_root::<C::Digest, _>(&attestation.data);
+		let existings = self.pool.entry(hash).or_insert_with(Vec::new);
+
+		if existings.iter().any(|existing| {
+			is_subset(&attestation.aggregation_bits, &existing.aggregation_bits)
+		}) {
+			return Ok(())
+		}
+
+		existings.retain(|existing| {
+			!is_subset(&existing.aggregation_bits, &attestation.aggregation_bits)
 		});
 
-		self.pool.entry(hash)
-			.and_modify(|existings| {
-				let attestation = attestation.clone();
-				let mut aggregated = false;
-
-				for existing in existings.iter_mut() {
-					let has_duplicate = {
-						let mut has_duplicate = false;
-						for i in 0..existing.aggregation_bits.len() {
-							if attestation.aggregation_bits[i] {
-								has_duplicate = true;
-							}
-						}
-						has_duplicate
-					};
-
-					if has_duplicate {
-						continue
-					}
-
-					for (i, bit) in attestation.aggregation_bits.iter().cloned().enumerate() {
-						existing.aggregation_bits[i] |= bit;
-					}
-					for (i, bit) in attestation.custody_bits.iter().cloned().enumerate() {
-						existing.custody_bits[i] |= bit;
-					}
-					for i in 0..existing.custody_bits.len() {
-						assert_eq!(attestation.custody_bits[i], false);
-					}
-					existing.signature = BLS::aggregate_signatures(&[
-						existing.signature, attestation.signature.clone()
-					]);
-
-					aggregated = true;
-					break;
-				}
-
-				if !aggregated {
-					existings.push(attestation);
-				}
+		let aggregated_index = existings.iter().position(|existing| {
+			is_disjoint(&existing.aggregation_bits, &attestation.aggregation_bits)
+		});
+
+		match aggregated_index {
+			Some(index) => {
+				let aggregated = {
+					let existing = &mut existings[index];
+					merge::<C, BLS>(existing, &attestation);
+					existing.clone()
+				};
+
+				let mut i = 0;
+				existings.retain(|existing| {
+					let keep = i == index ||
+						!is_subset(&existing.aggregation_bits, &aggregated.aggregation_bits);
+					i += 1;
+					keep
+				});
+			},
+			None => existings.push(attestation),
+		}
+
+		Ok(())
+	}
+
+	/// Remove all attestations that can no longer be included in a block
+	/// built on top of the given state.
+	pub fn prune(&mut self, executive: &BeaconExecutive<C>) {
+		let slot = executive.slot;
+		let previous_epoch = executive.previous_epoch();
+		let finalized_epoch = executive.finalized_checkpoint.epoch;
+
+		self.pool.retain(|_, existings| {
+			existings.retain(|attestation| {
+				attestation.data.slot + C::slots_per_epoch() >= slot &&
+					attestation.data.target.epoch >= previous_epoch &&
+					(attestation.data.target.epoch > finalized_epoch ||
+					 attestation.data.target.epoch == C::genesis_epoch())
+			});
+			!existings.is_empty()
+		});
+	}
+
+	/// Pick attestations to include in a block built on top of the given
+	/// state.
+	///
+	/// This uses a greedy max-coverage heuristic. Each round picks the
+	/// attestation that adds the most effective balance not yet attested in
+	/// the state or by an already picked attestation.
+	pub fn select(&self, executive: &BeaconExecutive<C>) -> Vec<Attestation<C>> {
+		let current_epoch = executive.current_epoch();
+		let previous_epoch = executive.previous_epoch();
+
+		let mut covered = HashSet::<(Epoch, ValidatorIndex)>::new();
+		for pending in executive.current_epoch_attestations.iter()
+			.chain(executive.previous_epoch_attestations.iter())
+		{
+			if let Ok(indices) = executive.attesting_indices(
+				&pending.data, &pending.aggregation_bits
+			) {
+				covered.extend(indices.into_iter().map(|i| (pending.data.target.epoch, i)));
+			}
+		}
+
+		let mut candidates = self.iter()
+			.filter(|attestation| {
+				let data = &attestation.data;
+				let source = if data.target.epoch == current_epoch {
+					&executive.current_justified_checkpoint
+				} else if data.target.epoch == previous_epoch {
+					&executive.previous_justified_checkpoint
+				} else {
+					return false
+				};
+
+				data.slot + C::min_attestation_inclusion_delay() <= executive.slot &&
+					executive.slot <= data.slot + C::slots_per_epoch() &&
+					data.index < executive.committee_count_at_slot(data.slot) &&
+					&data.source == source
+			})
+			.filter_map(|attestation| {
+				executive.attesting_indices(&attestation.data, &attestation.aggregation_bits)
+					.ok()
+					.map(|indices| (attestation, indices))
 			})
-			.or_insert(vec![attestation]);
-    }
+			.collect::<Vec<_>>();
 
-	pub fn pop(&mut self, key: &H256) {
-		self.pool.remove(key);
+		let mut selected = Vec::new();
+		while (selected.len() as u64) < C::max_attestations() {
+			let best = candidates.iter()
+				.enumerate()
+				.map(|(i, (attestation, indices))| {
+					let epoch = attestation.data.target.epoch;
+					let gain = indices.iter()
+						.filter(|index| !covered.contains(&(epoch, **index)))
+						.map(|index| executive.validators[*index as usize].effective_balance)
+						.sum::<u64>();
+					(i, gain)
+				})
+				.max_by_key(|(_, gain)| *gain);
+
+			match best {
+				Some((i, gain)) if gain > 0 => {
+					let (attestation, indices) = candidates.swap_remove(i);
+					let epoch = attestation.data.target.epoch;
+					covered.extend(indices.into_iter().map(|index| (epoch, index)));
+					selected.push(attestation.clone());
+				},
+				_ => break,
+			}
+		}
+
+		selected
+	}
+
+	/// Best aggregate of the attestations in the pool with the given data.
+	///
+	/// Starting from the attestation with the most bits set, every other
+	/// attestation whose bits are disjoint from the aggregate so far is
+	/// merged into it.
+	pub fn aggregate(&self, data: &AttestationData) -> Option<Attestation<C>> {
+		self.aggregate_by_root(&tree_root::<C::Digest, _>(data))
+	}
+
+	/// Best aggregate of the attestations in the pool whose data has the
+	/// given root.
+	pub fn aggregate_by_root(&self, data_root: &H256) -> Option<Attestation<C>> {
+		let mut existings = self.pool.get(data_root)?.iter().collect::<Vec<_>>();
+		existings.sort_by_key(|attestation| {
+			core::cmp::Reverse(attestation.aggregation_bits.iter().filter(|bit| **bit).count())
+		});
+
+		let mut existings = existings.into_iter();
+		let mut aggregate = existings.next()?.clone();
+		for attestation in existings {
+			if is_disjoint(&aggregate.aggregation_bits, &attestation.aggregation_bits) {
+				merge::<C, BLS>(&mut aggregate, attestation);
+			}
+		}
+
+		Some(aggregate)
+	}
+
+	pub fn len(&self) -> usize {
+		self.pool.values().map(|ats| ats.len()).sum()
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item=&Attestation<C>> {
+		self.pool.values().flat_map(|ats| ats.iter())
 	}
+}
+
+/// Operation pools as persisted in the backend.
+#[derive(Codec, Encode, Decode)]
+struct PersistedOperationPool<C: Config> {
+	attestations: Vec<Attestation<C>>,
+	voluntary_exits: Vec<VoluntaryExit>,
+	proposer_slashings: Vec<ProposerSlashing>,
+	attester_slashings: Vec<AttesterSlashing<C>>,
+}
+
+/// All operation pools of the local node.
+///
+/// Voluntary exits and proposer slashings are kept once per validator, and
+/// attester slashings once per slashing.
+pub struct OperationPool<C: Config, BLS: BLSConfig> {
+	pub attestations: AttestationPool<C, BLS>,
+	voluntary_exits: HashMap<ValidatorIndex, VoluntaryExit>,
+	proposer_slashings: HashMap<ValidatorIndex, ProposerSlashing>,
+	attester_slashings: HashMap<H256, AttesterSlashing<C>>,
+}
+
+impl<C: Config, BLS: BLSConfig> OperationPool<C, BLS> {
+	pub fn new() -> Self {
+		Self {
+			attestations: AttestationPool::new(),
+			voluntary_exits: Default::default(),
+			proposer_slashings: Default::default(),
+			attester_slashings: Default::default(),
+		}
+	}
+
+	/// Validate a voluntary exit against the given state, and add it to the
+	/// pool. Returns whether it was not already in the pool.
+	///
+	/// Validation processes the exit, so the state must be discarded
+	/// afterwards.
+	pub fn push_voluntary_exit(
+		&mut self,
+		exit: VoluntaryExit,
+		executive: &mut BeaconExecutive<C>,
+	) -> Result<bool, Error> {
+		if self.voluntary_exits.contains_key(&exit.validator_index) {
+			return Ok(false)
+		}
+
+		executive.process_voluntary_exit::<BLS>(exit.clone())?;
+		self.voluntary_exits.insert(exit.validator_index, exit);
+		Ok(true)
+	}
+
+	/// Validate a proposer slashing against the given state, and add it to
+	/// the pool. Returns whether it was not already in the pool.
+	///
+	/// Validation processes the slashing, so the state must be discarded
+	/// afterwards.
+	pub fn push_proposer_slashing(
+		&mut self,
+		slashing: ProposerSlashing,
+		executive: &mut BeaconExecutive<C>,
+	) -> Result<bool, Error> {
+		if self.proposer_slashings.contains_key(&slashing.proposer_index) {
+			return Ok(false)
+		}
+
+		executive.process_proposer_slashing::<BLS>(slashing.clone())?;
+		self.proposer_slashings.insert(slashing.proposer_index, slashing);
+		Ok(true)
+	}
+
+	/// Validate an attester slashing against the given state, and add it to
+	/// the pool. Returns whether it was not already in the pool.
+	///
+	/// Validation processes the slashing, so the state must be discarded
+	/// afterwards.
+	pub fn push_attester_slashing(
+		&mut self,
+		slashing: AttesterSlashing<C>,
+		executive: &mut BeaconExecutive<C>,
+	) -> Result<bool, Error> {
+		let hash = tree_root::<C::Digest, _>(&slashing);
+		if self.attester_slashings.contains_key(&hash) {
+			return Ok(false)
+		}
+
+		executive.process_attester_slashing::<BLS>(slashing.clone())?;
+		self.attester_slashings.insert(hash, slashing);
+		Ok(true)
+	}
+
+	pub fn voluntary_exits(&self) -> impl Iterator<Item=&VoluntaryExit> {
+		self.voluntary_exits.values()
+	}
+
+	pub fn proposer_slashings(&self) -> impl Iterator<Item=&ProposerSlashing> {
+		self.proposer_slashings.values()
+	}
+
+	pub fn attester_slashings(&self) -> impl Iterator<Item=&AttesterSlashing<C>> {
+		self.attester_slashings.values()
+	}
+
+	/// Load operation pools previously persisted in the backend. Every
+	/// operation is re-validated against the current head state, and those
+	/// no longer valid are dropped.
+	pub fn load<B>(backend: &B) -> Result<Self, Error> where
+		B: ChainQuery + Store<Block=Block<C>, Auxiliary=ShasperAuxiliary>,
+		B::State: StateExternalities<Config=C>,
+	{
+		let mut pool = Self::new();
+
+		let raw = match backend.auxiliary(&AuxiliaryKey::OperationPool)
+			.map_err(Error::backend)?
+		{
+			Some(ShasperAuxiliary::OperationPool(raw)) => raw,
+			None => return Ok(pool),
+		};
+		let persisted = match PersistedOperationPool::<C>::decode(&raw) {
+			Ok(persisted) => persisted,
+			Err(err) => {
+				warn!("Discarding undecodable persisted operation pool: {:?}", err);
+				return Ok(pool)
+			},
+		};
+
+		let mut state = backend.state_at(&backend.head())
+			.map_err(Error::backend)?;
+		let head_state = state.state().clone();
+		let executive = BeaconExecutive::new(state.state_mut());
+
+		let total = persisted.attestations.len();
+		for attestation in persisted.attestations {
+			let _ = pool.attestations.push(attestation, &executive);
+		}
+		info!("Loaded {} of {} persisted attestations", pool.attestations.len(), total);
+
+		for exit in persisted.voluntary_exits {
+			let mut scratch = head_state.clone();
+			let _ = pool.push_voluntary_exit(exit, &mut BeaconExecutive::new(&mut scratch));
+		}
+		for slashing in persisted.proposer_slashings {
+			let mut scratch = head_state.clone();
+			let _ = pool.push_proposer_slashing(slashing, &mut BeaconExecutive::new(&mut scratch));
+		}
+		for slashing in persisted.attester_slashings {
+			let mut scratch = head_state.clone();
+			let _ = pool.push_attester_slashing(slashing, &mut BeaconExecutive::new(&mut scratch));
+		}
+
+		Ok(pool)
+	}
+
+	/// Remove all operations that can no longer be included in a block built
+	/// on top of the block `head`.
+	pub fn prune_at<B>(&mut self, backend: &B, head: &H256) -> Result<(), Error> where
+		B: ChainQuery + Store<Block=Block<C>>,
+		B::State: StateExternalities<Config=C>,
+	{
+		let mut state = backend.state_at(head)
+			.map_err(Error::backend)?;
+		let executive = BeaconExecutive::new(state.state_mut());
+		self.attestations.prune(&executive);
+
+		let current_epoch = executive.current_epoch();
+		let is_slashable = |index: &ValidatorIndex| {
+			executive.validators.get(*index as usize)
+				.map(|validator| validator.is_slashable(current_epoch))
+				.unwrap_or(false)
+		};
+
+		self.voluntary_exits.retain(|index, _| {
+			executive.validators.get(*index as usize)
+				.map(|validator| validator.exit_epoch == consts::FAR_FUTURE_EPOCH)
+				.unwrap_or(false)
+		});
+		self.proposer_slashings.retain(|index, _| is_slashable(index));
+		self.attester_slashings.retain(|_, slashing| {
+			slashing.attestation_1.custody_bit_0_indices.iter()
+				.chain(slashing.attestation_1.custody_bit_1_indices.iter())
+				.chain(slashing.attestation_2.custody_bit_0_indices.iter())
+				.chain(slashing.attestation_2.custody_bit_1_indices.iter())
+				.any(|index| is_slashable(index))
+		});
+		Ok(())
+	}
+
+	/// Persist operation pools into the backend.
+	pub fn persist<B>(&self, backend: &B, import_lock: &ImportLock) -> Result<(), Error> where
+		B: ChainQuery + Store<Block=Block<C>, Auxiliary=ShasperAuxiliary>,
+		B: SharedCommittable<Operation=Operation<<B as Store>::Block, <B as Store>::State, <B as Store>::Auxiliary>>,
+	{
+		let persisted = PersistedOperationPool::<C> {
+			attestations: self.attestations.iter().cloned().collect(),
+			voluntary_exits: self.voluntary_exits.values().cloned().collect(),
+			proposer_slashings: self.proposer_slashings.values().cloned().collect(),
+			attester_slashings: self.attester_slashings.values().cloned().collect(),
+		};
+
+		let mut action = ImportAction::new(backend, import_lock.lock());
+		action.insert_auxiliary(ShasperAuxiliary::OperationPool(persisted.encode()));
+		action.commit().map_err(Error::backend)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use beacon::{BeaconState, BLSNoVerification};
+	use beacon::primitives::Signature;
+	use beacon::types::Checkpoint;
+	use crate::production::unaggregated_attestation;
+	use crate::testing::{self, TestConfig};
+
+	type TestPool = AttestationPool<TestConfig, BLSNoVerification>;
+
+	fn state_at(slot: u64) -> BeaconState<TestConfig> {
+		let mut state = testing::genesis_state(32);
+		if slot > 0 {
+			beacon::initialize_block::<TestConfig>(&mut state, slot).unwrap();
+		}
+		state
+	}
+
+	fn attestation(
+		executive: &BeaconExecutive<TestConfig>,
+		slot: u64,
+		target_epoch: u64,
+		root: u8,
+		bits: &[usize],
+	) -> Attestation<TestConfig> {
+		let committee_length = executive.beacon_committee(slot, 0).unwrap().len();
+		let data = AttestationData {
+			slot,
+			index: 0,
+			beacon_block_root: H256::repeat_byte(root),
+			target: Checkpoint { epoch: target_epoch, ..Default::default() },
+			..Default::default()
+		};
+
+		let mut attestation = unaggregated_attestation(
+			data, Signature::default(), bits[0], committee_length,
+		);
+		for bit in &bits[1..] {
+			attestation.aggregation_bits[*bit] = true;
+		}
+		attestation
+	}
+
+	fn bits(attestation: &Attestation<TestConfig>) -> Vec<usize> {
+		attestation.aggregation_bits.iter()
+			.enumerate()
+			.filter(|(_, bit)| **bit)
+			.map(|(i, _)| i)
+			.collect()
+	}
+
+	#[test]
+	fn disjoint_attestations_are_aggregated() {
+		let mut state = state_at(1);
+		let executive = BeaconExecutive::new(&mut state);
+		let mut pool = TestPool::new();
+
+		pool.push(attestation(&executive, 0, 0, 1, &[0]), &executive).unwrap();
+		pool.push(attestation(&executive, 0, 0, 1, &[1]), &executive).unwrap();
+		assert_eq!(pool.len(), 1);
+
+		pool.push(attestation(&executive, 0, 0, 1, &[0, 2]), &executive).unwrap();
+		assert_eq!(pool.len(), 2);
+
+		pool.push(attestation(&executive, 0, 0, 1, &[3]), &executive).unwrap();
+		assert_eq!(pool.len(), 2);
+
+		let data = attestation(&executive, 0, 0, 1, &[0]).data;
+		assert_eq!(bits(&pool.aggregate(&data).unwrap()), vec![0, 1, 3]);
+	}
+
+	#[test]
+	fn covered_attestations_are_dropped() {
+		let mut state = state_at(1);
+		let executive = BeaconExecutive::new(&mut state);
+		let mut pool = TestPool::new();
+		let data = attestation(&executive, 0, 0, 1, &[0]).data;
+
+		pool.push(attestation(&executive, 0, 0, 1, &[0, 1]), &executive).unwrap();
+		pool.push(attestation(&executive, 0, 0, 1, &[0]), &executive).unwrap();
+		assert_eq!(pool.len(), 1);
+		assert_eq!(bits(&pool.aggregate(&data).unwrap()), vec![0, 1]);
+
+		pool.push(attestation(&executive, 0, 0, 1, &[0, 1, 2]), &executive).unwrap();
+		assert_eq!(pool.len(), 1);
+		assert_eq!(bits(&pool.aggregate(&data).unwrap()), vec![0, 1, 2]);
+	}
+
+	#[test]
+	fn select_picks_greedy_max_coverage() {
+		let mut state = state_at(1);
+		let executive = BeaconExecutive::new(&mut state);
+		let mut pool = TestPool::new();
+
+		pool.push(attestation(&executive, 0, 0, 1, &[0, 1, 2]), &executive).unwrap();
+		pool.push(attestation(&executive, 0, 0, 2, &[0, 1]), &executive).unwrap();
+		pool.push(attestation(&executive, 0, 0, 3, &[3]), &executive).unwrap();
+
+		let selected = pool.select(&executive);
+		assert_eq!(selected.len(), 2);
+		assert_eq!(selected[0].data.beacon_block_root, H256::repeat_byte(1));
+		assert_eq!(selected[1].data.beacon_block_root, H256::repeat_byte(3));
+	}
+
+	#[test]
+	fn prune_removes_attestations_out_of_inclusion_range() {
+		let slots_per_epoch = TestConfig::slots_per_epoch();
+		let mut pool = TestPool::new();
+
+		let mut state = state_at(1);
+		let executive = BeaconExecutive::new(&mut state);
+		pool.push(attestation(&executive, 0, 0, 1, &[0]), &executive).unwrap();
+
+		let mut state = state_at(2 * slots_per_epoch);
+		let executive = BeaconExecutive::new(&mut state);
+		pool.push(
+			attestation(&executive, 2 * slots_per_epoch - 1, 1, 2, &[0]), &executive,
+		).unwrap();
+		assert_eq!(pool.len(), 2);
 
-	pub fn iter(&self) -> impl Iterator<Item=(&H256, &Attestation<C>)> {
-		self.pool.iter().flat_map(|(h, ats)| ats.iter().map(move |at| (h, at)))
+		pool.prune(&executive);
+		assert_eq!(pool.len(), 1);
+		assert!(pool.iter().all(|attestation| attestation.data.target.epoch == 1));
 	}
 }
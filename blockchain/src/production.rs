@@ -0,0 +1,553 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Parity Shasper.
+
+// Parity Shasper is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+
+// Parity Shasper is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more
+// details.
+
+// You should have received a copy of the GNU General Public License along with
+// Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
+//! Duties, block and attestation production, shared by the built-in block
+//! builder and the validator API.
+
+use beacon::{Config, BLSConfig, BeaconExecutive, Inherent, Transaction, Error as BeaconError};
+use beacon::primitives::{H256, Signature, ValidatorId, ValidatorIndex, Uint};
+use beacon::types::{
+	Attestation, AttestationData, BeaconBlock, Checkpoint, Eth1Data, Fork,
+	AggregateAndProof, SignedAggregateAndProof, VoluntaryExit, ProposerSlashing, AttesterSlashing,
+	UnsealedBeaconBlock,
+};
+use beacon::utils;
+use blockchain::{AsExternalities, Block as BlockT};
+use blockchain::backend::{ChainQuery, Store};
+use shasper_eth1::Eth1Follower;
+use serde::{Serialize, Deserialize};
+use std::sync::RwLock;
+use crypto::signer::ForkInfo;
+use log::{info, warn};
+use crate::{Error, Block, Executor, OperationPool, StateExternalities, genesis, gossip};
+
+/// Duties of a validator in an epoch.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct ValidatorDuty {
+	/// Public key of the validator.
+	pub validator_pubkey: ValidatorId,
+	/// Index of the validator, if it is in the registry.
+	pub validator_index: Option<u64>,
+	/// Slot at which the validator attests.
+	pub attestation_slot: Option<u64>,
+	/// Index of the committee the validator attests in.
+	pub attestation_committee_index: Option<u64>,
+	/// Position of the validator in its committee.
+	pub attestation_committee_position: Option<u64>,
+	/// Size of the committee the validator attests in.
+	pub committee_length: Option<u64>,
+	/// Slots at which the validator proposes a block.
+	pub block_proposal_slots: Vec<u64>,
+}
+
+/// Head block, and head state processed to `slot` if it is ahead of the
+/// head.
+pub(crate) fn head_state_at<B, C: Config>(backend: &B, slot: Uint) -> Result<(Block<C>, B::State), Error> where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C>,
+{
+	let head = backend.head();
+	let head_block = backend.block_at(&head)
+		.map_err(Error::backend)?;
+	let mut state = backend.state_at(&head)
+		.map_err(Error::backend)?;
+
+	if slot > state.state().slot {
+		beacon::initialize_block::<C>(state.state_mut(), slot)?;
+	}
+	Ok((head_block, state))
+}
+
+/// Compute duties of the given validators in `epoch`, which must not be
+/// before the epoch of the head.
+pub fn duties<B, C: Config>(
+	backend: &B,
+	epoch: Uint,
+	pubkeys: &[ValidatorId],
+) -> Result<Vec<ValidatorDuty>, Error> where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C>,
+{
+	let (_, mut state) = head_state_at(backend, utils::start_slot_of_epoch::<C>(epoch))?;
+	let executive = BeaconExecutive::new(state.state_mut());
+	if executive.current_epoch() != epoch {
+		return Err(BeaconError::EpochOutOfRange.into())
+	}
+	let proposers = executive.proposer_assignments()?;
+
+	pubkeys.iter().map(|pubkey| {
+		let validator_index = executive.validator_index(pubkey);
+		let mut duty = ValidatorDuty {
+			validator_pubkey: pubkey.clone(),
+			validator_index,
+			attestation_slot: None,
+			attestation_committee_index: None,
+			attestation_committee_position: None,
+			committee_length: None,
+			block_proposal_slots: Vec::new(),
+		};
+
+		if let Some(validator_index) = validator_index {
+			if let Some(assignment) = executive.committee_assignment(epoch, validator_index)? {
+				duty.attestation_slot = Some(assignment.slot);
+				duty.attestation_committee_index = Some(assignment.index);
+				duty.attestation_committee_position = assignment.validators.iter()
+					.position(|v| *v == validator_index)
+					.map(|position| position as u64);
+				duty.committee_length = Some(assignment.validators.len() as u64);
+			}
+
+			duty.block_proposal_slots = proposers.iter()
+				.filter(|(_, proposer)| *proposer == validator_index)
+				.map(|(slot, _)| *slot)
+				.collect();
+		}
+
+		Ok(duty)
+	}).collect()
+}
+
+/// Proposer of a slot.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct ProposerDuty {
+	/// Public key of the proposer.
+	pub pubkey: ValidatorId,
+	/// Index of the proposer.
+	#[serde(serialize_with = "utils::serialize_uint", deserialize_with = "utils::deserialize_uint")]
+	pub validator_index: Uint,
+	/// Slot to propose at.
+	#[serde(serialize_with = "utils::serialize_uint", deserialize_with = "utils::deserialize_uint")]
+	pub slot: Uint,
+}
+
+/// Attestation duty of a validator in an epoch.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct AttesterDuty {
+	/// Public key of the validator.
+	pub pubkey: ValidatorId,
+	/// Index of the validator.
+	#[serde(serialize_with = "utils::serialize_uint", deserialize_with = "utils::deserialize_uint")]
+	pub validator_index: Uint,
+	/// Index of the committee the validator attests in.
+	#[serde(serialize_with = "utils::serialize_uint", deserialize_with = "utils::deserialize_uint")]
+	pub committee_index: Uint,
+	/// Size of the committee.
+	#[serde(serialize_with = "utils::serialize_uint", deserialize_with = "utils::deserialize_uint")]
+	pub committee_length: Uint,
+	/// Number of committees at the slot.
+	#[serde(serialize_with = "utils::serialize_uint", deserialize_with = "utils::deserialize_uint")]
+	pub committees_at_slot: Uint,
+	/// Position of the validator in the committee.
+	#[serde(serialize_with = "utils::serialize_uint", deserialize_with = "utils::deserialize_uint")]
+	pub validator_committee_index: Uint,
+	/// Slot to attest at.
+	#[serde(serialize_with = "utils::serialize_uint", deserialize_with = "utils::deserialize_uint")]
+	pub slot: Uint,
+}
+
+/// Proposers of all slots in `epoch`, which must not be before the epoch of
+/// the head.
+pub fn proposer_duties<B, C: Config>(backend: &B, epoch: Uint) -> Result<Vec<ProposerDuty>, Error> where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C>,
+{
+	let (_, mut state) = head_state_at(backend, utils::start_slot_of_epoch::<C>(epoch))?;
+	let executive = BeaconExecutive::new(state.state_mut());
+	if executive.current_epoch() != epoch {
+		return Err(BeaconError::EpochOutOfRange.into())
+	}
+
+	executive.proposer_assignments()?.into_iter().map(|(slot, validator_index)| {
+		Ok(ProposerDuty {
+			pubkey: executive.validator_pubkey(validator_index)
+				.ok_or(BeaconError::IndexOutOfRange)?,
+			validator_index,
+			slot,
+		})
+	}).collect()
+}
+
+/// Attestation duties in `epoch` of the validators at `indices`, which must
+/// not be before the epoch of the head. Validators without a committee
+/// assignment are skipped.
+pub fn attester_duties<B, C: Config>(
+	backend: &B,
+	epoch: Uint,
+	indices: &[Uint],
+) -> Result<Vec<AttesterDuty>, Error> where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C>,
+{
+	let (_, mut state) = head_state_at(backend, utils::start_slot_of_epoch::<C>(epoch))?;
+	let executive = BeaconExecutive::new(state.state_mut());
+	if executive.current_epoch() != epoch {
+		return Err(BeaconError::EpochOutOfRange.into())
+	}
+
+	let mut duties = Vec::new();
+	for validator_index in indices {
+		let pubkey = executive.validator_pubkey(*validator_index)
+			.ok_or(BeaconError::IndexOutOfRange)?;
+		if let Some(assignment) = executive.committee_assignment(epoch, *validator_index)? {
+			let position = assignment.validators.iter()
+				.position(|v| v == validator_index)
+				.expect("Committee assignment contains the validator; qed");
+
+			duties.push(AttesterDuty {
+				pubkey,
+				validator_index: *validator_index,
+				committee_index: assignment.index,
+				committee_length: assignment.validators.len() as Uint,
+				committees_at_slot: executive.committee_count_at_slot(assignment.slot),
+				validator_committee_index: position as Uint,
+				slot: assignment.slot,
+			});
+		}
+	}
+	Ok(duties)
+}
+
+/// Fork of the head state.
+pub fn head_fork<B, C: Config>(backend: &B) -> Result<Fork, Error> where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C>,
+{
+	let state = backend.state_at(&backend.head())
+		.map_err(Error::backend)?;
+	Ok(state.state().fork.clone())
+}
+
+/// Root of the validator registry of the genesis state.
+pub fn genesis_validators_root<B, C: Config>(backend: &B) -> Result<H256, Error> where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C>,
+{
+	let state = backend.state_at(&backend.genesis())
+		.map_err(Error::backend)?;
+	Ok(genesis::genesis_validators_root(state.state()))
+}
+
+/// Fork of the head state, for signing messages.
+pub fn head_fork_info<B, C: Config>(backend: &B) -> Result<ForkInfo, Error> where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C>,
+{
+	Ok(ForkInfo {
+		fork: head_fork(backend)?,
+		genesis_validators_root: genesis_validators_root(backend)?,
+	})
+}
+
+/// Attestation data for the committee at `index` in `slot`, voting for the
+/// current fork choice head.
+pub fn attestation_data<B, C: Config>(
+	backend: &B,
+	slot: Uint,
+	index: Uint,
+) -> Result<AttestationData, Error> where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C>,
+{
+	let (head_block, mut state) = head_state_at(backend, slot)?;
+	let executive = BeaconExecutive::new(state.state_mut());
+	if index >= executive.committee_count_at_slot(slot) {
+		return Err(BeaconError::AttestationInvalidData.into())
+	}
+
+	// The target is the latest block at or before the epoch boundary slot.
+	// When the head is not after the boundary, either the boundary block
+	// is the head or the boundary slot was skipped and the head is the
+	// latest block before it.
+	let target_epoch = utils::epoch_of_slot::<C>(slot);
+	let target_slot = utils::start_slot_of_epoch::<C>(target_epoch);
+	let target_root = if head_block.0.slot <= target_slot {
+		head_block.id()
+	} else {
+		executive.block_root(target_epoch)?
+	};
+
+	Ok(AttestationData {
+		beacon_block_root: head_block.id(),
+		source: executive.current_justified_checkpoint.clone(),
+		target: Checkpoint {
+			epoch: target_epoch,
+			root: target_root,
+		},
+		slot,
+		index,
+	})
+}
+
+/// Validate an attestation against the head state, and add it to the pool.
+pub fn submit_attestation<B, C: Config, BLS: BLSConfig>(
+	backend: &B,
+	pool: &mut OperationPool<C, BLS>,
+	attestation: Attestation<C>,
+) -> Result<(), Error> where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C>,
+{
+	let (_, mut state) = head_state_at(backend, attestation.data.slot)?;
+	let executive = BeaconExecutive::new(state.state_mut());
+	pool.attestations.push(attestation, &executive)
+}
+
+/// Validators voting for the beacon block of an attestation, as votes for
+/// fork choice.
+pub fn attestation_votes<B, C: Config>(
+	backend: &B,
+	attestation: &Attestation<C>,
+) -> Result<Vec<(ValidatorIndex, H256)>, Error> where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C>,
+{
+	let (_, mut state) = head_state_at(backend, attestation.data.slot)?;
+	let executive = BeaconExecutive::new(state.state_mut());
+	let indices = executive.attesting_indices(&attestation.data, &attestation.aggregation_bits)?;
+	Ok(indices.into_iter()
+	   .map(|index| (index, attestation.data.beacon_block_root))
+	   .collect())
+}
+
+/// Validate a voluntary exit against the head state, and add it to the pool.
+/// Returns whether it was not already in the pool.
+pub fn submit_voluntary_exit<B, C: Config, BLS: BLSConfig>(
+	backend: &B,
+	pool: &mut OperationPool<C, BLS>,
+	exit: VoluntaryExit,
+) -> Result<bool, Error> where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C>,
+{
+	let mut state = backend.state_at(&backend.head())
+		.map_err(Error::backend)?;
+	pool.push_voluntary_exit(exit, &mut BeaconExecutive::new(state.state_mut()))
+}
+
+/// Validate a proposer slashing against the head state, and add it to the
+/// pool. Returns whether it was not already in the pool.
+pub fn submit_proposer_slashing<B, C: Config, BLS: BLSConfig>(
+	backend: &B,
+	pool: &mut OperationPool<C, BLS>,
+	slashing: ProposerSlashing,
+) -> Result<bool, Error> where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C>,
+{
+	let mut state = backend.state_at(&backend.head())
+		.map_err(Error::backend)?;
+	pool.push_proposer_slashing(slashing, &mut BeaconExecutive::new(state.state_mut()))
+}
+
+/// Validate an attester slashing against the head state, and add it to the
+/// pool. Returns whether it was not already in the pool.
+pub fn submit_attester_slashing<B, C: Config, BLS: BLSConfig>(
+	backend: &B,
+	pool: &mut OperationPool<C, BLS>,
+	slashing: AttesterSlashing<C>,
+) -> Result<bool, Error> where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C>,
+{
+	let mut state = backend.state_at(&backend.head())
+		.map_err(Error::backend)?;
+	pool.push_attester_slashing(slashing, &mut BeaconExecutive::new(state.state_mut()))
+}
+
+/// Aggregate of the pooled attestations with the given data, to be sent by
+/// the aggregator at `aggregator_index`.
+pub fn aggregate_and_proof<C: Config, BLS: BLSConfig>(
+	pool: &OperationPool<C, BLS>,
+	aggregator_index: Uint,
+	selection_proof: Signature,
+	data: &AttestationData,
+) -> Option<AggregateAndProof<C>> {
+	pool.attestations.aggregate(data).map(|aggregate| AggregateAndProof {
+		index: aggregator_index,
+		selection_proof,
+		aggregate,
+	})
+}
+
+/// Validate an aggregate and proof received while the clock is between
+/// `earliest_slot` and `latest_slot`, and add its aggregate to the pool.
+pub fn submit_aggregate_and_proof<B, C: Config, BLS: BLSConfig>(
+	backend: &B,
+	pool: &mut OperationPool<C, BLS>,
+	earliest_slot: Uint,
+	latest_slot: Uint,
+	signed: SignedAggregateAndProof<C>,
+) -> Result<(), Error> where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C>,
+{
+	gossip::validate_aggregate_and_proof::<_, _, BLS>(
+		backend, earliest_slot, latest_slot, &signed,
+	)?;
+	submit_attestation(backend, pool, signed.message.aggregate)
+}
+
+/// Apply pooled operations of one kind to a block being built, up to `max`
+/// of them. Operations failing to apply are skipped.
+fn apply_operations<C: Config, BLS: BLSConfig, T>(
+	executor: &Executor<C, BLS>,
+	block: &mut UnsealedBeaconBlock<C>,
+	state: &mut (dyn StateExternalities<Config=C> + 'static),
+	operations: impl Iterator<Item=T>,
+	wrap: impl Fn(T) -> Transaction<C>,
+	max: Uint,
+	name: &str,
+) {
+	let mut collected = 0;
+	for operation in operations {
+		if collected >= max {
+			break
+		}
+		match executor.apply_extrinsic(block, state, wrap(operation)) {
+			Ok(()) => collected += 1,
+			Err(err) => warn!("Error when submitting {}: {}", name, err),
+		}
+	}
+	if collected > 0 {
+		info!("Pushed {} {}s", collected, name);
+	}
+}
+
+/// Build an unsigned block at `slot` on top of the current head, including
+/// slashings, attestations and voluntary exits from the pool and pending
+/// deposits.
+pub fn produce_block<B, C: Config, BLS: BLSConfig>(
+	backend: &B,
+	pool: &mut OperationPool<C, BLS>,
+	eth1: Option<&RwLock<Eth1Follower<C>>>,
+	eth1_data: &Eth1Data,
+	slot: Uint,
+	randao_reveal: Signature,
+	graffiti: H256,
+) -> Result<BeaconBlock<C>, Error> where
+	B: ChainQuery + Store<Block=Block<C>>,
+	B::State: StateExternalities<Config=C> + AsExternalities<dyn StateExternalities<Config=C>>,
+{
+	let executor = Executor::<C, BLS>::new();
+	let (head_block, mut state) = head_state_at(backend, slot)?;
+	if head_block.0.slot >= slot {
+		return Err(BeaconError::SlotOutOfRange.into())
+	}
+
+	let (selected_attestations, eth1_vote) = {
+		let executive = BeaconExecutive::new(state.state_mut());
+		pool.attestations.prune(&executive);
+
+		let eth1_vote = match eth1 {
+			Some(eth1) => eth1.read().expect("Eth1 follower lock poisoned")
+				.eth1_vote(&executive)
+				.unwrap_or_else(|| executive.eth1_data.clone()),
+			None => eth1_data.clone(),
+		};
+		(pool.attestations.select(&executive), eth1_vote)
+	};
+
+	let mut unsealed_block = executor.apply_inherent(
+		&head_block, state.as_externalities(),
+		Inherent {
+			randao_reveal,
+			eth1_data: eth1_vote,
+			graffiti,
+		}
+	)?;
+
+	apply_operations(
+		&executor, &mut unsealed_block, state.as_externalities(),
+		pool.proposer_slashings().cloned(), Transaction::ProposerSlashing,
+		C::max_proposer_slashings(), "proposer slashing",
+	);
+	apply_operations(
+		&executor, &mut unsealed_block, state.as_externalities(),
+		pool.attester_slashings().cloned(), Transaction::AttesterSlashing,
+		C::max_attester_slashings(), "attester slashing",
+	);
+
+	let mut collected_attestations = 0;
+	for attestation in selected_attestations {
+		match executor.apply_extrinsic(
+			&mut unsealed_block, state.as_externalities(),
+			Transaction::Attestation(attestation)
+		) {
+			Ok(()) => {
+				collected_attestations += 1;
+			},
+			Err(err) => {
+				warn!("Error when submitting an attestation: {}", err);
+			},
+		}
+	}
+	info!("Pushed {} attestations", collected_attestations);
+
+	if let Some(eth1) = eth1 {
+		let deposits = eth1.read().expect("Eth1 follower lock poisoned")
+			.deposits(state.as_externalities().state());
+		match deposits {
+			Ok(deposits) => {
+				let mut collected_deposits = 0;
+				for deposit in deposits {
+					match executor.apply_extrinsic(
+						&mut unsealed_block, state.as_externalities(),
+						Transaction::Deposit(deposit)
+					) {
+						Ok(()) => {
+							collected_deposits += 1;
+						},
+						Err(err) => {
+							warn!("Error when submitting a deposit: {}", err);
+							break
+						},
+					}
+				}
+				info!("Pushed {} deposits", collected_deposits);
+			},
+			Err(err) => {
+				warn!("Error when collecting deposits: {}", err);
+			},
+		}
+	}
+
+	apply_operations(
+		&executor, &mut unsealed_block, state.as_externalities(),
+		pool.voluntary_exits().cloned(), Transaction::VoluntaryExit,
+		C::max_voluntary_exits(), "voluntary exit",
+	);
+
+	executor.finalize_block(&mut unsealed_block, state.as_externalities())?;
+	Ok(unsealed_block.fake_seal())
+}
+
+/// Wrap the signature of an attester into an unaggregated attestation for
+/// the given position in a committee.
+pub fn unaggregated_attestation<C: Config>(
+	data: AttestationData,
+	signature: Signature,
+	committee_position: usize,
+	committee_length: usize,
+) -> Attestation<C> {
+	let mut aggregation_bits = vec![false; committee_length];
+	aggregation_bits[committee_position] = true;
+
+	Attestation {
+		aggregation_bits: aggregation_bits.into(),
+		data,
+		custody_bits: vec![false; committee_length].into(),
+		signature,
+	}
+}
@@ -0,0 +1,430 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Parity Shasper.
+
+// Parity Shasper is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+
+// Parity Shasper is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more
+// details.
+
+// You should have received a copy of the GNU General Public License along with
+// Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Slashing protection for local validators. Every signed block slot and
+//! attestation source and target is recorded per validator, and signing
+//! anything slashable with respect to the records is refused. Records are
+//! persisted in the EIP-3076 interchange format. As interchanges may be
+//! pruned, nothing older than the imported records is signed either.
+
+use beacon::primitives::{H256, ValidatorId, Uint};
+use beacon::types::AttestationData;
+use beacon::utils::{serialize_uint, deserialize_uint};
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+/// Supported version of the interchange format.
+pub const INTERCHANGE_FORMAT_VERSION: u64 = 5;
+
+#[derive(Debug)]
+pub enum Error {
+	/// IO error.
+	Io(std::io::Error),
+	/// JSON error.
+	Json(serde_json::Error),
+	/// Unsupported interchange format version.
+	UnsupportedVersion(u64),
+	/// Records are for a different chain.
+	GenesisValidatorsRootMismatch { expected: H256, found: H256 },
+	/// A different block was already signed at the slot.
+	DoubleBlockProposal { slot: Uint },
+	/// A different attestation was already signed for the target epoch.
+	DoubleVote { target_epoch: Uint },
+	/// The attestation surrounds, or is surrounded by, a signed one.
+	SurroundVote { source_epoch: Uint, target_epoch: Uint },
+	/// The block is not after the lowest imported block slot.
+	BlockBelowLowerBound { slot: Uint, lower_bound: Uint },
+	/// The attestation source is before the lowest imported source, or its
+	/// target is not after the lowest imported target.
+	AttestationBelowLowerBound { source_epoch: Uint, target_epoch: Uint },
+	/// The attestation source is after its target.
+	InvalidAttestation,
+}
+
+impl std::fmt::Display for Error {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "{:?}", self)
+	}
+}
+
+impl std::error::Error for Error { }
+
+impl From<std::io::Error> for Error {
+	fn from(error: std::io::Error) -> Error {
+		Error::Io(error)
+	}
+}
+
+impl From<serde_json::Error> for Error {
+	fn from(error: serde_json::Error) -> Error {
+		Error::Json(error)
+	}
+}
+
+/// Metadata of an interchange.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct InterchangeMetadata {
+	#[serde(serialize_with = "serialize_uint", deserialize_with = "deserialize_uint")]
+	pub interchange_format_version: u64,
+	pub genesis_validators_root: H256,
+}
+
+/// A signed block in the interchange format.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct SignedBlock {
+	#[serde(serialize_with = "serialize_uint", deserialize_with = "deserialize_uint")]
+	pub slot: Uint,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub signing_root: Option<H256>,
+}
+
+/// A signed attestation in the interchange format.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct SignedAttestation {
+	#[serde(serialize_with = "serialize_uint", deserialize_with = "deserialize_uint")]
+	pub source_epoch: Uint,
+	#[serde(serialize_with = "serialize_uint", deserialize_with = "deserialize_uint")]
+	pub target_epoch: Uint,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub signing_root: Option<H256>,
+}
+
+/// Records of a single validator in the interchange format.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct InterchangeValidator {
+	pub pubkey: ValidatorId,
+	pub signed_blocks: Vec<SignedBlock>,
+	pub signed_attestations: Vec<SignedAttestation>,
+}
+
+/// EIP-3076 slashing protection interchange.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Interchange {
+	pub metadata: InterchangeMetadata,
+	pub data: Vec<InterchangeValidator>,
+}
+
+impl Interchange {
+	pub fn read(path: &Path) -> Result<Self, Error> {
+		let interchange: Self = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+		if interchange.metadata.interchange_format_version != INTERCHANGE_FORMAT_VERSION {
+			return Err(Error::UnsupportedVersion(interchange.metadata.interchange_format_version))
+		}
+		Ok(interchange)
+	}
+
+	pub fn write(&self, path: &Path) -> Result<(), Error> {
+		serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), self)?;
+		Ok(())
+	}
+}
+
+#[derive(Default, Clone)]
+struct History {
+	blocks: Vec<SignedBlock>,
+	attestations: Vec<SignedAttestation>,
+	/// Lowest imported block slot.
+	min_slot: Option<Uint>,
+	/// Lowest imported attestation source epoch.
+	min_source_epoch: Option<Uint>,
+	/// Lowest imported attestation target epoch.
+	min_target_epoch: Option<Uint>,
+}
+
+fn min_option(a: Option<Uint>, b: Uint) -> Option<Uint> {
+	Some(a.map_or(b, |a| a.min(b)))
+}
+
+/// Why the attestation `a` may not be signed alongside `b`, if it may not.
+/// These are the conditions of `AttestationData::is_slashable` in both
+/// directions, with data compared by signing root.
+fn slashing_violation(a: &SignedAttestation, b: &SignedAttestation) -> Option<Error> {
+	if a.target_epoch == b.target_epoch && a.signing_root != b.signing_root {
+		return Some(Error::DoubleVote { target_epoch: a.target_epoch })
+	}
+
+	if (a.source_epoch < b.source_epoch && b.target_epoch < a.target_epoch) ||
+		(b.source_epoch < a.source_epoch && a.target_epoch < b.target_epoch)
+	{
+		return Some(Error::SurroundVote {
+			source_epoch: a.source_epoch,
+			target_epoch: a.target_epoch,
+		})
+	}
+
+	None
+}
+
+/// Persistent slashing protection store.
+pub struct SlashingProtection {
+	path: PathBuf,
+	genesis_validators_root: H256,
+	histories: HashMap<ValidatorId, History>,
+}
+
+impl SlashingProtection {
+	/// Open the store at `path` for the chain of `genesis_validators_root`,
+	/// creating it if it does not exist.
+	pub fn open(path: &Path, genesis_validators_root: H256) -> Result<Self, Error> {
+		let mut protection = Self {
+			path: path.to_path_buf(),
+			genesis_validators_root,
+			histories: HashMap::new(),
+		};
+
+		if path.exists() {
+			protection.import(Interchange::read(path)?)?;
+		} else {
+			protection.persist()?;
+		}
+
+		Ok(protection)
+	}
+
+	/// Open an existing store at `path`, for whichever chain it was created.
+	pub fn open_existing(path: &Path) -> Result<Self, Error> {
+		let interchange = Interchange::read(path)?;
+		let mut protection = Self {
+			path: path.to_path_buf(),
+			genesis_validators_root: interchange.metadata.genesis_validators_root,
+			histories: HashMap::new(),
+		};
+		protection.import(interchange)?;
+
+		Ok(protection)
+	}
+
+	/// Merge records of an interchange into the store, and persist it.
+	pub fn import(&mut self, interchange: Interchange) -> Result<(), Error> {
+		if interchange.metadata.genesis_validators_root != self.genesis_validators_root {
+			return Err(Error::GenesisValidatorsRootMismatch {
+				expected: self.genesis_validators_root,
+				found: interchange.metadata.genesis_validators_root,
+			})
+		}
+
+		for validator in interchange.data {
+			let history = self.histories.entry(validator.pubkey).or_default();
+			for block in validator.signed_blocks {
+				history.min_slot = min_option(history.min_slot, block.slot);
+				if !history.blocks.contains(&block) {
+					history.blocks.push(block);
+				}
+			}
+			for attestation in validator.signed_attestations {
+				history.min_source_epoch =
+					min_option(history.min_source_epoch, attestation.source_epoch);
+				history.min_target_epoch =
+					min_option(history.min_target_epoch, attestation.target_epoch);
+				if !history.attestations.contains(&attestation) {
+					history.attestations.push(attestation);
+				}
+			}
+		}
+
+		self.persist()
+	}
+
+	/// All records of the store in the interchange format.
+	pub fn export(&self) -> Interchange {
+		let mut data = self.histories.iter()
+			.map(|(pubkey, history)| InterchangeValidator {
+				pubkey: pubkey.clone(),
+				signed_blocks: history.blocks.clone(),
+				signed_attestations: history.attestations.clone(),
+			})
+			.collect::<Vec<_>>();
+		data.sort_by(|a, b| a.pubkey.cmp(&b.pubkey));
+
+		Interchange {
+			metadata: InterchangeMetadata {
+				interchange_format_version: INTERCHANGE_FORMAT_VERSION,
+				genesis_validators_root: self.genesis_validators_root,
+			},
+			data,
+		}
+	}
+
+	/// Check that signing the block with `signing_root` at `slot` is not
+	/// slashable and is after the lowest imported block, and record it.
+	/// Signing the same block again is allowed.
+	pub fn sign_block(
+		&mut self,
+		pubkey: &ValidatorId,
+		slot: Uint,
+		signing_root: H256,
+	) -> Result<(), Error> {
+		let block = SignedBlock { slot, signing_root: Some(signing_root) };
+		let history = self.histories.entry(pubkey.clone()).or_default();
+
+		if history.blocks.contains(&block) {
+			return Ok(())
+		}
+		if let Some(lower_bound) = history.min_slot.filter(|min| slot <= *min) {
+			return Err(Error::BlockBelowLowerBound { slot, lower_bound })
+		}
+		if history.blocks.iter().any(|signed| signed.slot == slot) {
+			return Err(Error::DoubleBlockProposal { slot })
+		}
+
+		history.blocks.push(block);
+		self.persist()
+	}
+
+	/// Check that signing attestation `data` with `signing_root` is not
+	/// slashable and is not older than the imported attestations, and record
+	/// it. Signing the same attestation again is allowed.
+	pub fn sign_attestation(
+		&mut self,
+		pubkey: &ValidatorId,
+		data: &AttestationData,
+		signing_root: H256,
+	) -> Result<(), Error> {
+		if data.source.epoch > data.target.epoch {
+			return Err(Error::InvalidAttestation)
+		}
+
+		let attestation = SignedAttestation {
+			source_epoch: data.source.epoch,
+			target_epoch: data.target.epoch,
+			signing_root: Some(signing_root),
+		};
+		let history = self.histories.entry(pubkey.clone()).or_default();
+
+		if history.attestations.contains(&attestation) {
+			return Ok(())
+		}
+		if history.min_source_epoch.map_or(false, |min| attestation.source_epoch < min) ||
+			history.min_target_epoch.map_or(false, |min| attestation.target_epoch <= min)
+		{
+			return Err(Error::AttestationBelowLowerBound {
+				source_epoch: attestation.source_epoch,
+				target_epoch: attestation.target_epoch,
+			})
+		}
+		if let Some(err) = history.attestations.iter()
+			.filter_map(|signed| slashing_violation(&attestation, signed))
+			.next()
+		{
+			return Err(err)
+		}
+
+		history.attestations.push(attestation);
+		self.persist()
+	}
+
+	fn persist(&self) -> Result<(), Error> {
+		let mut tmp = self.path.clone().into_os_string();
+		tmp.push(".tmp");
+		let tmp = PathBuf::from(tmp);
+
+		{
+			let file = File::create(&tmp)?;
+			serde_json::to_writer_pretty(BufWriter::new(&file), &self.export())?;
+			file.sync_all()?;
+		}
+		fs::rename(&tmp, &self.path)?;
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use beacon::types::Checkpoint;
+
+	fn attestation_data(source_epoch: Uint, target_epoch: Uint) -> AttestationData {
+		AttestationData {
+			source: Checkpoint { epoch: source_epoch, root: H256::default() },
+			target: Checkpoint { epoch: target_epoch, root: H256::default() },
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn refuses_slashable_messages() {
+		let path = std::env::temp_dir().join(
+			format!("shasper-slashing-protection-{}.json", std::process::id())
+		);
+		let _ = fs::remove_file(&path);
+		let pubkey = ValidatorId::default();
+		let root = H256::repeat_byte(1);
+		let other_root = H256::repeat_byte(2);
+
+		let mut protection = SlashingProtection::open(&path, H256::default()).unwrap();
+		protection.sign_block(&pubkey, 10, root).unwrap();
+		protection.sign_block(&pubkey, 10, root).unwrap();
+		assert!(protection.sign_block(&pubkey, 10, other_root).is_err());
+		protection.sign_block(&pubkey, 11, other_root).unwrap();
+
+		protection.sign_attestation(&pubkey, &attestation_data(2, 3), root).unwrap();
+		protection.sign_attestation(&pubkey, &attestation_data(2, 3), root).unwrap();
+		assert!(protection.sign_attestation(&pubkey, &attestation_data(2, 3), other_root).is_err());
+		assert!(protection.sign_attestation(&pubkey, &attestation_data(1, 4), other_root).is_err());
+		protection.sign_attestation(&pubkey, &attestation_data(3, 4), other_root).unwrap();
+
+		let reopened = SlashingProtection::open(&path, H256::default()).unwrap();
+		assert_eq!(reopened.export(), protection.export());
+		assert!(SlashingProtection::open(&path, H256::repeat_byte(3)).is_err());
+
+		fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn refuses_messages_below_imported_records() {
+		let path = std::env::temp_dir().join(
+			format!("shasper-slashing-protection-pruned-{}.json", std::process::id())
+		);
+		let _ = fs::remove_file(&path);
+		let pubkey = ValidatorId::default();
+		let root = H256::repeat_byte(1);
+		let other_root = H256::repeat_byte(2);
+
+		let mut protection = SlashingProtection::open(&path, H256::default()).unwrap();
+		protection.import(Interchange {
+			metadata: InterchangeMetadata {
+				interchange_format_version: INTERCHANGE_FORMAT_VERSION,
+				genesis_validators_root: H256::default(),
+			},
+			data: vec![InterchangeValidator {
+				pubkey: pubkey.clone(),
+				signed_blocks: vec![SignedBlock { slot: 20, signing_root: Some(root) }],
+				signed_attestations: vec![SignedAttestation {
+					source_epoch: 5,
+					target_epoch: 6,
+					signing_root: Some(root),
+				}],
+			}],
+		}).unwrap();
+
+		protection.sign_block(&pubkey, 20, root).unwrap();
+		assert!(protection.sign_block(&pubkey, 10, other_root).is_err());
+		assert!(protection.sign_block(&pubkey, 20, other_root).is_err());
+		protection.sign_block(&pubkey, 21, other_root).unwrap();
+
+		protection.sign_attestation(&pubkey, &attestation_data(5, 6), root).unwrap();
+		assert!(protection.sign_attestation(&pubkey, &attestation_data(3, 4), other_root).is_err());
+		assert!(protection.sign_attestation(&pubkey, &attestation_data(4, 7), other_root).is_err());
+		assert!(protection.sign_attestation(&pubkey, &attestation_data(5, 6), other_root).is_err());
+		protection.sign_attestation(&pubkey, &attestation_data(6, 7), other_root).unwrap();
+
+		fs::remove_file(&path).unwrap();
+	}
+}
@@ -0,0 +1,80 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Parity Shasper.
+
+// Parity Shasper is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+
+// Parity Shasper is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more
+// details.
+
+// You should have received a copy of the GNU General Public License along with
+// Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
+//! Helpers shared by unit tests: a small interop genesis, and an in-memory
+//! backend blocks can be inserted into without executing them.
+
+use beacon::{MinimalConfig, BeaconState, BLSNoVerification};
+use beacon::primitives::H256;
+use beacon::types::BeaconBlock;
+use blockchain::Block as BlockT;
+use blockchain::backend::{SharedMemoryBackend, ImportLock, ImportOperation};
+use blockchain::import::ImportAction;
+use bm_le::tree_root;
+use crate::{Block, MemoryState, ShasperAuxiliary, genesis};
+use crate::backend::ShasperBackend;
+
+pub type TestConfig = MinimalConfig;
+pub type TestBackend = ShasperBackend<
+	SharedMemoryBackend<Block<TestConfig>, ShasperAuxiliary, MemoryState<TestConfig>>
+>;
+
+/// Interop genesis state with `validators` validators, each with the
+/// interop secret of its index.
+pub fn genesis_state(validators: u64) -> BeaconState<TestConfig> {
+	let deposit_datas = (0..validators).map(|i| {
+		genesis::signed_deposit_data::<TestConfig>(
+			&crypto::interop::secret(i), <TestConfig as beacon::Config>::max_effective_balance(),
+		)
+	}).collect::<Vec<_>>();
+	genesis::interop_genesis_state::<TestConfig, BLSNoVerification>(&deposit_datas, 0)
+		.expect("Interop genesis is valid")
+}
+
+/// In-memory backend with the given genesis state.
+pub fn backend(state: BeaconState<TestConfig>) -> TestBackend {
+	let genesis_block = Block(BeaconBlock {
+		state_root: tree_root::<<TestConfig as beacon::Config>::Digest, _>(&state),
+		..Default::default()
+	});
+	ShasperBackend::new(SharedMemoryBackend::new_with_genesis(genesis_block, state.into()))
+}
+
+/// Insert a block on top of `parent` with the given post state, without
+/// executing it, and optionally make it the head.
+pub fn insert_block(
+	backend: &TestBackend,
+	import_lock: &ImportLock,
+	parent: H256,
+	slot: u64,
+	state: BeaconState<TestConfig>,
+	set_head: bool,
+) -> H256 {
+	let block = Block(BeaconBlock {
+		slot,
+		parent_root: parent,
+		state_root: tree_root::<<TestConfig as beacon::Config>::Digest, _>(&state),
+		..Default::default()
+	});
+	let id = block.id();
+
+	let mut action = ImportAction::new(backend, import_lock.lock());
+	action.import_raw(ImportOperation { block, state: state.into() });
+	if set_head {
+		action.set_head(id);
+	}
+	action.commit().expect("Committing to memory backend cannot fail");
+	id
+}
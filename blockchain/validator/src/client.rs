@@ -0,0 +1,230 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Parity Shasper.
+
+// Parity Shasper is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+
+// Parity Shasper is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more
+// details.
+
+// You should have received a copy of the GNU General Public License along with
+// Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
+//! Blocking client of the node API.
+
+use beacon::Config;
+use beacon::primitives::{H256, Signature, ValidatorId, Version, Uint};
+use beacon::types::{
+	Attestation, AttestationData, BeaconBlock, Fork, SignedAggregateAndProof,
+	UnsealedBeaconBlock, Validator,
+};
+use beacon::utils::deserialize_uint;
+use shasper_blockchain::production::{ProposerDuty, AttesterDuty};
+use shasper_blockchain::slashing_protection;
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+
+/// Response body of the standard Beacon API.
+#[derive(Deserialize)]
+struct Data<T> {
+	data: T,
+}
+
+/// Signed message of the standard Beacon API.
+#[derive(Serialize)]
+struct SignedMessage<T> {
+	message: T,
+	signature: Signature,
+}
+
+/// Genesis of the chain.
+#[derive(Deserialize, Clone, Debug)]
+pub struct Genesis {
+	#[serde(deserialize_with = "deserialize_uint")]
+	pub genesis_time: Uint,
+	pub genesis_validators_root: H256,
+	pub genesis_fork_version: Version,
+}
+
+#[derive(Deserialize)]
+struct ValidatorData {
+	#[serde(deserialize_with = "deserialize_uint")]
+	index: Uint,
+	validator: Validator,
+}
+
+#[derive(Debug)]
+pub enum Error {
+	/// HTTP transport error.
+	Http(reqwest::Error),
+	/// The node rejected the request.
+	Rejected(u16, String),
+	/// The signer failed to sign.
+	Signer(crypto::signer::Error),
+	/// Signing was refused by slashing protection.
+	SlashingProtection(slashing_protection::Error),
+}
+
+impl std::fmt::Display for Error {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "{:?}", self)
+	}
+}
+
+impl std::error::Error for Error { }
+
+impl From<reqwest::Error> for Error {
+	fn from(error: reqwest::Error) -> Error {
+		Error::Http(error)
+	}
+}
+
+impl From<crypto::signer::Error> for Error {
+	fn from(error: crypto::signer::Error) -> Error {
+		Error::Signer(error)
+	}
+}
+
+impl From<slashing_protection::Error> for Error {
+	fn from(error: slashing_protection::Error) -> Error {
+		Error::SlashingProtection(error)
+	}
+}
+
+/// Client of the API served by a beacon node.
+pub struct BeaconNodeClient {
+	client: reqwest::Client,
+	endpoint: String,
+}
+
+impl BeaconNodeClient {
+	pub fn new(endpoint: &str) -> Self {
+		Self {
+			client: reqwest::Client::new(),
+			endpoint: endpoint.trim_end_matches('/').to_string(),
+		}
+	}
+
+	fn check(mut response: reqwest::Response) -> Result<reqwest::Response, Error> {
+		if !response.status().is_success() {
+			let status = response.status().as_u16();
+			return Err(Error::Rejected(status, response.text().unwrap_or_default()))
+		}
+		Ok(response)
+	}
+
+	fn get<T: DeserializeOwned>(&self, path: &str, query: &[(&str, String)]) -> Result<T, Error> {
+		let response = self.client.get(&format!("{}{}", self.endpoint, path))
+			.query(query)
+			.send()?;
+		Ok(Self::check(response)?.json::<Data<T>>()?.data)
+	}
+
+	fn post_request<B: Serialize>(&self, path: &str, body: &B) -> Result<reqwest::Response, Error> {
+		let response = self.client.post(&format!("{}{}", self.endpoint, path))
+			.json(body)
+			.send()?;
+		Self::check(response)
+	}
+
+	fn post<B: Serialize>(&self, path: &str, body: &B) -> Result<(), Error> {
+		self.post_request(path, body).map(|_| ())
+	}
+
+	/// Genesis of the chain.
+	pub fn genesis(&self) -> Result<Genesis, Error> {
+		self.get("/eth/v1/beacon/genesis", &[])
+	}
+
+	/// Fork of the head state.
+	pub fn fork(&self) -> Result<Fork, Error> {
+		self.get("/eth/v1/beacon/states/head/fork", &[])
+	}
+
+	/// Indices of the given validators that are in the registry.
+	pub fn validator_indices(&self, pubkeys: &[ValidatorId]) -> Result<Vec<(ValidatorId, Uint)>, Error> {
+		let ids = pubkeys.iter()
+			.map(|pubkey| format!("0x{}", hex::encode(&pubkey[..])))
+			.collect::<Vec<_>>()
+			.join(",");
+		let validators: Vec<ValidatorData> = self.get(
+			"/eth/v1/beacon/states/head/validators", &[("id", ids)],
+		)?;
+		Ok(validators.into_iter().map(|data| (data.validator.pubkey, data.index)).collect())
+	}
+
+	/// Proposers of all slots in `epoch`.
+	pub fn proposer_duties(&self, epoch: Uint) -> Result<Vec<ProposerDuty>, Error> {
+		self.get(&format!("/eth/v1/validator/duties/proposer/{}", epoch), &[])
+	}
+
+	/// Attestation duties in `epoch` of the validators at `indices`.
+	pub fn attester_duties(&self, epoch: Uint, indices: &[Uint]) -> Result<Vec<AttesterDuty>, Error> {
+		let indices = indices.iter().map(|index| index.to_string()).collect::<Vec<_>>();
+		let response = self.post_request(
+			&format!("/eth/v1/validator/duties/attester/{}", epoch), &indices,
+		)?;
+		Ok(response.json::<Data<_>>()?.data)
+	}
+
+	/// Ask the node to produce an unsigned block at `slot`.
+	pub fn produce_block<C: Config>(
+		&self,
+		slot: Uint,
+		randao_reveal: &Signature,
+		graffiti: Option<&H256>,
+	) -> Result<BeaconBlock<C>, Error> {
+		let mut query = vec![("randao_reveal", format!("0x{}", hex::encode(&randao_reveal[..])))];
+		if let Some(graffiti) = graffiti {
+			query.push(("graffiti", format!("0x{}", hex::encode(&graffiti[..]))));
+		}
+		let block: UnsealedBeaconBlock<C> = self.get(
+			&format!("/eth/v1/validator/blocks/{}", slot), &query,
+		)?;
+		Ok(block.fake_seal())
+	}
+
+	/// Submit a signed block.
+	pub fn publish_block<C: Config>(&self, block: &BeaconBlock<C>) -> Result<(), Error> {
+		self.post("/eth/v1/beacon/blocks", &SignedMessage {
+			message: UnsealedBeaconBlock::from(block),
+			signature: block.signature,
+		})
+	}
+
+	/// Attestation data for the committee at `index` in `slot`.
+	pub fn attestation_data(&self, slot: Uint, index: Uint) -> Result<AttestationData, Error> {
+		self.get("/eth/v1/validator/attestation_data", &[
+			("slot", slot.to_string()),
+			("committee_index", index.to_string()),
+		])
+	}
+
+	/// Submit signed attestations.
+	pub fn publish_attestations<C: Config>(&self, attestations: &[Attestation<C>]) -> Result<(), Error> {
+		self.post("/eth/v1/beacon/pool/attestations", &attestations)
+	}
+
+	/// Aggregate known by the node of the attestations with the given data.
+	pub fn aggregate_attestation<C: Config>(
+		&self,
+		data_root: &H256,
+		slot: Uint,
+	) -> Result<Attestation<C>, Error> {
+		self.get("/eth/v1/validator/aggregate_attestation", &[
+			("attestation_data_root", format!("0x{}", hex::encode(&data_root[..]))),
+			("slot", slot.to_string()),
+		])
+	}
+
+	/// Submit signed aggregates.
+	pub fn publish_aggregate_and_proofs<C: Config>(
+		&self,
+		aggregates: &[SignedAggregateAndProof<C>],
+	) -> Result<(), Error> {
+		self.post("/eth/v1/validator/aggregate_and_proofs", &aggregates)
+	}
+}
@@ -0,0 +1,88 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Parity Shasper.
+
+// Parity Shasper is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+
+// Parity Shasper is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more
+// details.
+
+// You should have received a copy of the GNU General Public License along with
+// Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
+//! Per-epoch validator duties.
+
+use beacon::primitives::{ValidatorId, Uint, Epoch};
+use shasper_blockchain::production::{ProposerDuty, AttesterDuty};
+use std::collections::{BTreeMap, HashMap};
+use log::info;
+use crate::client::{BeaconNodeClient, Error};
+
+/// Duties of the local validators, fetched from the node once per epoch.
+pub struct DutiesCache {
+	indices: HashMap<ValidatorId, Uint>,
+	duties: BTreeMap<Epoch, (Vec<ProposerDuty>, Vec<AttesterDuty>)>,
+}
+
+impl DutiesCache {
+	pub fn new() -> Self {
+		Self { indices: HashMap::new(), duties: BTreeMap::new() }
+	}
+
+	/// Make sure duties of `epoch` are known, and drop those of earlier
+	/// epochs. Indices of validators that are not yet in the registry are
+	/// looked up again for every new epoch.
+	pub fn update(
+		&mut self,
+		client: &BeaconNodeClient,
+		epoch: Epoch,
+		pubkeys: &[ValidatorId],
+	) -> Result<(), Error> {
+		self.duties = self.duties.split_off(&epoch);
+
+		if !self.duties.contains_key(&epoch) {
+			let unknown = pubkeys.iter()
+				.filter(|pubkey| !self.indices.contains_key(pubkey))
+				.cloned()
+				.collect::<Vec<_>>();
+			if !unknown.is_empty() {
+				self.indices.extend(client.validator_indices(&unknown)?);
+			}
+
+			let proposers = client.proposer_duties(epoch)?.into_iter()
+				.filter(|duty| self.indices.contains_key(&duty.pubkey))
+				.collect::<Vec<_>>();
+			let indices = self.indices.values().cloned().collect::<Vec<_>>();
+			let attesters = if indices.is_empty() {
+				Vec::new()
+			} else {
+				client.attester_duties(epoch, &indices)?
+			};
+			info!(
+				"Fetched duties of epoch {} for {} of {} validators",
+				epoch, indices.len(), pubkeys.len(),
+			);
+			self.duties.insert(epoch, (proposers, attesters));
+		}
+
+		Ok(())
+	}
+
+	/// Duties that attest at `slot`.
+	pub fn attesters<'a>(&'a self, epoch: Epoch, slot: Uint) -> impl Iterator<Item=&'a AttesterDuty> {
+		self.duties.get(&epoch).into_iter()
+			.flat_map(|(_, attesters)| attesters.iter())
+			.filter(move |duty| duty.slot == slot)
+	}
+
+	/// Duty that proposes at `slot`, if any.
+	pub fn proposer(&self, epoch: Epoch, slot: Uint) -> Option<&ProposerDuty> {
+		self.duties.get(&epoch)?
+			.0
+			.iter()
+			.find(|duty| duty.slot == slot)
+	}
+}
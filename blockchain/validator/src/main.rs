@@ -0,0 +1,288 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Parity Shasper.
+
+// Parity Shasper is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+
+// Parity Shasper is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more
+// details.
+
+// You should have received a copy of the GNU General Public License along with
+// Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
+//! Standalone validator client. It fetches duties from a beacon node over
+//! the standard Beacon API, signs randao reveals, blocks, attestations and
+//! aggregates with its own keys or a remote signer, and submits them back to
+//! the node.
+
+mod client;
+mod duties;
+
+use beacon::Config;
+use beacon::primitives::{H256, ValidatorId};
+use beacon::types::{AttestationData, AggregateAndProof, SignedAggregateAndProof};
+use beacon::utils::epoch_of_slot;
+use bm_le::tree_root;
+use shasper_blockchain::genesis::{self as interop, ValidatorKey};
+use shasper_blockchain::production::{self, ProposerDuty, AttesterDuty};
+use shasper_blockchain::clock::SlotClock;
+use shasper_blockchain::slashing_protection::SlashingProtection;
+use clap::{App, Arg, ArgMatches};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use log::{info, warn};
+use crypto::bls;
+use crypto::signer::{Signer, SigningMessage, ForkInfo, LocalSigner, RemoteSigner};
+use crate::client::BeaconNodeClient;
+use crate::duties::DutiesCache;
+
+/// Interval at which connecting to the beacon node is retried on startup.
+const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+fn main() {
+	pretty_env_logger::init();
+
+	let matches = App::new("Shasper validator client")
+		.arg(Arg::with_name("beacon-node")
+			 .long("beacon-node")
+			 .takes_value(true)
+			 .default_value("http://127.0.0.1:5052")
+			 .help("API endpoint of the beacon node"))
+		.arg(Arg::with_name("validator-keys")
+			 .long("validator-keys")
+			 .takes_value(true)
+			 .help("Yaml validator keys"))
+		.arg(Arg::with_name("validator-keystores")
+			 .long("validator-keystores")
+			 .takes_value(true)
+			 .help("Directory of EIP-2335 validator keystores"))
+		.arg(Arg::with_name("keystore-password-file")
+			 .long("keystore-password-file")
+			 .takes_value(true)
+			 .help("File containing the keystore password, prompted for if not given"))
+		.arg(Arg::with_name("remote-signer")
+			 .long("remote-signer")
+			 .takes_value(true)
+			 .conflicts_with_all(&["validator-keys", "validator-keystores"])
+			 .help("Remote signer taking Web3Signer requests with this client's signing roots, instead of local validator keys"))
+		.arg(Arg::with_name("slashing-protection")
+			 .long("slashing-protection")
+			 .takes_value(true)
+			 .default_value("slashing_protection.json")
+			 .help("Slashing protection file, checked before signing any block or attestation"))
+		.arg(Arg::with_name("graffiti")
+			 .long("graffiti")
+			 .takes_value(true)
+			 .help("Text of at most 32 bytes to include in proposed blocks"))
+		.arg(Arg::with_name("config")
+			 .long("config")
+			 .takes_value(true)
+			 .default_value("minimal")
+			 .help("Config to use"))
+		.get_matches();
+
+	match matches.value_of("config").unwrap() {
+		"minimal" => run::<beacon::MinimalConfig>(&matches),
+		"mainnet" => run::<beacon::MainnetConfig>(&matches),
+		"sapphire" => run::<beacon::SapphireConfig>(&matches),
+		e => panic!("Unknown config name: {:?}", e),
+	}
+}
+
+fn load_keys(matches: &ArgMatches) -> HashMap<ValidatorId, bls::Secret> {
+	let mut keys = HashMap::new();
+
+	if let Some(validator_keys) = matches.value_of("validator-keys") {
+		let file = File::open(validator_keys).unwrap();
+		let coll = serde_yaml::from_reader::<_, Vec<ValidatorKey>>(BufReader::new(file)).unwrap();
+
+		for key in coll {
+			let sk = key.secret().unwrap();
+			keys.insert(interop::validator_id(&sk), sk);
+		}
+	}
+
+	if let Some(keystores) = matches.value_of("validator-keystores") {
+		let secrets = shasper_blockchain::keys::load_keystores(
+			Path::new(keystores),
+			matches.value_of("keystore-password-file").map(Path::new),
+		).unwrap();
+
+		for sk in secrets {
+			keys.insert(interop::validator_id(&sk), sk);
+		}
+	}
+
+	keys
+}
+
+fn run<C: Config>(matches: &ArgMatches) {
+	let signer: Box<dyn Signer<C>> = match matches.value_of("remote-signer") {
+		Some(endpoint) => {
+			info!("Signing with remote signer {}", endpoint);
+			Box::new(RemoteSigner::new(endpoint))
+		},
+		None => Box::new(LocalSigner::new(load_keys(matches))),
+	};
+	let pubkeys = signer.pubkeys().expect("Fetching validator keys from signer failed");
+	if pubkeys.is_empty() {
+		warn!("No validator keys given, nothing to do.");
+		return
+	}
+	info!("Loaded {} validator keys", pubkeys.len());
+
+	let graffiti = matches.value_of("graffiti").map(|text| {
+		let bytes = text.as_bytes();
+		assert!(bytes.len() <= 32, "Graffiti must be at most 32 bytes");
+		let mut graffiti = H256::default();
+		graffiti[..bytes.len()].copy_from_slice(bytes);
+		graffiti
+	});
+
+	let client = BeaconNodeClient::new(matches.value_of("beacon-node").unwrap());
+	let genesis = loop {
+		match client.genesis() {
+			Ok(genesis) => break genesis,
+			Err(err) => {
+				warn!("Unable to fetch genesis from beacon node: {}", err);
+				thread::sleep(RETRY_INTERVAL);
+			},
+		}
+	};
+	info!("Connected to beacon node with genesis time {}", genesis.genesis_time);
+
+	let path = PathBuf::from(matches.value_of("slashing-protection").unwrap());
+	info!("Using slashing protection file {}", path.display());
+	let mut slashing_protection = SlashingProtection::open(&path, genesis.genesis_validators_root)
+		.expect("Opening slashing protection failed");
+
+	let clock = SlotClock::<C>::new(genesis.genesis_time);
+	clock.wait_for_genesis();
+	let mut duties = DutiesCache::new();
+
+	loop {
+		let slot = clock.wait_for_next_slot();
+		let epoch = epoch_of_slot::<C>(slot);
+
+		if let Err(err) = duties.update(&client, epoch, &pubkeys) {
+			warn!("Unable to fetch duties of epoch {}: {}", epoch, err);
+			continue
+		}
+		let fork_info = match client.fork() {
+			Ok(fork) => ForkInfo { fork, genesis_validators_root: genesis.genesis_validators_root },
+			Err(err) => {
+				warn!("Unable to fetch fork from beacon node: {}", err);
+				continue
+			},
+		};
+
+		if let Some(duty) = duties.proposer(epoch, slot) {
+			if let Err(err) = propose::<C>(
+				&client, &*signer, &mut slashing_protection, &fork_info, duty, slot, graffiti.as_ref(),
+			) {
+				warn!("Unable to propose for validator {}: {}", duty.pubkey, err);
+			}
+		}
+
+		clock.wait_until(slot, SlotClock::<C>::attestation_offset());
+		let mut attested = Vec::new();
+		for duty in duties.attesters(epoch, slot) {
+			match attest::<C>(&client, &*signer, &mut slashing_protection, &fork_info, duty, slot) {
+				Ok(data) => attested.push((duty, data)),
+				Err(err) => warn!("Unable to attest for validator {}: {}", duty.pubkey, err),
+			}
+		}
+
+		if attested.is_empty() {
+			continue
+		}
+		clock.wait_until(slot, SlotClock::<C>::aggregation_offset());
+		for (duty, data) in attested {
+			if let Err(err) = aggregate::<C>(&client, &*signer, &fork_info, duty, &data, slot) {
+				warn!("Unable to aggregate for validator {}: {}", duty.pubkey, err);
+			}
+		}
+	}
+}
+
+fn aggregate<C: Config>(
+	client: &BeaconNodeClient,
+	signer: &dyn Signer<C>,
+	fork_info: &ForkInfo,
+	duty: &AttesterDuty,
+	data: &AttestationData,
+	slot: u64,
+) -> Result<(), client::Error> {
+	let selection_proof = signer.sign(
+		&duty.pubkey, fork_info, &SigningMessage::AggregationSlot(slot),
+	)?;
+	if !beacon::utils::is_aggregator::<C>(duty.committee_length, &selection_proof) {
+		return Ok(())
+	}
+
+	let aggregate = client.aggregate_attestation::<C>(
+		&tree_root::<<C as Config>::Digest, _>(data), slot,
+	)?;
+	let message = AggregateAndProof { index: duty.validator_index, selection_proof, aggregate };
+	let signature = signer.sign(
+		&duty.pubkey, fork_info, &SigningMessage::AggregateAndProof(message.clone()),
+	)?;
+	client.publish_aggregate_and_proofs::<C>(&[SignedAggregateAndProof { message, signature }])?;
+	info!("Published aggregate of validator {} at slot {}", duty.pubkey, slot);
+
+	Ok(())
+}
+
+fn attest<C: Config>(
+	client: &BeaconNodeClient,
+	signer: &dyn Signer<C>,
+	slashing_protection: &mut SlashingProtection,
+	fork_info: &ForkInfo,
+	duty: &AttesterDuty,
+	slot: u64,
+) -> Result<AttestationData, client::Error> {
+	let data = client.attestation_data(slot, duty.committee_index)?;
+	let message = SigningMessage::Attestation(data.clone());
+	slashing_protection.sign_attestation(&duty.pubkey, &data, message.signing_root())?;
+	let signature = signer.sign(&duty.pubkey, fork_info, &message)?;
+	let attestation = production::unaggregated_attestation::<C>(
+		data.clone(),
+		signature,
+		duty.validator_committee_index as usize,
+		duty.committee_length as usize,
+	);
+	client.publish_attestations(&[attestation])?;
+	info!("Published attestation of validator {} at slot {}", duty.pubkey, slot);
+
+	Ok(data)
+}
+
+fn propose<C: Config>(
+	client: &BeaconNodeClient,
+	signer: &dyn Signer<C>,
+	slashing_protection: &mut SlashingProtection,
+	fork_info: &ForkInfo,
+	duty: &ProposerDuty,
+	slot: u64,
+	graffiti: Option<&H256>,
+) -> Result<(), client::Error> {
+	let randao_reveal = signer.sign(
+		&duty.pubkey, fork_info, &SigningMessage::RandaoReveal(epoch_of_slot::<C>(slot)),
+	)?;
+
+	let mut block = client.produce_block::<C>(slot, &randao_reveal, graffiti)?;
+	let message = SigningMessage::Block(block.clone());
+	slashing_protection.sign_block(&duty.pubkey, slot, message.signing_root())?;
+	block.signature = signer.sign(&duty.pubkey, fork_info, &message)?;
+	client.publish_block(&block)?;
+	info!("Published block of validator {} at slot {}", duty.pubkey, slot);
+
+	Ok(())
+}
@@ -0,0 +1,251 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Parity Shasper.
+
+// Parity Shasper is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+
+// Parity Shasper is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more
+// details.
+
+// You should have received a copy of the GNU General Public License along with
+// Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
+//! EIP-2333 hierarchical BLS key derivation, with EIP-2334 validator key
+//! paths and BIP-39 mnemonic seeds.
+
+use sha2::{Sha256, Sha512, Digest};
+use hmac::{Hmac, Mac};
+use unicode_normalization::UnicodeNormalization;
+use crate::{bls, scalar};
+
+/// Purpose field of EIP-2334 paths.
+pub const PURPOSE: u32 = 12381;
+/// Coin type of eth2 in EIP-2334 paths.
+pub const COIN_TYPE: u32 = 3600;
+
+/// Minimum seed length accepted by `derive_master_sk`.
+pub const MIN_SEED_LENGTH: usize = 32;
+
+const LAMPORT_CHUNKS: usize = 255;
+const HKDF_MOD_R_LENGTH: usize = 48;
+const HKDF_MOD_R_SALT: &[u8] = b"BLS-SIG-KEYGEN-SALT-";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+	/// Seed is shorter than `MIN_SEED_LENGTH`.
+	SeedTooShort,
+	/// Path is not of the form `m/a/b/...`.
+	InvalidPath(String),
+}
+
+impl std::fmt::Display for Error {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "{:?}", self)
+	}
+}
+
+impl std::error::Error for Error { }
+
+fn hmac_sha256(key: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+	let mut mac = Hmac::<Sha256>::new_varkey(key)
+		.expect("HMAC accepts keys of any length; qed");
+	for part in parts {
+		mac.input(part);
+	}
+	let mut out = [0u8; 32];
+	out.copy_from_slice(&mac.result().code());
+	out
+}
+
+/// HKDF-SHA256 with an explicit extract and expand step, as used by
+/// EIP-2333.
+fn hkdf(salt: &[u8], ikm: &[&[u8]], info: &[u8], length: usize) -> Vec<u8> {
+	let prk = hmac_sha256(salt, ikm);
+
+	// HKDF allows at most 255 blocks, so the counter fits in a byte.
+	let blocks = (length + 31) / 32;
+	assert!(blocks <= 255, "HKDF output length too large");
+
+	let mut okm = Vec::with_capacity(blocks * 32);
+	let mut block = Vec::new();
+	for counter in 1..=(blocks as u8) {
+		block = hmac_sha256(&prk, &[&block[..], info, &[counter][..]]).to_vec();
+		okm.extend_from_slice(&block);
+	}
+	okm.truncate(length);
+	okm
+}
+
+/// Hash arbitrary key material into a non-zero scalar.
+fn hkdf_mod_r(ikm: &[u8]) -> [u8; 32] {
+	let mut salt = HKDF_MOD_R_SALT.to_vec();
+	loop {
+		salt = Sha256::digest(&salt).to_vec();
+		let okm = hkdf(
+			&salt,
+			&[ikm, &[0u8][..]],
+			&(HKDF_MOD_R_LENGTH as u16).to_be_bytes(),
+			HKDF_MOD_R_LENGTH,
+		);
+		let sk = scalar::reduce(&okm);
+		if sk != [0u8; 32] {
+			return sk
+		}
+	}
+}
+
+/// Compressed Lamport public key of the parent key at the given index.
+fn parent_sk_to_lamport_pk(parent_sk: &[u8; 32], index: u32) -> [u8; 32] {
+	let salt = index.to_be_bytes();
+	let mut flipped = *parent_sk;
+	for byte in flipped.iter_mut() {
+		*byte = !*byte;
+	}
+
+	let mut lamport_pk = Sha256::new();
+	for ikm in &[&parent_sk[..], &flipped[..]] {
+		let lamport_sk = hkdf(&salt, &[*ikm], &[], 32 * LAMPORT_CHUNKS);
+		for chunk in lamport_sk.chunks(32) {
+			lamport_pk.input(&Sha256::digest(chunk)[..]);
+		}
+	}
+
+	let mut out = [0u8; 32];
+	out.copy_from_slice(&lamport_pk.result());
+	out
+}
+
+/// Derive the master secret key from a seed. Returns big-endian scalar
+/// bytes.
+pub fn derive_master_sk(seed: &[u8]) -> Result<[u8; 32], Error> {
+	if seed.len() < MIN_SEED_LENGTH {
+		return Err(Error::SeedTooShort)
+	}
+	Ok(hkdf_mod_r(seed))
+}
+
+/// Derive the child secret key at `index` from a parent secret key, both as
+/// big-endian scalar bytes.
+pub fn derive_child_sk(parent_sk: &[u8; 32], index: u32) -> [u8; 32] {
+	hkdf_mod_r(&parent_sk_to_lamport_pk(parent_sk, index))
+}
+
+/// Parse a path of the form `m/12381/3600/0/0` into its indices.
+pub fn parse_path(path: &str) -> Result<Vec<u32>, Error> {
+	let mut components = path.split('/');
+	if components.next() != Some("m") {
+		return Err(Error::InvalidPath(path.to_string()))
+	}
+	components
+		.map(|component| component.parse::<u32>()
+			 .map_err(|_| Error::InvalidPath(path.to_string())))
+		.collect()
+}
+
+/// Derive the secret key at `path` from a seed.
+pub fn derive_path(seed: &[u8], path: &str) -> Result<[u8; 32], Error> {
+	let indices = parse_path(path)?;
+	let mut sk = derive_master_sk(seed)?;
+	for index in indices {
+		sk = derive_child_sk(&sk, index);
+	}
+	Ok(sk)
+}
+
+/// EIP-2334 path of the withdrawal key of the validator at `index`.
+pub fn withdrawal_key_path(index: u32) -> String {
+	format!("m/{}/{}/{}/0", PURPOSE, COIN_TYPE, index)
+}
+
+/// EIP-2334 path of the signing key of the validator at `index`.
+pub fn signing_key_path(index: u32) -> String {
+	format!("m/{}/{}/{}/0/0", PURPOSE, COIN_TYPE, index)
+}
+
+/// Convert derived secret key bytes into a BLS secret key.
+pub fn to_secret(sk: &[u8; 32]) -> bls::Secret {
+	scalar::to_secret(sk)
+}
+
+/// BIP-39 seed of a mnemonic and optional passphrase.
+///
+/// The mnemonic is not checked against the BIP-39 word list, so a typo
+/// silently yields a different seed.
+pub fn mnemonic_to_seed(mnemonic: &str, passphrase: &str) -> [u8; 64] {
+	let mnemonic = mnemonic.nfkd().collect::<String>();
+	let salt = format!("mnemonic{}", passphrase).nfkd().collect::<String>();
+
+	let mut seed = [0u8; 64];
+	pbkdf2::pbkdf2::<Hmac<Sha512>>(mnemonic.as_bytes(), salt.as_bytes(), 2048, &mut seed);
+	seed
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn hex32(s: &str) -> [u8; 32] {
+		let mut out = [0u8; 32];
+		out.copy_from_slice(&hex::decode(s).unwrap());
+		out
+	}
+
+	#[test]
+	fn eip2333_vectors() {
+		let vectors = [
+			(
+				"c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04",
+				"0d7359d57963ab8fbbde1852dcf553fedbc31f464d80ee7d40ae683122b45070",
+				0,
+				"2d18bd6c14e6d15bf8b5085c9b74f3daae3b03cc2014770a599d8c1539e50f8e",
+			),
+			(
+				"3141592653589793238462643383279502884197169399375105820974944592",
+				"41c9e07822b092a93fd6797396338c3ada4170cc81829fdfce6b5d34bd5e7ec7",
+				3141592653,
+				"384843fad5f3d777ea39de3e47a8f999ae91f89e42bffa993d91d9782d152a0f",
+			),
+			(
+				"0099ff991111002299dd7744ee3355bbdd8844115566cc55663355668888cc00",
+				"3cfa341ab3910a7d00d933d8f7c4fe87c91798a0397421d6b19fd5b815132e80",
+				4294967295,
+				"40e86285582f35b28821340f6a53b448588efa575bc4d88c32ef8567b8d9479b",
+			),
+			(
+				"d4e56740f876aef8c010b86a40d5f56745a118d0906a34e69aec8c0db1cb8fa3",
+				"2a0e28ffa5fbbe2f8e7aad4ed94f745d6bf755c51182e119bb1694fe61d3afca",
+				42,
+				"455c0dc9fccb3395825d92a60d2672d69416be1c2578a87a7a3d3ced11ebb88d",
+			),
+		];
+
+		for (seed, master_sk, index, child_sk) in vectors.iter() {
+			let master = derive_master_sk(&hex::decode(seed).unwrap()).unwrap();
+			assert_eq!(master, hex32(master_sk));
+			assert_eq!(derive_child_sk(&master, *index), hex32(child_sk));
+		}
+	}
+
+	#[test]
+	fn bip39_seed() {
+		let seed = mnemonic_to_seed(
+			"abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+			"TREZOR",
+		);
+		assert_eq!(
+			hex::encode(&seed[..]),
+			"c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04",
+		);
+	}
+
+	#[test]
+	fn eip2334_paths() {
+		assert_eq!(parse_path(&signing_key_path(3)), Ok(vec![12381, 3600, 3, 0, 0]));
+		assert_eq!(parse_path(&withdrawal_key_path(3)), Ok(vec![12381, 3600, 3, 0]));
+		assert!(parse_path("12381/3600").is_err());
+		assert!(parse_path("m/12381/x").is_err());
+	}
+}
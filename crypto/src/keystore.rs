@@ -0,0 +1,388 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Parity Shasper.
+
+// Parity Shasper is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+
+// Parity Shasper is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more
+// details.
+
+// You should have received a copy of the GNU General Public License along with
+// Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
+//! EIP-2335 encrypted BLS keystores.
+
+use serde::{Serialize, Deserialize};
+use serde_json::{Value, json};
+use sha2::{Sha256, Digest};
+use hmac::Hmac;
+use aes_ctr::Aes128Ctr;
+use aes_ctr::stream_cipher::{NewStreamCipher, SyncStreamCipher};
+use aes_ctr::stream_cipher::generic_array::GenericArray;
+use unicode_normalization::UnicodeNormalization;
+use rand::RngCore;
+use crate::bls;
+
+/// Keystore format version.
+pub const KEYSTORE_VERSION: u32 = 4;
+
+const DKLEN: usize = 32;
+
+#[derive(Debug)]
+pub enum Error {
+	/// JSON error.
+	Json(serde_json::Error),
+	/// Invalid hex value.
+	InvalidHex,
+	/// Unsupported keystore version.
+	UnsupportedVersion(u32),
+	/// Unsupported KDF function.
+	UnsupportedKdf(String),
+	/// Unsupported checksum function.
+	UnsupportedChecksum(String),
+	/// Unsupported cipher function.
+	UnsupportedCipher(String),
+	/// Invalid module parameters.
+	InvalidParams,
+	/// Checksum mismatch, usually caused by a wrong password.
+	InvalidChecksum,
+	/// Decrypted secret is not a valid BLS secret key.
+	InvalidSecret,
+	/// Decrypted secret does not match the keystore public key.
+	PubkeyMismatch,
+}
+
+impl std::fmt::Display for Error {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "{:?}", self)
+	}
+}
+
+impl std::error::Error for Error { }
+
+impl From<serde_json::Error> for Error {
+	fn from(error: serde_json::Error) -> Error {
+		Error::Json(error)
+	}
+}
+
+/// Key derivation function used when encrypting a keystore.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Kdf {
+	/// Scrypt with the given cost parameters.
+	Scrypt { n: u32, r: u32, p: u32 },
+	/// PBKDF2 with HMAC-SHA256 and the given iteration count.
+	Pbkdf2 { c: u32 },
+}
+
+impl Default for Kdf {
+	fn default() -> Self {
+		Kdf::Scrypt { n: 262144, r: 8, p: 1 }
+	}
+}
+
+/// A keystore module, consisting of a function, its parameters and a
+/// message.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Module {
+	pub function: String,
+	pub params: Value,
+	pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct KeystoreCrypto {
+	pub kdf: Module,
+	pub checksum: Module,
+	pub cipher: Module,
+}
+
+/// EIP-2335 keystore.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Keystore {
+	pub crypto: KeystoreCrypto,
+	#[serde(default)]
+	pub description: String,
+	pub pubkey: String,
+	pub path: String,
+	pub uuid: String,
+	pub version: u32,
+}
+
+#[derive(Deserialize)]
+struct ScryptParams {
+	dklen: usize,
+	n: u32,
+	r: u32,
+	p: u32,
+	salt: String,
+}
+
+#[derive(Deserialize)]
+struct Pbkdf2Params {
+	dklen: usize,
+	c: u32,
+	prf: String,
+	salt: String,
+}
+
+#[derive(Deserialize)]
+struct CipherParams {
+	iv: String,
+}
+
+fn decode_hex(value: &str) -> Result<Vec<u8>, Error> {
+	hex::decode(value.trim_start_matches("0x")).map_err(|_| Error::InvalidHex)
+}
+
+/// Normalize the password to NFKD and strip control codes.
+fn process_password(password: &str) -> Vec<u8> {
+	password.nfkd()
+		.filter(|c| !(*c <= '\u{1f}' || ('\u{7f}'..='\u{9f}').contains(c)))
+		.collect::<String>()
+		.into_bytes()
+}
+
+fn derive_key(kdf: &Module, password: &[u8]) -> Result<Vec<u8>, Error> {
+	match kdf.function.as_str() {
+		"scrypt" => {
+			let params: ScryptParams = serde_json::from_value(kdf.params.clone())?;
+			if params.dklen < DKLEN || !params.n.is_power_of_two() {
+				return Err(Error::InvalidParams)
+			}
+			let scrypt_params = scrypt::ScryptParams::new(
+				params.n.trailing_zeros() as u8, params.r, params.p
+			).map_err(|_| Error::InvalidParams)?;
+
+			let mut key = vec![0u8; params.dklen];
+			scrypt::scrypt(password, &decode_hex(&params.salt)?, &scrypt_params, &mut key)
+				.map_err(|_| Error::InvalidParams)?;
+			Ok(key)
+		},
+		"pbkdf2" => {
+			let params: Pbkdf2Params = serde_json::from_value(kdf.params.clone())?;
+			if params.dklen < DKLEN || params.prf != "hmac-sha256" {
+				return Err(Error::InvalidParams)
+			}
+
+			let mut key = vec![0u8; params.dklen];
+			pbkdf2::pbkdf2::<Hmac<Sha256>>(
+				password, &decode_hex(&params.salt)?, params.c as usize, &mut key
+			);
+			Ok(key)
+		},
+		function => Err(Error::UnsupportedKdf(function.to_string())),
+	}
+}
+
+fn checksum(key: &[u8], cipher_message: &[u8]) -> Vec<u8> {
+	let mut hasher = Sha256::new();
+	hasher.input(&key[16..32]);
+	hasher.input(cipher_message);
+	hasher.result().to_vec()
+}
+
+fn aes_128_ctr(key: &[u8], iv: &[u8], data: &mut [u8]) -> Result<(), Error> {
+	if iv.len() != 16 {
+		return Err(Error::InvalidParams)
+	}
+	let mut cipher = Aes128Ctr::new(
+		GenericArray::from_slice(&key[..16]),
+		GenericArray::from_slice(iv),
+	);
+	cipher.apply_keystream(data);
+	Ok(())
+}
+
+fn pubkey_hex(secret: &bls::Secret) -> String {
+	hex::encode(&bls::Public::from_secret_key(secret).as_bytes()[..])
+}
+
+impl Keystore {
+	/// Parse a keystore from JSON.
+	pub fn from_json(json: &str) -> Result<Self, Error> {
+		Ok(serde_json::from_str(json)?)
+	}
+
+	/// Serialize the keystore to JSON.
+	pub fn to_json(&self) -> String {
+		serde_json::to_string_pretty(self).expect("Keystore serialization cannot fail; qed")
+	}
+
+	/// Encrypt a secret key with the given password into a new keystore.
+	pub fn encrypt(
+		secret: &bls::Secret,
+		password: &str,
+		path: &str,
+		kdf: Kdf,
+	) -> Result<Self, Error> {
+		let mut rng = rand::thread_rng();
+		let mut salt = [0u8; 32];
+		rng.fill_bytes(&mut salt);
+		let mut iv = [0u8; 16];
+		rng.fill_bytes(&mut iv);
+
+		let kdf = match kdf {
+			Kdf::Scrypt { n, r, p } => Module {
+				function: "scrypt".to_string(),
+				params: json!({
+					"dklen": DKLEN, "n": n, "r": r, "p": p, "salt": hex::encode(&salt),
+				}),
+				message: String::new(),
+			},
+			Kdf::Pbkdf2 { c } => Module {
+				function: "pbkdf2".to_string(),
+				params: json!({
+					"dklen": DKLEN, "c": c, "prf": "hmac-sha256", "salt": hex::encode(&salt),
+				}),
+				message: String::new(),
+			},
+		};
+		let key = derive_key(&kdf, &process_password(password))?;
+
+		let secret_bytes = secret.as_bytes();
+		let mut cipher_message = secret_bytes[(secret_bytes.len() - 32)..].to_vec();
+		aes_128_ctr(&key, &iv, &mut cipher_message)?;
+
+		Ok(Self {
+			crypto: KeystoreCrypto {
+				checksum: Module {
+					function: "sha256".to_string(),
+					params: json!({}),
+					message: hex::encode(checksum(&key, &cipher_message)),
+				},
+				cipher: Module {
+					function: "aes-128-ctr".to_string(),
+					params: json!({ "iv": hex::encode(&iv) }),
+					message: hex::encode(&cipher_message),
+				},
+				kdf,
+			},
+			description: String::new(),
+			pubkey: pubkey_hex(secret),
+			path: path.to_string(),
+			uuid: uuid::Uuid::new_v4().to_string(),
+			version: KEYSTORE_VERSION,
+		})
+	}
+
+	/// Decrypt the secret key of the keystore with the given password.
+	pub fn decrypt(&self, password: &str) -> Result<bls::Secret, Error> {
+		if self.version != KEYSTORE_VERSION {
+			return Err(Error::UnsupportedVersion(self.version))
+		}
+
+		let key = derive_key(&self.crypto.kdf, &process_password(password))?;
+		let mut cipher_message = decode_hex(&self.crypto.cipher.message)?;
+
+		match self.crypto.checksum.function.as_str() {
+			"sha256" => {
+				if checksum(&key, &cipher_message) != decode_hex(&self.crypto.checksum.message)? {
+					return Err(Error::InvalidChecksum)
+				}
+			},
+			function => return Err(Error::UnsupportedChecksum(function.to_string())),
+		}
+
+		match self.crypto.cipher.function.as_str() {
+			"aes-128-ctr" => {
+				let params: CipherParams = serde_json::from_value(
+					self.crypto.cipher.params.clone()
+				)?;
+				aes_128_ctr(&key, &decode_hex(&params.iv)?, &mut cipher_message)?;
+			},
+			function => return Err(Error::UnsupportedCipher(function.to_string())),
+		}
+
+		if cipher_message.len() > 48 {
+			return Err(Error::InvalidSecret)
+		}
+		let mut secret_bytes = vec![0u8; 48 - cipher_message.len()];
+		secret_bytes.extend_from_slice(&cipher_message);
+		let secret = bls::Secret::from_bytes(&secret_bytes).map_err(|_| Error::InvalidSecret)?;
+
+		if !self.pubkey.is_empty() &&
+			self.pubkey.trim_start_matches("0x").to_lowercase() != pubkey_hex(&secret)
+		{
+			return Err(Error::PubkeyMismatch)
+		}
+
+		Ok(secret)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const PASSWORD: &str = "\u{1d531}\u{1d522}\u{1d530}\u{1d531}\u{1d52d}\u{1d51e}\u{1d530}\u{1d530}\u{1d534}\u{1d52c}\u{1d52f}\u{1d521}\u{1f511}";
+	const SECRET: &str = "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f";
+
+	#[test]
+	fn decrypts_eip2335_pbkdf2_vector() {
+		let keystore = Keystore::from_json(r#"{
+			"crypto": {
+				"kdf": {
+					"function": "pbkdf2",
+					"params": {
+						"dklen": 32,
+						"c": 262144,
+						"prf": "hmac-sha256",
+						"salt": "d4e56740f876aef8c010b86a40d5f56745a118d0906a34e69aec8c0db1cb8fa3"
+					},
+					"message": ""
+				},
+				"checksum": {
+					"function": "sha256",
+					"params": {},
+					"message": "8a9f5d9912ed7e75ea794bc5a89bca5f193721d30868ade6f73043c6ea6febf1"
+				},
+				"cipher": {
+					"function": "aes-128-ctr",
+					"params": {
+						"iv": "264daa3f303d7259501c93d997d84fe6"
+					},
+					"message": "cee03fde2af33149775b7223e7845e4fb2c8ae1792e5f99fe9ecf474cc8c16ad"
+				}
+			},
+			"description": "This is a test keystore that uses PBKDF2 to secure the secret.",
+			"pubkey": "9612d7a727c9d0a22e185a1c768478dfe919cada9266988cb32359c11f2b7b27f4ae4040902382ae2910c15e2b420d07",
+			"path": "m/12381/60/0/0",
+			"uuid": "64625def-3331-4eea-ab6f-782f3ed16a83",
+			"version": 4
+		}"#).unwrap();
+
+		let secret = keystore.decrypt(PASSWORD).unwrap();
+		assert_eq!(hex::encode(&secret.as_bytes()[16..]), SECRET);
+		assert!(match keystore.decrypt("wrong password") {
+			Err(Error::InvalidChecksum) => true,
+			_ => false,
+		});
+	}
+
+	#[test]
+	fn encrypt_decrypt_roundtrip() {
+		let secret = crate::interop::secret(0);
+		for kdf in &[Kdf::Scrypt { n: 16, r: 8, p: 1 }, Kdf::Pbkdf2 { c: 2 }] {
+			let keystore = Keystore::encrypt(&secret, PASSWORD, "", *kdf).unwrap();
+			let keystore = Keystore::from_json(&keystore.to_json()).unwrap();
+			assert_eq!(keystore.decrypt(PASSWORD).unwrap().as_bytes(), secret.as_bytes());
+		}
+	}
+
+	#[test]
+	fn accepts_fields_added_by_other_tools() {
+		let secret = crate::interop::secret(0);
+		let keystore = Keystore::encrypt(&secret, PASSWORD, "", Kdf::Pbkdf2 { c: 2 }).unwrap();
+		let mut json: Value = serde_json::from_str(&keystore.to_json()).unwrap();
+		json["crypto"]["kdf"]["name"] = "pbkdf2".into();
+		json["crypto"]["cipher"]["name"] = "aes".into();
+		json["name"] = "validator".into();
+
+		let keystore = Keystore::from_json(&json.to_string()).unwrap();
+		assert_eq!(keystore.decrypt(PASSWORD).unwrap().as_bytes(), secret.as_bytes());
+	}
+}
@@ -108,3 +108,115 @@ pub mod bls {
 		}
 	}
 }
+
+#[cfg(feature = "std")]
+pub mod keystore;
+#[cfg(feature = "std")]
+pub mod derivation;
+#[cfg(feature = "std")]
+pub mod signer;
+
+/// Arithmetic on BLS12-381 scalars, stored as 32-byte big-endian integers.
+pub(crate) mod scalar {
+	use crate::bls;
+
+	/// BLS12-381 curve order, big-endian.
+	const CURVE_ORDER: [u8; 32] = [
+		0x73, 0xed, 0xa7, 0x53, 0x29, 0x9d, 0x7d, 0x48,
+		0x33, 0x39, 0xd8, 0x08, 0x09, 0xa1, 0xd8, 0x05,
+		0x53, 0xbd, 0xa4, 0x02, 0xff, 0xfe, 0x5b, 0xfe,
+		0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x01,
+	];
+
+	/// Subtract `b` from `a` in place, both big-endian, assuming `a >= b`.
+	fn sub_assign(a: &mut [u8; 32], b: &[u8; 32]) {
+		let mut borrow = 0i16;
+		for i in (0..32).rev() {
+			let mut value = a[i] as i16 - b[i] as i16 - borrow;
+			borrow = if value < 0 { value += 256; 1 } else { 0 };
+			a[i] = value as u8;
+		}
+	}
+
+	/// Reduce a big-endian integer of any length modulo the curve order.
+	pub fn reduce(value: &[u8]) -> [u8; 32] {
+		let mut rem = [0u8; 32];
+		for byte in value {
+			for bit in (0..8).rev() {
+				// The remainder is below the curve order, which is smaller
+				// than 2^255, so shifting it left never overflows.
+				let mut carry = (byte >> bit) & 1;
+				for i in (0..32).rev() {
+					let shifted = (rem[i] << 1) | carry;
+					carry = rem[i] >> 7;
+					rem[i] = shifted;
+				}
+				if rem[..] >= CURVE_ORDER[..] {
+					sub_assign(&mut rem, &CURVE_ORDER);
+				}
+			}
+		}
+		rem
+	}
+
+	/// Convert reduced big-endian scalar bytes into a BLS secret key.
+	pub fn to_secret(value: &[u8; 32]) -> bls::Secret {
+		let mut bytes = [0u8; 48];
+		bytes[16..].copy_from_slice(value);
+		bls::Secret::from_bytes(&bytes)
+			.expect("Value is reduced modulo curve order; qed")
+	}
+}
+
+/// Deterministic validator keys of the eth2 interop scheme.
+pub mod interop {
+	use sha2::{Sha256, Digest};
+	use crate::{bls, scalar};
+
+	/// Big-endian secret key bytes of the validator at `index`. This is
+	/// `sha256(index as 32-byte little-endian)`, read as a little-endian
+	/// integer, modulo the curve order.
+	pub fn secret_bytes(index: u64) -> [u8; 32] {
+		let mut preimage = [0u8; 32];
+		preimage[..8].copy_from_slice(&index.to_le_bytes());
+		let hash = Sha256::digest(&preimage);
+
+		let mut value = [0u8; 32];
+		for i in 0..32 {
+			value[i] = hash[31 - i];
+		}
+		scalar::reduce(&value)
+	}
+
+	/// Secret key of the validator at `index`.
+	pub fn secret(index: u64) -> bls::Secret {
+		scalar::to_secret(&secret_bytes(index))
+	}
+
+	#[cfg(test)]
+	mod tests {
+		use super::*;
+
+		#[test]
+		fn interop_secret_keys() {
+			assert_eq!(
+				&secret_bytes(0)[..],
+				&[
+					0x25, 0x29, 0x5f, 0x0d, 0x1d, 0x59, 0x2a, 0x90,
+					0xb3, 0x33, 0xe2, 0x6e, 0x85, 0x14, 0x97, 0x08,
+					0x20, 0x8e, 0x9f, 0x8e, 0x8b, 0xc1, 0x8f, 0x6c,
+					0x77, 0xbd, 0x62, 0xf8, 0xad, 0x7a, 0x68, 0x66,
+				][..],
+			);
+			assert_eq!(
+				&secret_bytes(1)[..],
+				&[
+					0x51, 0xd0, 0xb6, 0x51, 0x85, 0xdb, 0x69, 0x89,
+					0xab, 0x0b, 0x56, 0x0d, 0x6d, 0xee, 0xd1, 0x9c,
+					0x7e, 0xad, 0x0e, 0x24, 0xb9, 0xb6, 0x37, 0x2c,
+					0xbe, 0xcb, 0x1f, 0x26, 0xbd, 0xfa, 0xd0, 0x00,
+				][..],
+			);
+		}
+	}
+}
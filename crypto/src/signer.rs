@@ -0,0 +1,431 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Parity Shasper.
+
+// Parity Shasper is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+
+// Parity Shasper is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more
+// details.
+
+// You should have received a copy of the GNU General Public License along with
+// Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Signing of validator messages, either with local keys or by a remote
+//! signer over the request format of the Web3Signer eth2 signing API.
+//!
+//! Signing roots and domains are those of the spec version this client
+//! follows, which predates `genesis_validators_root` in domains and still
+//! signs attestations with a custody bit. A remote signer has to compute the
+//! same roots, as the bundled mock does; a current Web3Signer computes
+//! different ones and refuses the requests.
+
+use beacon::{Config, utils};
+use beacon::primitives::{H256, Signature, ValidatorId, Uint};
+use beacon::types::{
+	AggregateAndProof, AttestationData, AttestationDataAndCustodyBit, BeaconBlock, Fork,
+	SigningVoluntaryExit, UnsealedBeaconBlock, VoluntaryExit,
+};
+use bm_le::tree_root;
+use serde::{Serialize, Deserialize};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use crate::bls;
+
+#[derive(Debug)]
+pub enum Error {
+	/// No key of the given public key is available.
+	UnknownKey(ValidatorId),
+	/// HTTP transport error.
+	Http(reqwest::Error),
+	/// The remote signer rejected the request.
+	Rejected(u16, String),
+	/// The remote signer returned an invalid response.
+	InvalidResponse(String),
+}
+
+impl std::fmt::Display for Error {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "{:?}", self)
+	}
+}
+
+impl std::error::Error for Error { }
+
+impl From<reqwest::Error> for Error {
+	fn from(error: reqwest::Error) -> Error {
+		Error::Http(error)
+	}
+}
+
+/// Fork of the chain a message is signed for.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct ForkInfo {
+	pub fork: Fork,
+	pub genesis_validators_root: H256,
+}
+
+/// Signing domain of the given type at `epoch`.
+pub fn fork_domain(fork: &Fork, domain_type: u32, epoch: Uint) -> u64 {
+	let fork_version = if epoch < fork.epoch {
+		fork.previous_version
+	} else {
+		fork.current_version
+	};
+	utils::bls_domain(domain_type, fork_version)
+}
+
+/// A message to be signed by a validator.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum SigningMessage<C: Config> {
+	/// Randao reveal of an epoch.
+	RandaoReveal(Uint),
+	/// Block proposal.
+	Block(BeaconBlock<C>),
+	/// Attestation data.
+	Attestation(AttestationData),
+	/// Voluntary exit.
+	VoluntaryExit(VoluntaryExit),
+	/// Slot, proving whether the validator is an aggregator at it.
+	AggregationSlot(Uint),
+	/// Aggregate and proof of an aggregator.
+	AggregateAndProof(AggregateAndProof<C>),
+}
+
+impl<C: Config> SigningMessage<C> {
+	/// Root of the signed message.
+	pub fn signing_root(&self) -> H256 {
+		match self {
+			Self::RandaoReveal(epoch) => tree_root::<C::Digest, _>(epoch),
+			Self::Block(block) => tree_root::<C::Digest, _>(&UnsealedBeaconBlock::<C>::from(block)),
+			Self::Attestation(data) => tree_root::<C::Digest, _>(&AttestationDataAndCustodyBit {
+				data: data.clone(),
+				custody_bit: false,
+			}),
+			Self::VoluntaryExit(exit) =>
+				tree_root::<C::Digest, _>(&SigningVoluntaryExit::from(exit.clone())),
+			Self::AggregationSlot(slot) => tree_root::<C::Digest, _>(slot),
+			Self::AggregateAndProof(aggregate_and_proof) =>
+				tree_root::<C::Digest, _>(aggregate_and_proof),
+		}
+	}
+
+	/// Signing domain of the message at the given fork.
+	pub fn domain(&self, fork: &Fork) -> u64 {
+		let (domain_type, epoch) = match self {
+			Self::RandaoReveal(epoch) => (C::domain_randao(), *epoch),
+			Self::Block(block) =>
+				(C::domain_beacon_proposer(), utils::epoch_of_slot::<C>(block.slot)),
+			Self::Attestation(data) => (C::domain_beacon_attester(), data.target.epoch),
+			Self::VoluntaryExit(exit) => (C::domain_voluntary_exit(), exit.epoch),
+			Self::AggregationSlot(slot) =>
+				(C::domain_beacon_attester(), utils::epoch_of_slot::<C>(*slot)),
+			Self::AggregateAndProof(aggregate_and_proof) => (
+				C::domain_aggregate_and_proof(),
+				utils::epoch_of_slot::<C>(aggregate_and_proof.aggregate.data.slot),
+			),
+		};
+		fork_domain(fork, domain_type, epoch)
+	}
+
+	/// Type name and body field of the message in a Web3Signer request.
+	fn web3signer_field(&self) -> (&'static str, &'static str, Value) {
+		match self {
+			Self::RandaoReveal(epoch) =>
+				("RANDAO_REVEAL", "randao_reveal", json!({ "epoch": epoch.to_string() })),
+			Self::Block(block) => ("BLOCK", "block", json!(block)),
+			Self::Attestation(data) => ("ATTESTATION", "attestation", json!(data)),
+			Self::VoluntaryExit(exit) => ("VOLUNTARY_EXIT", "voluntary_exit", json!(exit)),
+			Self::AggregationSlot(slot) =>
+				("AGGREGATION_SLOT", "aggregation_slot", json!({ "slot": slot.to_string() })),
+			Self::AggregateAndProof(aggregate_and_proof) =>
+				("AGGREGATE_AND_PROOF", "aggregate_and_proof", json!(aggregate_and_proof)),
+		}
+	}
+
+	/// Parse the message of a Web3Signer request body.
+	fn from_web3signer(body: &Value) -> Result<Self, String> {
+		fn field<T: serde::de::DeserializeOwned>(body: &Value, name: &str) -> Result<T, String> {
+			serde_json::from_value(body[name].clone())
+				.map_err(|e| format!("Invalid field {}: {}", name, e))
+		}
+
+		#[derive(Deserialize)]
+		struct Epoch {
+			#[serde(deserialize_with = "utils::deserialize_uint")]
+			epoch: Uint,
+		}
+
+		#[derive(Deserialize)]
+		struct Slot {
+			#[serde(deserialize_with = "utils::deserialize_uint")]
+			slot: Uint,
+		}
+
+		match body["type"].as_str() {
+			Some("RANDAO_REVEAL") =>
+				Ok(Self::RandaoReveal(field::<Epoch>(body, "randao_reveal")?.epoch)),
+			Some("BLOCK") => Ok(Self::Block(field(body, "block")?)),
+			Some("ATTESTATION") => Ok(Self::Attestation(field(body, "attestation")?)),
+			Some("VOLUNTARY_EXIT") => Ok(Self::VoluntaryExit(field(body, "voluntary_exit")?)),
+			Some("AGGREGATION_SLOT") =>
+				Ok(Self::AggregationSlot(field::<Slot>(body, "aggregation_slot")?.slot)),
+			Some("AGGREGATE_AND_PROOF") =>
+				Ok(Self::AggregateAndProof(field(body, "aggregate_and_proof")?)),
+			typ => Err(format!("Unsupported signing type {:?}", typ)),
+		}
+	}
+}
+
+/// Signer of validator messages.
+pub trait Signer<C: Config> {
+	/// Public keys of all validators the signer holds keys of.
+	fn pubkeys(&self) -> Result<Vec<ValidatorId>, Error>;
+
+	/// Sign `message` with the key of `pubkey`.
+	fn sign(
+		&self,
+		pubkey: &ValidatorId,
+		fork_info: &ForkInfo,
+		message: &SigningMessage<C>,
+	) -> Result<Signature, Error>;
+}
+
+/// Signer with keys held in memory.
+pub struct LocalSigner {
+	keys: HashMap<ValidatorId, bls::Secret>,
+}
+
+impl LocalSigner {
+	pub fn new(keys: HashMap<ValidatorId, bls::Secret>) -> Self {
+		Self { keys }
+	}
+}
+
+impl<C: Config> Signer<C> for LocalSigner {
+	fn pubkeys(&self) -> Result<Vec<ValidatorId>, Error> {
+		Ok(self.keys.keys().cloned().collect())
+	}
+
+	fn sign(
+		&self,
+		pubkey: &ValidatorId,
+		fork_info: &ForkInfo,
+		message: &SigningMessage<C>,
+	) -> Result<Signature, Error> {
+		let secret = self.keys.get(pubkey).ok_or_else(|| Error::UnknownKey(pubkey.clone()))?;
+		let signature = bls::Signature::new(
+			&message.signing_root()[..],
+			message.domain(&fork_info.fork),
+			secret,
+		);
+		Ok(Signature::from_slice(&signature.as_bytes()[..]))
+	}
+}
+
+#[derive(Deserialize)]
+struct SignResponse {
+	signature: Signature,
+}
+
+/// Signer delegating to a remote HTTP server speaking the Web3Signer request
+/// format, with signing roots as computed by this client.
+pub struct RemoteSigner {
+	client: reqwest::Client,
+	endpoint: String,
+}
+
+impl RemoteSigner {
+	pub fn new(endpoint: &str) -> Self {
+		Self {
+			client: reqwest::Client::new(),
+			endpoint: endpoint.trim_end_matches('/').to_string(),
+		}
+	}
+
+	fn handle<T: serde::de::DeserializeOwned>(mut response: reqwest::Response) -> Result<T, Error> {
+		if !response.status().is_success() {
+			let status = response.status().as_u16();
+			return Err(Error::Rejected(status, response.text().unwrap_or_default()))
+		}
+		response.json().map_err(|e| Error::InvalidResponse(format!("{}", e)))
+	}
+}
+
+impl<C: Config> Signer<C> for RemoteSigner {
+	fn pubkeys(&self) -> Result<Vec<ValidatorId>, Error> {
+		let response = self.client.get(&format!("{}/api/v1/eth2/publicKeys", self.endpoint))
+			.send()?;
+		Self::handle(response)
+	}
+
+	fn sign(
+		&self,
+		pubkey: &ValidatorId,
+		fork_info: &ForkInfo,
+		message: &SigningMessage<C>,
+	) -> Result<Signature, Error> {
+		let (typ, name, value) = message.web3signer_field();
+		let mut body = json!({
+			"type": typ,
+			"fork_info": fork_info,
+			"signingRoot": message.signing_root(),
+		});
+		body[name] = value;
+
+		let response = self.client.post(&format!(
+			"{}/api/v1/eth2/sign/0x{}", self.endpoint, hex::encode(&pubkey[..])
+		))
+			.header(reqwest::header::ACCEPT, "application/json")
+			.json(&body)
+			.send()?;
+		Ok(Self::handle::<SignResponse>(response)?.signature)
+	}
+}
+
+/// In-process signer server for tests, serving the Web3Signer eth2 signing
+/// API with local keys.
+pub mod mock {
+	use beacon::Config;
+	use beacon::primitives::{H256, ValidatorId};
+	use serde_json::{Value, json};
+	use tiny_http::{Server, Request, Response, Method, Header};
+	use std::io::Read;
+	use std::marker::PhantomData;
+	use std::thread;
+	use super::{Signer, LocalSigner, ForkInfo, SigningMessage};
+
+	/// A running mock signer server.
+	pub struct MockSigner<C: Config> {
+		endpoint: String,
+		_marker: PhantomData<C>,
+	}
+
+	impl<C: Config> MockSigner<C> {
+		/// Serve the keys of `signer` on a random local port.
+		pub fn start(signer: LocalSigner) -> Result<Self, String> {
+			let server = Server::http("127.0.0.1:0").map_err(|e| format!("{}", e))?;
+			let endpoint = format!("http://{}", server.server_addr());
+
+			thread::spawn(move || {
+				for mut request in server.incoming_requests() {
+					let (status, body) = match handle::<C>(&signer, &mut request) {
+						Ok(body) => (200, body),
+						Err((status, message)) => (status, message),
+					};
+					let response = Response::from_string(body)
+						.with_status_code(status)
+						.with_header(
+							Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+								.expect("Header is valid ASCII; qed")
+						);
+					let _ = request.respond(response);
+				}
+			});
+
+			Ok(Self { endpoint, _marker: PhantomData })
+		}
+
+		/// Base URL of the server.
+		pub fn endpoint(&self) -> &str {
+			&self.endpoint
+		}
+	}
+
+	fn handle<C: Config>(
+		signer: &LocalSigner,
+		request: &mut Request,
+	) -> Result<String, (u16, String)> {
+		let bad_request = |message: String| (400, message);
+		let url = request.url().to_string();
+
+		match (request.method().clone(), url.as_str()) {
+			(Method::Get, "/upcheck") => Ok("OK".to_string()),
+			(Method::Get, "/api/v1/eth2/publicKeys") => {
+				let pubkeys = Signer::<C>::pubkeys(signer).map_err(|e| (500, format!("{}", e)))?;
+				Ok(json!(pubkeys).to_string())
+			},
+			(Method::Post, path) if path.starts_with("/api/v1/eth2/sign/") => {
+				let pubkey = hex::decode(path["/api/v1/eth2/sign/".len()..].trim_start_matches("0x"))
+					.ok()
+					.filter(|pubkey| pubkey.len() == 48)
+					.map(|pubkey| ValidatorId::from_slice(&pubkey))
+					.ok_or_else(|| bad_request(format!("Invalid public key in {}", path)))?;
+
+				let mut body = String::new();
+				request.as_reader().read_to_string(&mut body)
+					.map_err(|e| bad_request(format!("Unable to read body: {}", e)))?;
+				let body: Value = serde_json::from_str(&body)
+					.map_err(|e| bad_request(format!("Invalid JSON body: {}", e)))?;
+				let fork_info: ForkInfo = serde_json::from_value(body["fork_info"].clone())
+					.map_err(|e| bad_request(format!("Invalid fork info: {}", e)))?;
+				let message = SigningMessage::<C>::from_web3signer(&body).map_err(bad_request)?;
+
+				if let Some(signing_root) = body.get("signingRoot") {
+					let signing_root: H256 = serde_json::from_value(signing_root.clone())
+						.map_err(|e| bad_request(format!("Invalid signing root: {}", e)))?;
+					if signing_root != message.signing_root() {
+						return Err(bad_request("Signing root mismatch".to_string()))
+					}
+				}
+
+				let signature = signer.sign(&pubkey, &fork_info, &message)
+					.map_err(|e| (404, format!("{}", e)))?;
+				Ok(json!({ "signature": signature }).to_string())
+			},
+			_ => Err((404, "Not found".to_string())),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use super::mock::MockSigner;
+	use beacon::{BLSConfig, MinimalConfig};
+	use beacon::types::Checkpoint;
+	use crate::interop;
+
+	#[test]
+	fn remote_signer_matches_local_signer() {
+		let pubkey = ValidatorId::from_slice(
+			&bls::Public::from_secret_key(&interop::secret(0)).as_bytes()[..]
+		);
+		let signer = || {
+			let mut keys = HashMap::new();
+			keys.insert(pubkey.clone(), interop::secret(0));
+			LocalSigner::new(keys)
+		};
+
+		let local = signer();
+		let server = MockSigner::<MinimalConfig>::start(signer()).unwrap();
+		let remote = RemoteSigner::new(server.endpoint());
+
+		let fork_info = ForkInfo { fork: Fork::default(), genesis_validators_root: H256::default() };
+		let messages = vec![
+			SigningMessage::<MinimalConfig>::RandaoReveal(3),
+			SigningMessage::Block(BeaconBlock { slot: 17, ..Default::default() }),
+			SigningMessage::Attestation(AttestationData {
+				slot: 17,
+				target: Checkpoint { epoch: 2, root: H256::repeat_byte(1) },
+				..Default::default()
+			}),
+			SigningMessage::AggregationSlot(17),
+		];
+
+		assert_eq!(Signer::<MinimalConfig>::pubkeys(&remote).unwrap(), vec![pubkey.clone()]);
+		for message in messages {
+			let signature = remote.sign(&pubkey, &fork_info, &message).unwrap();
+			assert_eq!(signature, local.sign(&pubkey, &fork_info, &message).unwrap());
+			assert!(bls::BLSVerification::verify(
+				&pubkey, &message.signing_root(), &signature, message.domain(&fork_info.fork),
+			));
+		}
+		assert!(remote.sign(
+			&ValidatorId::default(), &fork_info, &SigningMessage::<MinimalConfig>::RandaoReveal(3),
+		).is_err());
+	}
+}
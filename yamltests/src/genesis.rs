@@ -0,0 +1,145 @@
+// Copyright 2019 Parity Technologies (UK) Ltd.
+// This file is part of Parity Shasper.
+
+// Parity Shasper is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option) any
+// later version.
+
+// Parity Shasper is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+// FOR A PARTICULAR PURPOSE.  See the GNU General Public License for more
+// details.
+
+// You should have received a copy of the GNU General Public License along with
+// Parity Shasper.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::path::PathBuf;
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+use ssz::Encode;
+use beacon::{Config, BeaconState, MinimalConfig, MainnetConfig};
+use beacon::primitives::H256;
+use beacon::types::*;
+use crypto::bls::BLSVerification;
+use crate::{test_name, read_value_unwrap, read_raw_unwrap};
+use crate::description::{TestDescription, TestPhase, GenesisType, TestNetwork};
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Meta {
+	pub deposits_count: usize,
+}
+
+pub fn test(typ: GenesisType, desc: TestDescription) {
+	match desc.network {
+		TestNetwork::Mainnet => test_with_config::<MainnetConfig>(typ, desc),
+		TestNetwork::Minimal => test_with_config::<MinimalConfig>(typ, desc),
+		TestNetwork::General => unimplemented!("Not supported"),
+	}
+}
+
+pub fn test_with_config<C: Config>(typ: GenesisType, desc: TestDescription) where
+	C: Serialize + DeserializeOwned,
+{
+	assert_eq!(desc.phase, TestPhase::Phase0);
+	let path = desc.path.clone().unwrap();
+
+	match typ {
+		GenesisType::Initialization => test_initialization::<C>(path),
+		GenesisType::Validity => test_validity::<C>(path),
+	}
+}
+
+fn read_state_unwrap<C: Config>(path: &PathBuf, name: &str) -> BeaconState<C> where
+	C: DeserializeOwned,
+{
+	let state = {
+		let mut path = path.clone();
+		path.push(&format!("{}.yaml", name));
+
+		read_value_unwrap::<_, BeaconState<C>>(path)
+	};
+
+	let state_ssz = {
+		let mut path = path.clone();
+		path.push(&format!("{}.ssz", name));
+
+		read_raw_unwrap(path)
+	};
+
+	assert_eq!(Encode::encode(&state), state_ssz);
+	state
+}
+
+pub fn test_initialization<C: Config>(path: PathBuf) where
+	C: Serialize + DeserializeOwned,
+{
+	print!("Running test: {} ...", test_name(path.clone()).unwrap());
+
+	let eth1_block_hash = {
+		let mut path = path.clone();
+		path.push("eth1_block_hash.yaml");
+
+		read_value_unwrap::<_, H256>(path)
+	};
+
+	let eth1_timestamp = {
+		let mut path = path.clone();
+		path.push("eth1_timestamp.yaml");
+
+		read_value_unwrap::<_, u64>(path)
+	};
+
+	let meta = {
+		let mut path = path.clone();
+		path.push("meta.yaml");
+
+		read_value_unwrap::<_, Meta>(path)
+	};
+
+	let mut deposits = Vec::new();
+	for i in 0..meta.deposits_count {
+		let deposit = {
+			let mut path = path.clone();
+			path.push(&format!("deposits_{}.yaml", i));
+
+			read_value_unwrap::<_, Deposit>(path)
+		};
+
+		let deposit_ssz = {
+			let mut path = path.clone();
+			path.push(&format!("deposits_{}.ssz", i));
+
+			read_raw_unwrap(path)
+		};
+
+		assert_eq!(Encode::encode(&deposit), deposit_ssz);
+		deposits.push(deposit);
+	}
+
+	let expected = read_state_unwrap::<C>(&path, "state");
+	let state = beacon::initialize_beacon_state_from_eth1::<C, BLSVerification>(
+		eth1_block_hash, eth1_timestamp, &deposits
+	).unwrap();
+	assert_eq!(state, expected);
+
+	println!(" passed");
+}
+
+pub fn test_validity<C: Config>(path: PathBuf) where
+	C: Serialize + DeserializeOwned,
+{
+	print!("Running test: {} ...", test_name(path.clone()).unwrap());
+
+	let genesis = read_state_unwrap::<C>(&path, "genesis");
+	let is_valid = {
+		let mut path = path.clone();
+		path.push("is_valid.yaml");
+
+		read_value_unwrap::<_, bool>(path)
+	};
+
+	assert_eq!(beacon::is_valid_genesis_state(&genesis), is_valid);
+
+	println!(" passed");
+}
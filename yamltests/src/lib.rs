@@ -3,6 +3,7 @@ pub mod ssz_static;
 pub mod operations;
 pub mod sanity;
 pub mod epoch_processing;
+pub mod genesis;
 
 use std::fs::File;
 use std::io::{self, BufReader, Read};
@@ -45,6 +46,7 @@ pub fn test(desc: TestDescription) {
 		TestType::Operations(typ) => operations::test(typ, desc),
 		TestType::Sanity(typ) => sanity::test(typ, desc),
 		TestType::EpochProcessing(typ) => epoch_processing::test(typ, desc),
+		TestType::Genesis(typ) => genesis::test(typ, desc),
 		_ => println!("Skipped {}", test_name(desc.path.unwrap()).unwrap()),
 	}
 }
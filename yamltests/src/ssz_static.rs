@@ -33,7 +33,7 @@ pub fn test_with_config<C: Config>(typ: SszStaticType, desc: TestDescription) wh
 	let path = desc.path.clone().unwrap();
 
 	match typ {
-		SszStaticType::AggregateAndProof => println!("Skipped {}", test_name(path).unwrap()),
+		SszStaticType::AggregateAndProof => test_ssz::<C, AggregateAndProof<C>>(path),
 		SszStaticType::Attestation => test_ssz::<C, Attestation<C>>(path),
 		SszStaticType::AttestationData => test_ssz::<C, AttestationData>(path),
 		SszStaticType::AttestationDataAndCustodyBit => test_ssz::<C, AttestationDataAndCustodyBit>(path),